@@ -0,0 +1,171 @@
+use nalgebra::{Matrix4, Point3, Quaternion, Translation3, UnitQuaternion, Vector3};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::gltf::GlbDocument;
+use crate::leaves::GPU_INSTANCING;
+
+/// An axis-aligned bounding box
+#[derive(Debug, Clone, Copy)]
+struct Aabb {
+    min: Point3<f32>,
+    max: Point3<f32>,
+}
+
+impl Aabb {
+    fn from_point(point: Point3<f32>) -> Self {
+        Self { min: point, max: point }
+    }
+
+    fn extend(&mut self, point: &Point3<f32>) {
+        self.min = self.min.inf(point);
+        self.max = self.max.sup(point);
+    }
+
+    fn to_json(self) -> Value {
+        json!({
+            "min": [self.min.x, self.min.y, self.min.z],
+            "max": [self.max.x, self.max.y, self.max.z],
+        })
+    }
+}
+
+/// Fill in the bounds of the document: the min and max of every position accessor, the box
+/// around each node and its descendants in `extras.bounds` of the node (in the node's frame), and
+/// the box and bounding sphere of the whole scene in the scene's `extras`
+///
+/// Leaf copies placed with `EXT_mesh_gpu_instancing` are included. Nodes without any geometry
+/// below them get no bounds.
+pub fn add_bounds(document: &mut GlbDocument) {
+    // Positions of every mesh, whose accessors get their exact min and max
+    let mut mesh_positions: HashMap<usize, Vec<Point3<f32>>> = HashMap::new();
+    let mesh_count = document.json["meshes"].as_array().map_or(0, |meshes| meshes.len());
+    for mesh in 0..mesh_count {
+        let primitives = document.json["meshes"][mesh]["primitives"].as_array().cloned().unwrap_or_default();
+        let mut positions = Vec::new();
+        for primitive in &primitives {
+            let Some(accessor) = primitive["attributes"]["POSITION"].as_u64() else { continue };
+            let Some((values, 3)) = document.read_float_accessor(accessor as usize) else { continue };
+            let points: Vec<Point3<f32>> = values.chunks(3).map(|p| Point3::new(p[0], p[1], p[2])).collect();
+            if let Some(aabb) = points_box(&points) {
+                let bounds = aabb.to_json();
+                document.json["accessors"][accessor as usize]["min"] = bounds["min"].clone();
+                document.json["accessors"][accessor as usize]["max"] = bounds["max"].clone();
+            }
+            positions.extend(points);
+        }
+        mesh_positions.insert(mesh, positions);
+    }
+
+    let roots: Vec<usize> = document.json["scenes"][0]["nodes"]
+        .as_array()
+        .map(|nodes| nodes.iter().filter_map(|node| node.as_u64()).map(|node| node as usize).collect())
+        .unwrap_or_default();
+
+    // The box of every node in its own frame, and the scene's points
+    let mut scene_points = Vec::new();
+    for &root in &roots {
+        let root_matrix = node_matrix(&document.json["nodes"][root]);
+        scene_points.extend(node_points(document, root, &mesh_positions).iter().map(|point| root_matrix.transform_point(point)));
+    }
+
+    let Some(scene_box) = points_box(&scene_points) else { return };
+    let center = nalgebra::center(&scene_box.min, &scene_box.max);
+    let radius = scene_points.iter().map(|point| (point - center).norm()).fold(0.0, f32::max);
+    let mut extras = scene_box.to_json();
+    extras["boundingSphere"] = json!({
+        "center": [center.x, center.y, center.z],
+        "radius": radius,
+    });
+    document.json["scenes"][0]["extras"]["bounds"] = extras;
+}
+
+/// Points of the geometry of a node and its descendants in the node's frame, writing their box
+/// into the node's extras
+fn node_points(document: &mut GlbDocument, node: usize, mesh_positions: &HashMap<usize, Vec<Point3<f32>>>) -> Vec<Point3<f32>> {
+    let mut points = Vec::new();
+    let mesh = document.json["nodes"][node]["mesh"].as_u64().map(|mesh| mesh as usize);
+    if let Some(positions) = mesh.and_then(|mesh| mesh_positions.get(&mesh)) {
+        for instance in instance_matrices(document, node) {
+            points.extend(positions.iter().map(|position| instance.transform_point(position)));
+        }
+    }
+
+    let children: Vec<usize> = document.json["nodes"][node]["children"]
+        .as_array()
+        .map(|children| children.iter().filter_map(|child| child.as_u64()).map(|child| child as usize).collect())
+        .unwrap_or_default();
+    for child in children {
+        let child_matrix = node_matrix(&document.json["nodes"][child]);
+        points.extend(node_points(document, child, mesh_positions).iter().map(|point| child_matrix.transform_point(point)));
+    }
+
+    if let Some(aabb) = points_box(&points) {
+        document.json["nodes"][node]["extras"]["bounds"] = aabb.to_json();
+    }
+    points
+}
+
+/// Box around points, none without points
+fn points_box(points: &[Point3<f32>]) -> Option<Aabb> {
+    let mut aabb = Aabb::from_point(*points.first()?);
+    points.iter().for_each(|point| aabb.extend(point));
+    Some(aabb)
+}
+
+/// Transforms of the copies of a node's mesh, a single identity without instancing
+fn instance_matrices(document: &GlbDocument, node: usize) -> Vec<Matrix4<f32>> {
+    let attributes = &document.json["nodes"][node]["extensions"][GPU_INSTANCING]["attributes"];
+    if !attributes.is_object() {
+        return vec![Matrix4::identity()];
+    }
+
+    let read = |name: &str, components: usize| {
+        attributes[name]
+            .as_u64()
+            .and_then(|accessor| document.read_float_accessor(accessor as usize))
+            .filter(|(_, read_components)| *read_components == components)
+            .map(|(values, _)| values)
+    };
+    let translations = read("TRANSLATION", 3);
+    let rotations = read("ROTATION", 4);
+    let scales = read("SCALE", 3);
+    let count = [translations.as_ref().map(|values| values.len() / 3), rotations.as_ref().map(|values| values.len() / 4), scales.as_ref().map(|values| values.len() / 3)]
+        .into_iter()
+        .flatten()
+        .min()
+        .unwrap_or(0);
+
+    (0..count)
+        .map(|i| {
+            let translation = translations.as_ref().map_or([0.0; 3], |values| [values[i * 3], values[i * 3 + 1], values[i * 3 + 2]]);
+            let rotation = rotations.as_ref().map_or([0.0, 0.0, 0.0, 1.0], |values| [values[i * 4], values[i * 4 + 1], values[i * 4 + 2], values[i * 4 + 3]]);
+            let scale = scales.as_ref().map_or([1.0; 3], |values| [values[i * 3], values[i * 3 + 1], values[i * 3 + 2]]);
+            trs_matrix(translation, rotation, scale)
+        })
+        .collect()
+}
+
+/// Local transform of a node, from its matrix or its translation, rotation and scale
+pub fn node_matrix(node: &Value) -> Matrix4<f32> {
+    let floats = |value: &Value| -> Option<Vec<f32>> {
+        value.as_array()?.iter().map(|value| value.as_f64().map(|value| value as f32)).collect()
+    };
+    if let Some(matrix) = floats(&node["matrix"]).filter(|matrix| matrix.len() == 16) {
+        return Matrix4::from_column_slice(&matrix);
+    }
+
+    let translation = floats(&node["translation"]).filter(|values| values.len() == 3).map_or([0.0; 3], |values| [values[0], values[1], values[2]]);
+    let rotation = floats(&node["rotation"]).filter(|values| values.len() == 4).map_or([0.0, 0.0, 0.0, 1.0], |values| [values[0], values[1], values[2], values[3]]);
+    let scale = floats(&node["scale"]).filter(|values| values.len() == 3).map_or([1.0; 3], |values| [values[0], values[1], values[2]]);
+    trs_matrix(translation, rotation, scale)
+}
+
+/// Matrix of a translation, rotation (x, y, z, w) and scale, applied in glTF's order
+fn trs_matrix(translation: [f32; 3], rotation: [f32; 4], scale: [f32; 3]) -> Matrix4<f32> {
+    let [x, y, z, w] = rotation;
+    let rotation = UnitQuaternion::from_quaternion(Quaternion::new(w, x, y, z));
+    Translation3::from(Vector3::from(translation)).to_homogeneous()
+        * rotation.to_homogeneous()
+        * Matrix4::new_nonuniform_scaling(&Vector3::from(scale))
+}
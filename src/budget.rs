@@ -0,0 +1,319 @@
+use crate::tree::BranchKind;
+use crate::{Billboard, BranchConfig, Param, TreeConfig};
+
+/// Fewest segments around a branch the mesh is built with
+const MIN_RADIAL_SEGMENTS: f32 = 3.0;
+/// Fewest rings along a branch
+const MIN_LENGTH_SEGMENTS: f32 = 2.0;
+/// Share of the length segments kept by each reduction step
+const LENGTH_REDUCTION: f32 = 0.8;
+
+/// A branch config in the hierarchy, by the child groups leading to it from the trunk or roots
+#[derive(Debug, Clone)]
+struct ConfigPath {
+    kind: BranchKind,
+    groups: Vec<usize>,
+}
+
+impl ConfigPath {
+    fn level(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Name used in the report, with the group path when a level has several groups
+    fn label(&self, ambiguous: bool) -> String {
+        let mut label = format!("{} level {}", self.kind.name(), self.level());
+        if ambiguous {
+            let groups: Vec<String> = self.groups.iter().map(|group| group.to_string()).collect();
+            label.push_str(&format!(" (group {})", groups.join(".")));
+        }
+        label
+    }
+
+    fn config<'a>(&self, config: &'a TreeConfig) -> Option<&'a BranchConfig> {
+        let mut branch = match self.kind {
+            BranchKind::Branch => &config.trunk,
+            BranchKind::Root => &config.roots.as_ref()?.config,
+        };
+        for &group in &self.groups {
+            branch = &branch.children.get(group)?.config;
+        }
+        Some(branch)
+    }
+
+    fn config_mut<'a>(&self, config: &'a mut TreeConfig) -> Option<&'a mut BranchConfig> {
+        let mut branch = match self.kind {
+            BranchKind::Branch => &mut config.trunk,
+            BranchKind::Root => &mut config.roots.as_mut()?.config,
+        };
+        for &group in &self.groups {
+            branch = &mut branch.children.get_mut(group)?.config;
+        }
+        Some(branch)
+    }
+}
+
+/// Predict the number of triangles of the tree grown from a config, leaves included
+///
+/// Every branch is a tube of `radial × rings` quads closed by a cap or a tip at each end, which
+/// makes `2 × radial × rings` triangles. Parameters with variance count with their mean value, and
+/// with adaptive segments the count is an upper bound as straight runs lose rings.
+pub fn predict_triangles(config: &TreeConfig) -> u64 {
+    let mut triangles = branch_triangles(&config.trunk);
+    if let Some(roots) = &config.roots {
+        triangles += expected_count(&roots.count) * branch_triangles(&roots.config);
+    }
+    (triangles + leaf_triangles(config)).round() as u64
+}
+
+/// Fit a config into a triangle budget by lowering the segment counts, the smallest branches first
+///
+/// Each branch level loses segments until the predicted count fits or it reaches 3 segments
+/// around and 2 rings, then the next larger level is reduced. With `reduce_leaves`, fewer leaves
+/// are placed once the branches can't be reduced any further. The changes are printed.
+pub fn fit_triangle_budget(config: &TreeConfig, max_triangles: u64, reduce_leaves: bool) -> TreeConfig {
+    let mut fitted = config.clone();
+    let predicted = predict_triangles(config);
+    if predicted <= max_triangles {
+        println!("Predicted {} triangles, within the budget of {}", predicted, max_triangles);
+        return fitted;
+    }
+
+    // Deepest levels first, and the roots before the branches as they are mostly hidden
+    let mut paths = Vec::new();
+    collect_paths(&config.trunk, BranchKind::Branch, Vec::new(), &mut paths);
+    if let Some(roots) = &config.roots {
+        collect_paths(&roots.config, BranchKind::Root, Vec::new(), &mut paths);
+    }
+    paths.sort_by_key(|path| (std::cmp::Reverse(path.level()), path.kind == BranchKind::Branch));
+
+    for path in &paths {
+        while predict_triangles(&fitted) > max_triangles {
+            let reduced = path.config_mut(&mut fitted).is_some_and(reduce_segments);
+            if !reduced {
+                break;
+            }
+        }
+    }
+
+    if reduce_leaves && predict_triangles(&fitted) > max_triangles {
+        let other = predict_triangles(&fitted) - leaf_triangles(&fitted) as u64;
+        if let Some(leaves) = fitted.leaves.as_mut() {
+            let per_leaf = leaf_prototype_triangles(leaves.billboard) as u64;
+            leaves.count = (max_triangles.saturating_sub(other) / per_leaf) as u32;
+        }
+    }
+
+    // Report what changed
+    println!("Predicted {} triangles, over the budget of {}:", predicted, max_triangles);
+    for path in &paths {
+        let (Some(before), Some(after)) = (path.config(config), path.config(&fitted)) else { continue };
+        let ambiguous = paths.iter().filter(|other| other.kind == path.kind && other.level() == path.level()).count() > 1;
+        let mut changes = Vec::new();
+        if before.radial_segments != after.radial_segments {
+            changes.push(format!("radial segments {} -> {}", before.radial_segments.value, after.radial_segments.value));
+        }
+        if before.length_segments != after.length_segments {
+            changes.push(format!("length segments {} -> {}", before.length_segments.value, after.length_segments.value));
+        }
+        if !changes.is_empty() {
+            println!("  {}: {}", path.label(ambiguous), changes.join(", "));
+        }
+    }
+    if let (Some(before), Some(after)) = (&config.leaves, &fitted.leaves) {
+        if before.count != after.count {
+            println!("  Leaves: count {} -> {}", before.count, after.count);
+        }
+    }
+
+    let fitted_triangles = predict_triangles(&fitted);
+    if fitted_triangles > max_triangles {
+        println!("The budget can't be met, the reduced tree still has {} predicted triangles", fitted_triangles);
+    } else {
+        println!("Now predicted at {} triangles", fitted_triangles);
+    }
+    fitted
+}
+
+/// List every branch config of a hierarchy
+fn collect_paths(config: &BranchConfig, kind: BranchKind, groups: Vec<usize>, paths: &mut Vec<ConfigPath>) {
+    for (index, group) in config.children.iter().enumerate() {
+        let mut child_groups = groups.clone();
+        child_groups.push(index);
+        collect_paths(&group.config, kind, child_groups, paths);
+    }
+    paths.push(ConfigPath { kind, groups });
+}
+
+/// Take one step off the segments of a branch config, false once both are at their minimum
+fn reduce_segments(config: &mut BranchConfig) -> bool {
+    let radial = config.radial_segments.value.round();
+    let length = config.length_segments.value.round();
+    if radial <= MIN_RADIAL_SEGMENTS && length <= MIN_LENGTH_SEGMENTS {
+        return false;
+    }
+
+    if radial > MIN_RADIAL_SEGMENTS {
+        config.radial_segments = reduced(config.radial_segments, radial - 1.0, MIN_RADIAL_SEGMENTS);
+    }
+    if length > MIN_LENGTH_SEGMENTS {
+        let target = (length * LENGTH_REDUCTION).floor().min(length - 1.0);
+        config.length_segments = reduced(config.length_segments, target, MIN_LENGTH_SEGMENTS);
+    }
+    true
+}
+
+/// A parameter lowered to a value, with its variance narrowed so samples stay above the minimum
+fn reduced(param: Param, value: f32, min: f32) -> Param {
+    let value = value.max(min);
+    Param {
+        value,
+        variance: param.variance.abs().min(value - min),
+    }
+}
+
+/// Expected triangles of a branch grown from a config, with its forks, leader and children
+fn branch_triangles(config: &BranchConfig) -> f32 {
+    let radial = config.radial_segments.value.round().max(MIN_RADIAL_SEGMENTS);
+    let length_segments = config.length_segments.value.round().max(0.0);
+    let tube = |rings: f32| 2.0 * radial * rings.max(MIN_LENGTH_SEGMENTS);
+
+    // A fork ends the branch early and its sub-axes carry the rest, sharing the children and leader
+    let splits = expected_count(&config.splits);
+    if splits >= 2.0 {
+        let fork_t = (config.split_pos_pct.value / 100.0).clamp(0.05, 0.95);
+        let axis = (length_segments * fork_t).round().max(MIN_LENGTH_SEGMENTS);
+        let sub_axis = (length_segments - axis).max(MIN_LENGTH_SEGMENTS);
+        return tube(axis) + splits * tube(sub_axis) + children_triangles(config, radial, sub_axis);
+    }
+
+    tube(length_segments) + children_triangles(config, radial, length_segments)
+}
+
+/// Expected triangles of the children and leader of a branch, or of all the sub-axes of a fork
+fn children_triangles(config: &BranchConfig, radial: f32, length_segments: f32) -> f32 {
+    let mut triangles = 0.0;
+    for group in &config.children {
+        triangles += expected_count(&group.count) * branch_triangles(&group.config);
+    }
+    if let Some(leader) = &config.leader {
+        let rings = (length_segments * leader.length_ratio.value).round().max(MIN_LENGTH_SEGMENTS);
+        triangles += 2.0 * radial * rings;
+    }
+    triangles
+}
+
+/// Triangles of all the leaf copies
+fn leaf_triangles(config: &TreeConfig) -> f32 {
+    config.leaves.as_ref().map_or(0.0, |leaves| (leaves.count * leaf_prototype_triangles(leaves.billboard)) as f32)
+}
+
+/// Triangles of the prototype leaf
+fn leaf_prototype_triangles(billboard: Billboard) -> u32 {
+    match billboard {
+        Billboard::Single => 2,
+        Billboard::Double => 4,
+    }
+}
+
+/// Expected value of a count sampled from a parameter
+fn expected_count(param: &Param) -> f32 {
+    if param.variance.abs() < f32::EPSILON {
+        param.value.round().max(0.0)
+    } else {
+        param.value.max(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::trunk_tree_config;
+    use crate::gltf::TempGlb;
+    use crate::stats::tree_stats;
+    use crate::tree::generate_tree_with_format;
+    use crate::{LeafShape, LeavesConfig};
+    use serde_json::json;
+
+    /// A trunk forking into two sub-axes with a leader, carrying two levels of children
+    fn config() -> TreeConfig {
+        let twig = json!({
+            "length": 0.5, "startRadius": 0.02, "endRadius": 0.01, "lengthSegments": 3,
+            "radialSegments": 5, "angle": 40.0, "twist": 0.0, "gnarliness": 0.0, "children": 0
+        });
+        let branch = json!({
+            "length": 2.0, "startRadius": 0.1, "endRadius": 0.05, "lengthSegments": 6,
+            "radialSegments": 8, "angle": 40.0, "twist": 0.0, "gnarliness": 0.0,
+            "children": 3, "children_config": twig
+        });
+        trunk_tree_config(json!({
+            "length": 6.0, "startRadius": 0.3, "endRadius": 0.1, "lengthSegments": 10,
+            "radialSegments": 12, "angle": 0.0, "twist": 0.0, "gnarliness": 0.0,
+            "splits": 2, "leader": {"lengthRatio": 0.5},
+            "children": 4, "children_config": branch
+        }))
+    }
+
+    fn leaves(count: u32) -> LeavesConfig {
+        LeavesConfig {
+            shape: LeafShape::Broad,
+            billboard: Billboard::Double,
+            angle: 30.0,
+            count,
+            start: 0.5,
+            size: 0.1,
+            size_variance: 0.0,
+            color: [0.0, 1.0, 0.0, 1.0],
+        }
+    }
+
+    /// Segment counts of the trunk, the branches and the twigs
+    fn segments(config: &TreeConfig) -> Vec<(f32, f32)> {
+        let branch = &config.trunk.children[0].config;
+        [&config.trunk, branch, &branch.children[0].config]
+            .iter()
+            .map(|config| (config.radial_segments.value, config.length_segments.value))
+            .collect()
+    }
+
+    #[test]
+    fn prediction_matches_the_generated_tree() {
+        let mut config = config();
+        config.leaves = Some(leaves(50));
+        let output = TempGlb::in_temp_dir();
+        let exported = generate_tree_with_format(&config, Some(7), Some(output.path()), None).unwrap();
+        assert_eq!(predict_triangles(&config), tree_stats(&exported, &config).triangles as u64);
+    }
+
+    #[test]
+    fn fitting_reduces_the_deepest_level_first() {
+        let config = config();
+        let full = segments(&config);
+
+        // A small cut is taken from the twigs alone
+        let fitted = fit_triangle_budget(&config, predict_triangles(&config) - 100, false);
+        let reduced = segments(&fitted);
+        assert_eq!(reduced[..2], full[..2]);
+        assert!(reduced[2].0 < full[2].0);
+
+        // Nothing fits a single triangle, every level stops at its fewest segments
+        let fitted = fit_triangle_budget(&config, 1, false);
+        assert!(segments(&fitted).iter().all(|&segments| segments == (MIN_RADIAL_SEGMENTS, MIN_LENGTH_SEGMENTS)));
+        assert!(!reduce_segments(&mut fitted.trunk.clone()));
+    }
+
+    #[test]
+    fn fewer_leaves_fill_the_rest_of_the_budget() {
+        let mut config = config();
+        config.leaves = Some(leaves(10_000));
+        let max_triangles = 5_000;
+
+        let fitted = fit_triangle_budget(&config, max_triangles, false);
+        assert_eq!(fitted.leaves.as_ref().map(|leaves| leaves.count), Some(10_000));
+        assert!(predict_triangles(&fitted) > max_triangles);
+
+        let fitted = fit_triangle_budget(&config, max_triangles, true);
+        assert!(fitted.leaves.as_ref().is_some_and(|leaves| leaves.count > 0 && leaves.count < 10_000));
+        assert!(predict_triangles(&fitted) <= max_triangles);
+    }
+}
@@ -0,0 +1,224 @@
+use nalgebra::{Point3, UnitQuaternion, Vector3};
+use serde::Serialize;
+use serde_json::json;
+use std::error::Error;
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use crate::gltf::GlbDocument;
+use crate::tree::{BranchKind, Tree};
+use crate::{ColliderShape, CollisionConfig};
+
+/// glTF extension attaching colliders to nodes
+const RIGID_BODIES_EXTENSION: &str = "KHR_physics_rigid_bodies";
+/// glTF extension holding the collider shapes
+const IMPLICIT_SHAPES_EXTENSION: &str = "KHR_implicit_shapes";
+
+/// A tapered capsule or cylinder around a group of branch segments, in the tree's frame
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Collider {
+    /// Branch the collider covers
+    pub branch: usize,
+    /// Center of the base of the collider
+    pub start: [f32; 3],
+    /// Center of the top of the collider
+    pub end: [f32; 3],
+    pub start_radius: f32,
+    pub end_radius: f32,
+}
+
+/// Colliders written to the standalone JSON file
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CollidersFile<'a> {
+    /// "capsule" or "cylinder"
+    shape: &'static str,
+    colliders: &'a [Collider],
+}
+
+impl ColliderShape {
+    /// Name of the shape in glTF and JSON
+    fn name(&self) -> &'static str {
+        match self {
+            ColliderShape::Capsule => "capsule",
+            ColliderShape::Cylinder => "cylinder",
+        }
+    }
+}
+
+/// Derive a compound collider from the branch skeleton
+///
+/// Each branch is cut into groups of `segments` segments, and every group at least `min_radius`
+/// thick gets one collider from its first to its last ring. Roots are underground and get none.
+pub fn build_colliders(tree: &Tree, collision: &CollisionConfig) -> Vec<Collider> {
+    let segments = collision.segments.max(1) as usize;
+    let mut colliders = Vec::new();
+    for (index, branch) in tree.branches.iter().enumerate() {
+        if branch.kind != BranchKind::Branch || branch.transforms.len() < 2 {
+            continue;
+        }
+
+        let world = tree.world_transform(index);
+        let points: Vec<Point3<f32>> = branch.transforms.iter().map(|transform| world * transform.point()).collect();
+        let mut first = 0;
+        while first + 1 < points.len() {
+            let last = (first + segments).min(points.len() - 1);
+            let radius = |i: usize| branch.radii.get(i).copied().unwrap_or(0.0);
+            let thickest = (first..=last).map(radius).fold(0.0, f32::max);
+            if thickest < collision.min_radius {
+                first = last;
+                continue;
+            }
+
+            colliders.push(Collider {
+                branch: index,
+                start: points[first].into(),
+                end: points[last].into(),
+                start_radius: radius(first),
+                end_radius: radius(last),
+            });
+            first = last;
+        }
+    }
+    colliders
+}
+
+/// Path of the standalone colliders file: `tree.glb` becomes `tree.colliders.json`
+pub fn colliders_path(output: &Path) -> PathBuf {
+    output.with_extension("colliders.json")
+}
+
+/// Write the colliders as standalone JSON
+pub fn write_colliders_json(colliders: &[Collider], collision: &CollisionConfig, path: &Path) -> Result<(), Box<dyn Error>> {
+    let file = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(file, &CollidersFile {
+        shape: collision.shape.name(),
+        colliders,
+    })?;
+    Ok(())
+}
+
+/// Add the colliders under a node as `KHR_physics_rigid_bodies` colliders with
+/// `KHR_implicit_shapes` shapes
+///
+/// Each collider is a child node whose +Y axis runs along the collider, the shapes' axis. The
+/// colliders have no rigid body motion, so they're static.
+pub fn add_colliders(document: &mut GlbDocument, parent: usize, colliders: &[Collider], collision: &CollisionConfig) {
+    if colliders.is_empty() {
+        return;
+    }
+
+    let group = document.push("nodes", json!({ "name": "Colliders" }));
+    document.add_child(parent, group);
+    for (index, collider) in colliders.iter().enumerate() {
+        let start = Point3::from(collider.start);
+        let end = Point3::from(collider.end);
+        let axis = end - start;
+        let rotation = UnitQuaternion::rotation_between(&Vector3::y(), &axis)
+            .unwrap_or_else(|| UnitQuaternion::from_axis_angle(&Vector3::x_axis(), PI))
+            .into_inner();
+        let center = nalgebra::center(&start, &end);
+
+        // Capsule heights run between the centers of the end spheres, cylinders between their caps
+        let name = collision.shape.name();
+        let shape = document.json["extensions"][IMPLICIT_SHAPES_EXTENSION]["shapes"]
+            .as_array()
+            .map_or(0, |shapes| shapes.len());
+        let shapes = &mut document.json["extensions"][IMPLICIT_SHAPES_EXTENSION]["shapes"];
+        if !shapes.is_array() {
+            *shapes = json!([]);
+        }
+        if let Some(shapes) = shapes.as_array_mut() {
+            shapes.push(json!({
+                "type": name,
+                name: {
+                    "height": axis.norm(),
+                    "radiusBottom": collider.start_radius,
+                    "radiusTop": collider.end_radius,
+                }
+            }));
+        }
+
+        let node = document.push("nodes", json!({
+            "name": format!("Collider_{}", index),
+            "translation": [center.x, center.y, center.z],
+            "rotation": [rotation.i, rotation.j, rotation.k, rotation.w],
+            "extensions": {
+                RIGID_BODIES_EXTENSION: {
+                    "collider": { "geometry": { "shape": shape } }
+                }
+            }
+        }));
+        document.add_child(group, node);
+    }
+
+    document.use_extension(RIGID_BODIES_EXTENSION);
+    document.use_extension(IMPLICIT_SHAPES_EXTENSION);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::Branch;
+
+    fn collision(min_radius: f32, segments: u32) -> CollisionConfig {
+        CollisionConfig { shape: ColliderShape::Capsule, min_radius, segments }
+    }
+
+    #[test]
+    fn groups_of_segments_cover_the_branch() {
+        let tree = Tree { branches: vec![Branch::straight(0, None, None, 6)], ..Default::default() };
+        let colliders = build_colliders(&tree, &collision(0.0, 2));
+        let ends: Vec<([f32; 3], [f32; 3])> = colliders.iter().map(|collider| (collider.start, collider.end)).collect();
+        assert_eq!(ends, vec![
+            ([0.0, 0.0, 0.0], [0.0, 0.0, 2.0]),
+            ([0.0, 0.0, 2.0], [0.0, 0.0, 4.0]),
+            ([0.0, 0.0, 4.0], [0.0, 0.0, 5.0]),
+        ]);
+    }
+
+    #[test]
+    fn thin_groups_are_skipped() {
+        // A thin stretch in the middle of the branch doesn't drop the thick part after it
+        let branch = Branch { radii: vec![0.3, 0.3, 0.05, 0.05, 0.3], ..Branch::straight(0, None, None, 5) };
+        let mut root = Branch::straight(0, None, None, 5);
+        root.kind = BranchKind::Root;
+        let tree = Tree { branches: vec![branch, root], ..Default::default() };
+
+        let colliders = build_colliders(&tree, &collision(0.1, 1));
+        let ends: Vec<(f32, f32)> = colliders.iter().map(|collider| (collider.start[2], collider.end[2])).collect();
+        assert_eq!(ends, vec![(0.0, 1.0), (1.0, 2.0), (3.0, 4.0)]);
+        assert!(colliders.iter().all(|collider| collider.branch == 0), "roots get no collider");
+    }
+
+    #[test]
+    fn colliders_are_static_shapes_along_their_axis() {
+        let tree = Tree { branches: vec![Branch::straight(0, None, None, 3)], ..Default::default() };
+        let collision = collision(0.0, 2);
+        let colliders = build_colliders(&tree, &collision);
+        let mut document = GlbDocument { json: json!({}), bin: Vec::new() };
+        let root = document.push("nodes", json!({ "name": "Tree" }));
+        add_colliders(&mut document, root, &colliders, &collision);
+
+        let json = &document.json;
+        let group = json["nodes"][root]["children"][0].as_u64().unwrap() as usize;
+        assert_eq!(json["nodes"][group]["name"], "Colliders");
+        let node = &json["nodes"][json["nodes"][group]["children"][0].as_u64().unwrap() as usize];
+        assert_eq!(node["extensions"][RIGID_BODIES_EXTENSION]["collider"]["geometry"]["shape"], 0);
+        assert_eq!(node["translation"], json!([0.0, 0.0, 1.0]));
+
+        // The +Y axis of the shape is turned onto the branch along +Z
+        let rotation = node["rotation"].as_array().unwrap().iter().map(|value| value.as_f64().unwrap() as f32).collect::<Vec<_>>();
+        let rotation = UnitQuaternion::from_quaternion(nalgebra::Quaternion::new(rotation[3], rotation[0], rotation[1], rotation[2]));
+        assert!((rotation * Vector3::y() - Vector3::z()).norm() < 1.0e-5);
+
+        let shape = &json["extensions"][IMPLICIT_SHAPES_EXTENSION]["shapes"][0];
+        assert_eq!(shape["type"], "capsule");
+        assert_eq!(shape["capsule"]["height"], 2.0);
+        let used = json["extensionsUsed"].as_array().unwrap();
+        assert!(used.contains(&json!(RIGID_BODIES_EXTENSION)) && used.contains(&json!(IMPLICIT_SHAPES_EXTENSION)));
+    }
+}
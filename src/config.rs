@@ -4,7 +4,8 @@ use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
 
-use crate::BranchConfig;
+use crate::{AdaptiveSegmentsConfig, Billboard, BranchConfig, ChildGroup, ColliderShape, CollisionConfig, ExportConfig, Force, Handedness, ImpostorConfig, ImpostorKind, LeaderConfig, LeafShape, LeavesConfig, Param, Pivot, GrowthConfig, RootsConfig, SagConfig, SkinConfig, TreeConfig, Units, UpAxis, WeldConfig, WindConfig, WindMode};
+use crate::tree::LEAVES_COLOR;
 
 /// JSON configuration for tree generation
 #[derive(Debug, Serialize, Deserialize)]
@@ -19,6 +20,308 @@ pub struct JsonTreeConfig {
     pub bark: BarkConfig,
     /// Trunk configuration (root branch)
     pub trunk: JsonBranchConfig,
+    /// Roots grown below the trunk
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub roots: Option<JsonRootsConfig>,
+    /// Wind animation
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub wind: Option<JsonWindConfig>,
+    /// Skinned skeleton export
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub skin: Option<JsonSkinConfig>,
+    /// Growth export
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub growth: Option<JsonGrowthConfig>,
+    /// Welded solid for PLY and STL output
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub weld: Option<JsonWeldConfig>,
+    /// Leaves configuration
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub leaves: Option<JsonLeavesConfig>,
+    /// Impostor for the far level of detail
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub impostor: Option<JsonImpostorConfig>,
+    /// Collision proxies
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub collision: Option<JsonCollisionConfig>,
+    /// Adaptive length segmentation
+    #[serde(rename = "adaptiveSegments", skip_serializing_if = "Option::is_none", default)]
+    pub adaptive_segments: Option<JsonAdaptiveSegmentsConfig>,
+    /// Placement of the exported tree
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub export: Option<JsonExportConfig>,
+    /// Branches bending under their weight
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub sag: Option<JsonSagConfig>,
+}
+
+/// Gravity sag configuration
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JsonSagConfig {
+    /// Young's modulus of the wood in GPa
+    #[serde(default = "default_sag_stiffness")]
+    pub stiffness: f32,
+    /// Density of the wood in kg/m³
+    #[serde(default = "default_sag_density")]
+    pub density: f32,
+}
+
+/// Export placement configuration
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JsonExportConfig {
+    /// Point put at the origin: "origin" or "trunkBase"
+    #[serde(default)]
+    pub pivot: JsonPivot,
+    /// Up axis: "z" or "y"
+    #[serde(rename = "upAxis", skip_serializing_if = "Option::is_none", default)]
+    pub up_axis: Option<JsonUpAxis>,
+    /// Shorthand for an up axis of "y"
+    #[serde(rename = "yUp", default)]
+    pub y_up: bool,
+    /// "right" or "left"
+    #[serde(default)]
+    pub handedness: JsonHandedness,
+    /// "meters", "centimeters" or "inches"
+    #[serde(default)]
+    pub units: JsonUnits,
+}
+
+/// Up axis of the exported tree
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum JsonUpAxis {
+    Z,
+    Y,
+}
+
+/// Handedness of the exported tree
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum JsonHandedness {
+    #[default]
+    Right,
+    Left,
+}
+
+/// Length unit of the exported tree
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum JsonUnits {
+    #[default]
+    Meters,
+    Centimeters,
+    Inches,
+}
+
+/// Pivot of the exported tree
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum JsonPivot {
+    /// Origin of the tree's frame
+    #[default]
+    Origin,
+    /// Base of the trunk
+    TrunkBase,
+}
+
+/// Adaptive length segmentation configuration
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JsonAdaptiveSegmentsConfig {
+    /// Largest bend between two meshed rings in degrees
+    #[serde(default = "default_adaptive_angle")]
+    pub angle: f32,
+}
+
+/// Collision proxy configuration
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonCollisionConfig {
+    /// Shape of the colliders: "capsule" or "cylinder"
+    #[serde(default)]
+    pub shape: JsonColliderShape,
+    /// Thinnest branch part that gets a collider
+    #[serde(rename = "minRadius", default = "default_collision_min_radius")]
+    pub min_radius: f32,
+    /// Number of branch segments per collider
+    #[serde(default = "default_collision_segments")]
+    pub segments: u32,
+}
+
+/// Collider shape
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JsonColliderShape {
+    /// Tapered capsules
+    #[default]
+    Capsule,
+    /// Tapered cylinders
+    Cylinder,
+}
+
+/// Impostor configuration
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JsonImpostorConfig {
+    /// Layout of the views: "crossed" or "octahedral"
+    #[serde(rename = "type", default)]
+    pub kind: JsonImpostorKind,
+    /// Number of azimuths, or frames per side of an octahedral atlas
+    #[serde(default = "default_impostor_views")]
+    pub views: u32,
+    /// Number of rows of views from the horizon up
+    #[serde(default = "default_impostor_elevations")]
+    pub elevations: u32,
+    /// Size of each view in pixels
+    #[serde(default = "default_impostor_resolution")]
+    pub resolution: u32,
+}
+
+/// Impostor layout
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JsonImpostorKind {
+    /// Quads facing views around the tree
+    #[default]
+    Crossed,
+    /// Octahedral atlas over the upper hemisphere
+    Octahedral,
+}
+
+/// Leaves configuration
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonLeavesConfig {
+    /// Type of leaves (e.g., "Oak", "Pine"), pines and conifers get needles
+    #[serde(rename = "type", default)]
+    pub leaf_type: String,
+    /// Billboard mode: "Single" or "Double"
+    #[serde(default)]
+    pub billboard: JsonBillboard,
+    /// Leaf angle in degrees
+    #[serde(default = "default_leaves_angle")]
+    pub angle: f32,
+    /// Total number of leaves
+    #[serde(default = "default_leaves_count")]
+    pub count: u32,
+    /// Where leaves start on the outermost branches (0.0-1.0)
+    #[serde(default = "default_leaves_start")]
+    pub start: f32,
+    /// Size of leaves in meters
+    #[serde(default = "default_leaves_size")]
+    pub size: f32,
+    /// Variance in leaf size (0.0-1.0)
+    #[serde(rename = "sizeVariance", default)]
+    pub size_variance: f32,
+    /// Color tint of the leaves (RGB)
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub tint: Option<u32>,
+}
+
+/// Leaf billboard mode
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum JsonBillboard {
+    /// One quad per leaf
+    Single,
+    /// Two crossed quads per leaf
+    #[default]
+    Double,
+}
+
+/// Weld configuration
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonWeldConfig {
+    /// Number of grid cells along the longest side of the tree
+    #[serde(default = "default_weld_resolution")]
+    pub resolution: u32,
+    /// Minimum printable thickness of the branches
+    #[serde(rename = "minThickness", skip_serializing_if = "Option::is_none", default)]
+    pub min_thickness: Option<f32>,
+}
+
+/// Skinned skeleton configuration
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JsonSkinConfig {
+    /// Deepest branch level that gets its own bone chain
+    #[serde(default = "default_skin_depth")]
+    pub depth: u32,
+}
+
+/// Growth configuration
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonGrowthConfig {
+    /// Age of the tree (0.0-1.0), or the start of the sequence
+    #[serde(default = "default_growth_age")]
+    pub age: f32,
+    /// Age at the end of the sequence
+    #[serde(rename = "endAge", skip_serializing_if = "Option::is_none", default)]
+    pub end_age: Option<f32>,
+    /// Number of files in the sequence
+    #[serde(default = "default_growth_frames")]
+    pub frames: u32,
+}
+
+/// Wind animation configuration
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonWindConfig {
+    /// Length of the animation loop in seconds
+    #[serde(default = "default_wind_duration")]
+    pub duration: f32,
+    /// Direction the wind blows towards
+    #[serde(default = "default_wind_direction")]
+    pub direction: ForceDirection,
+    /// Sway amplitude of the trunk in degrees
+    #[serde(default = "default_wind_strength")]
+    pub strength: f32,
+    /// Sway frequency of the trunk in Hz
+    #[serde(default = "default_wind_frequency")]
+    pub frequency: f32,
+    /// Export mode: "nodes" (animation channels) or "vertex" (merged mesh with wind attributes)
+    #[serde(default)]
+    pub mode: JsonWindMode,
+}
+
+/// Wind export mode
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JsonWindMode {
+    /// Animation channels rotating every branch node
+    #[default]
+    Nodes,
+    /// Merged mesh with `_WIND_*` vertex attributes
+    Vertex,
+}
+
+/// Roots configuration
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonRootsConfig {
+    /// Number of main roots around the trunk base
+    pub count: JsonParam,
+    /// Configuration of each main root, `angle` is its pitch below horizontal (degrees)
+    pub root: JsonBranchConfig,
+    /// Exponent of the radius falloff along each root (1.0 = linear)
+    #[serde(default = "default_roots_taper")]
+    pub taper: f32,
+    /// Force pulling the roots while they grow (default: straight down)
+    #[serde(default = "default_roots_force")]
+    pub force: JsonForce,
+    /// Color tint of the roots (RGB)
+    #[serde(default = "default_roots_tint")]
+    pub tint: u32,
+    /// Height of the ground plane, root geometry above it is clipped
+    #[serde(rename = "groundClip", skip_serializing_if = "Option::is_none", default)]
+    pub ground_clip: Option<f32>,
+}
+
+/// Force configuration
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JsonForce {
+    /// Direction of the force
+    pub direction: ForceDirection,
+    /// Strength of the force (0.0-1.0)
+    pub strength: f32,
 }
 
 /// Bark configuration
@@ -52,49 +355,125 @@ pub struct TextureScale {
 #[serde(rename_all = "camelCase")]
 pub struct JsonBranchConfig {
     /// Length of the branch
-    pub length: f32,
+    pub length: JsonParam,
     /// Radius at the start of the branch
     #[serde(rename = "startRadius")]
-    pub start_radius: f32,
+    pub start_radius: JsonParam,
     /// Radius at the end of the branch
     #[serde(rename = "endRadius")]
-    pub end_radius: f32,
+    pub end_radius: JsonParam,
     /// Number of segments along the branch length
     #[serde(rename = "lengthSegments")]
-    pub length_segments: u32,
+    pub length_segments: JsonParam,
     /// Number of segments around the branch circumference
     #[serde(rename = "radialSegments")]
-    pub radial_segments: u32,
+    pub radial_segments: JsonParam,
     /// Backward compatibility field, will be ignored if length_segments is present
     #[serde(default)]
     pub segments: u32,
     /// Angle of the branch relative to parent
-    pub angle: f32,
+    pub angle: JsonParam,
     /// Backward compatibility: Tapering factor of the branch
     #[serde(default)]
     pub taper: f32,
     /// Twist amount along the branch axis
-    pub twist: f32,
+    pub twist: JsonParam,
     /// Gnarliness factor (randomness in branch shape)
-    pub gnarliness: f32,
+    pub gnarliness: JsonParam,
     /// Minimum rotation angle (degrees) for branch variations
     #[serde(rename = "minRotation", default = "default_min_rotation")]
-    pub min_rotation: f32,
+    pub min_rotation: JsonParam,
     /// Maximum rotation angle (degrees) for branch variations
     #[serde(rename = "maxRotation", default = "default_max_rotation")]
-    pub max_rotation: f32,
+    pub max_rotation: JsonParam,
     /// Minimum percentage position along parent branch where child branches can appear (0-100)
     #[serde(rename = "minBranchPosPct", default = "default_min_branch_pos_pct")]
-    pub min_branch_pos_pct: f32,
+    pub min_branch_pos_pct: JsonParam,
     /// Maximum percentage position along parent branch where child branches can appear (0-100)
     #[serde(rename = "maxBranchPosPct", default = "default_max_branch_pos_pct")]
-    pub max_branch_pos_pct: f32,
-    /// Number of child branches
-    pub children: u32,
-    /// Configuration for child branches
+    pub max_branch_pos_pct: JsonParam,
+    /// Number of child branches, or a list of child groups each with their own config
+    pub children: JsonChildren,
+    /// Configuration for child branches when `children` is a number
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "children_config")]
     pub children_config: Option<Box<JsonBranchConfig>>,
+    /// Number of sub-axes the branch forks into (0 or 1 for no fork)
+    #[serde(default = "default_splits")]
+    pub splits: JsonParam,
+    /// Angle (degrees) between each sub-axis and the direction of the branch at the fork
+    #[serde(rename = "splitAngle", default = "default_split_angle")]
+    pub split_angle: JsonParam,
+    /// Percentage position along the branch where the fork happens (0-100)
+    #[serde(rename = "splitPosPct", default = "default_split_pos_pct")]
+    pub split_pos_pct: JsonParam,
+    /// Leader continuing the branch past its tip (excurrent trees)
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub leader: Option<JsonLeaderConfig>,
+}
+
+/// JSON configuration for the leader continuing a branch past its tip
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonLeaderConfig {
+    /// Length of the leader relative to the branch it continues
+    #[serde(rename = "lengthRatio")]
+    pub length_ratio: JsonParam,
+}
+
+/// Child branches: a single count using `children_config`, or a list of child groups
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum JsonChildren {
+    /// Number of children, all built from `children_config`
+    Count(JsonParam),
+    /// Groups of children with their own count, config and placement range
+    Groups(Vec<JsonChildGroup>),
+}
+
+/// JSON configuration for a group of child branches
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonChildGroup {
+    /// Number of child branches in the group
+    pub count: JsonParam,
+    /// Configuration for the child branches in the group
+    pub config: JsonBranchConfig,
+    /// Minimum percentage position along the parent branch where the children can appear (0-100)
+    #[serde(rename = "minBranchPosPct", default = "default_min_branch_pos_pct")]
+    pub min_branch_pos_pct: JsonParam,
+    /// Maximum percentage position along the parent branch where the children can appear (0-100)
+    #[serde(rename = "maxBranchPosPct", default = "default_max_branch_pos_pct")]
+    pub max_branch_pos_pct: JsonParam,
+}
+
+/// A numeric branch parameter: either a plain number, `{ value, variance }` or `{ min, max }`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum JsonParam {
+    /// Exact value
+    Scalar(f32),
+    /// Value sampled uniformly from `value ± variance`
+    Variance {
+        value: f32,
+        #[serde(default)]
+        variance: f32,
+    },
+    /// Value sampled uniformly from `[min, max]`
+    Range { min: f32, max: f32 },
+}
+
+impl From<JsonParam> for Param {
+    fn from(param: JsonParam) -> Self {
+        match param {
+            JsonParam::Scalar(value) => Param::fixed(value),
+            JsonParam::Variance { value, variance } => Param { value, variance },
+            JsonParam::Range { min, max } => Param {
+                value: (min + max) * 0.5,
+                variance: (max - min).abs() * 0.5,
+            },
+        }
+    }
 }
 
 /// Force direction (used in the bark texture orientation)
@@ -118,62 +497,337 @@ pub fn read_config_from_file<P: AsRef<Path>>(path: P) -> Result<JsonTreeConfig,
 
 /// Convert a JsonBranchConfig to the application's BranchConfig
 pub fn convert_json_branch_to_branch_config(json_branch: &JsonBranchConfig) -> BranchConfig {
-    println!("Converting JsonBranchConfig: children={}, has_children_config={}", 
-               json_branch.children, json_branch.children_config.is_some());
-    // Recursively convert the children configuration if it exists
-    let children_config = json_branch.children_config
-        .as_ref()
-        .map(|config| {
-            println!("  Found child config with start_radius={}", config.start_radius);
-            Box::new(convert_json_branch_to_branch_config(config))
-        });
-        
-    println!("  Resulting children_config is {}", if children_config.is_some() { "Some" } else { "None" });
+    // Recursively convert the child groups, a single count uses children_config for every child
+    let children = match &json_branch.children {
+        JsonChildren::Count(count) => {
+            json_branch.children_config
+                .as_ref()
+                .map(|config| {
+                    vec![ChildGroup {
+                        count: (*count).into(),
+                        config: convert_json_branch_to_branch_config(config),
+                        min_branch_pos_pct: json_branch.min_branch_pos_pct.into(),
+                        max_branch_pos_pct: json_branch.max_branch_pos_pct.into(),
+                    }]
+                })
+                .unwrap_or_default()
+        }
+        JsonChildren::Groups(groups) => {
+            groups.iter().map(convert_json_child_group).collect()
+        }
+    };
     
     // Determine segment count from length_segments or segments (backward compatibility)
-    let segments = if json_branch.length_segments > 0 {
-        json_branch.length_segments
+    let length_segments = Param::from(json_branch.length_segments);
+    let segments = if length_segments.value > 0.0 {
+        length_segments
     } else {
-        json_branch.segments
+        Param::fixed(json_branch.segments as f32)
     };
 
     BranchConfig {
-        length: json_branch.length,
-        start_radius: json_branch.start_radius,
-        end_radius: json_branch.end_radius,
+        length: json_branch.length.into(),
+        start_radius: json_branch.start_radius.into(),
+        end_radius: json_branch.end_radius.into(),
         length_segments: segments,
-        radial_segments: json_branch.radial_segments,
-        angle: json_branch.angle,
-        twist: json_branch.twist,
-        gnarliness: json_branch.gnarliness,
-        min_rotation: json_branch.min_rotation,
-        max_rotation: json_branch.max_rotation,
-        min_branch_pos_pct: json_branch.min_branch_pos_pct,
-        max_branch_pos_pct: json_branch.max_branch_pos_pct,
-        children: json_branch.children,
-        children_config,
+        radial_segments: json_branch.radial_segments.into(),
+        angle: json_branch.angle.into(),
+        twist: json_branch.twist.into(),
+        gnarliness: json_branch.gnarliness.into(),
+        min_rotation: json_branch.min_rotation.into(),
+        max_rotation: json_branch.max_rotation.into(),
+        children,
+        splits: json_branch.splits.into(),
+        split_angle: json_branch.split_angle.into(),
+        split_pos_pct: json_branch.split_pos_pct.into(),
+        leader: json_branch.leader.as_ref().map(|leader| LeaderConfig {
+            length_ratio: leader.length_ratio.into(),
+        }),
+    }
+}
+
+/// Convert a JsonChildGroup to the application's ChildGroup
+pub fn convert_json_child_group(json_group: &JsonChildGroup) -> ChildGroup {
+    ChildGroup {
+        count: json_group.count.into(),
+        config: convert_json_branch_to_branch_config(&json_group.config),
+        min_branch_pos_pct: json_group.min_branch_pos_pct.into(),
+        max_branch_pos_pct: json_group.max_branch_pos_pct.into(),
+    }
+}
+
+/// Tree configuration for tests, with the given trunk JSON and nothing else
+#[cfg(test)]
+pub(crate) fn trunk_tree_config(trunk: serde_json::Value) -> TreeConfig {
+    let json = serde_json::json!({
+        "type": "Deciduous",
+        "bark": {"type": "Oak", "tint": 16777215, "flatShading": false, "textured": false,
+                 "textureScale": {"x": 1.0, "y": 1.0}},
+        "trunk": trunk
+    });
+    get_tree_config(&serde_json::from_value(json).expect("valid trunk config"))
+}
+
+/// Get the whole tree configuration from the JSON config
+pub fn get_tree_config(json_config: &JsonTreeConfig) -> TreeConfig {
+    TreeConfig {
+        trunk: get_branch_config(json_config),
+        roots: get_roots_config(json_config),
+        wind: get_wind_config(json_config),
+        skin: json_config.skin.as_ref().map(|skin| SkinConfig { depth: skin.depth }),
+        growth: json_config.growth.as_ref().map(|growth| GrowthConfig {
+            age: growth.age,
+            end_age: growth.end_age,
+            frames: growth.frames,
+        }),
+        weld: json_config.weld.as_ref().map(|weld| WeldConfig {
+            resolution: weld.resolution,
+            min_thickness: weld.min_thickness,
+        }),
+        leaves: get_leaves_config(json_config),
+        impostor: json_config.impostor.as_ref().map(|impostor| ImpostorConfig {
+            kind: match impostor.kind {
+                JsonImpostorKind::Crossed => ImpostorKind::Crossed,
+                JsonImpostorKind::Octahedral => ImpostorKind::Octahedral,
+            },
+            views: impostor.views,
+            elevations: impostor.elevations,
+            resolution: impostor.resolution,
+        }),
+        collision: json_config.collision.as_ref().map(|collision| CollisionConfig {
+            shape: match collision.shape {
+                JsonColliderShape::Capsule => ColliderShape::Capsule,
+                JsonColliderShape::Cylinder => ColliderShape::Cylinder,
+            },
+            min_radius: collision.min_radius,
+            segments: collision.segments,
+        }),
+        adaptive_segments: json_config.adaptive_segments.as_ref().map(|adaptive| AdaptiveSegmentsConfig {
+            angle: adaptive.angle,
+        }),
+        export: json_config.export.as_ref().map(|export| ExportConfig {
+            pivot: match export.pivot {
+                JsonPivot::Origin => Pivot::Origin,
+                JsonPivot::TrunkBase => Pivot::TrunkBase,
+            },
+            up_axis: match (export.up_axis, export.y_up) {
+                (Some(JsonUpAxis::Y), _) | (None, true) => UpAxis::Y,
+                _ => UpAxis::Z,
+            },
+            handedness: match export.handedness {
+                JsonHandedness::Right => Handedness::Right,
+                JsonHandedness::Left => Handedness::Left,
+            },
+            units: match export.units {
+                JsonUnits::Meters => Units::Meters,
+                JsonUnits::Centimeters => Units::Centimeters,
+                JsonUnits::Inches => Units::Inches,
+            },
+        }),
+        sag: json_config.sag.as_ref().map(|sag| SagConfig {
+            stiffness: sag.stiffness,
+            density: sag.density,
+        }),
     }
 }
 
+/// Get the leaves configuration from the JSON config, if the tree has leaves
+pub fn get_leaves_config(json_config: &JsonTreeConfig) -> Option<LeavesConfig> {
+    json_config.leaves.as_ref().map(|leaves| LeavesConfig {
+        shape: match leaves.leaf_type.to_ascii_lowercase().as_str() {
+            "pine" | "conifer" => LeafShape::Needle,
+            _ => LeafShape::Broad,
+        },
+        billboard: match leaves.billboard {
+            JsonBillboard::Single => Billboard::Single,
+            JsonBillboard::Double => Billboard::Double,
+        },
+        angle: leaves.angle,
+        count: leaves.count,
+        start: leaves.start.clamp(0.0, 1.0),
+        size: leaves.size,
+        size_variance: leaves.size_variance.clamp(0.0, 1.0),
+        color: leaves.tint.map_or(LEAVES_COLOR, tint_to_color),
+    })
+}
+
+/// Get the wind configuration from the JSON config, if the tree is animated
+pub fn get_wind_config(json_config: &JsonTreeConfig) -> Option<WindConfig> {
+    json_config.wind.as_ref().map(|wind| WindConfig {
+        duration: wind.duration,
+        direction: [wind.direction.x, wind.direction.y, wind.direction.z],
+        strength: wind.strength,
+        frequency: wind.frequency,
+        mode: match wind.mode {
+            JsonWindMode::Nodes => WindMode::Nodes,
+            JsonWindMode::Vertex => WindMode::Vertex,
+        },
+    })
+}
+
+/// Get the roots configuration from the JSON config, if the tree has roots
+pub fn get_roots_config(json_config: &JsonTreeConfig) -> Option<RootsConfig> {
+    json_config.roots.as_ref().map(|roots| RootsConfig {
+        count: roots.count.into(),
+        config: convert_json_branch_to_branch_config(&roots.root),
+        taper: roots.taper,
+        force: Force {
+            direction: [roots.force.direction.x, roots.force.direction.y, roots.force.direction.z],
+            strength: roots.force.strength,
+        },
+        color: tint_to_color(roots.tint),
+        ground_clip: roots.ground_clip,
+    })
+}
+
+/// Convert an RGB tint (0xRRGGBB) to an RGBA color
+pub fn tint_to_color(tint: u32) -> [f32; 4] {
+    [
+        ((tint >> 16) & 0xff) as f32 / 255.0,
+        ((tint >> 8) & 0xff) as f32 / 255.0,
+        (tint & 0xff) as f32 / 255.0,
+        1.0,
+    ]
+}
+
 /// Get the trunk configuration from the JSON config
 pub fn get_branch_config(json_config: &JsonTreeConfig) -> BranchConfig {
     convert_json_branch_to_branch_config(&json_config.trunk)
 }
 
 /// Default value for minimum rotation (20 degrees)
-fn default_min_rotation() -> f32 {
-    20.0
+fn default_min_rotation() -> JsonParam {
+    JsonParam::Scalar(20.0)
 }
 
 /// Default value for maximum rotation (40 degrees)
-fn default_max_rotation() -> f32 {
-    40.0
+fn default_max_rotation() -> JsonParam {
+    JsonParam::Scalar(40.0)
+}
+
+fn default_min_branch_pos_pct() -> JsonParam {
+    JsonParam::Scalar(10.0) // Default to 10% from start of branch
+}
+
+fn default_max_branch_pos_pct() -> JsonParam {
+    JsonParam::Scalar(90.0) // Default to 90% from start of branch
+}
+
+/// Default value for the fork angle (25 degrees)
+fn default_split_angle() -> JsonParam {
+    JsonParam::Scalar(25.0)
+}
+
+fn default_split_pos_pct() -> JsonParam {
+    JsonParam::Scalar(50.0) // Default to forking halfway along the branch
+}
+
+fn default_splits() -> JsonParam {
+    JsonParam::Scalar(0.0) // Default to no fork
+}
+
+fn default_roots_taper() -> f32 {
+    1.5 // Roots thin out faster than branches
+}
+
+fn default_roots_force() -> JsonForce {
+    JsonForce {
+        direction: ForceDirection { x: 0.0, y: 0.0, z: -1.0 },
+        strength: 0.3,
+    }
+}
+
+fn default_roots_tint() -> u32 {
+    0x4a3421 // Dark brown
+}
+
+/// Default value for the leaf angle (45 degrees)
+fn default_leaves_angle() -> f32 {
+    45.0
+}
+
+fn default_leaves_count() -> u32 {
+    500
+}
+
+fn default_leaves_start() -> f32 {
+    0.7
+}
+
+fn default_leaves_size() -> f32 {
+    0.2
+}
+
+/// Default value for the largest bend between meshed rings (5 degrees)
+fn default_adaptive_angle() -> f32 {
+    5.0
+}
+
+/// Default value for the thinnest collider (5 cm)
+fn default_collision_min_radius() -> f32 {
+    0.05
+}
+
+fn default_collision_segments() -> u32 {
+    4
+}
+
+/// Default value for the wood stiffness (10 GPa, green hardwood)
+fn default_sag_stiffness() -> f32 {
+    10.0
+}
+
+/// Default value for the wood density (800 kg/m³, green wood)
+fn default_sag_density() -> f32 {
+    800.0
+}
+
+/// Default value for the number of impostor views (every 45 degrees)
+fn default_impostor_views() -> u32 {
+    8
+}
+
+fn default_impostor_elevations() -> u32 {
+    1
+}
+
+/// Default value for the size of each impostor view (128 pixels)
+fn default_impostor_resolution() -> u32 {
+    128
+}
+
+/// Default value for the weld grid resolution
+fn default_weld_resolution() -> u32 {
+    256
+}
+
+fn default_growth_age() -> f32 {
+    1.0
+}
+
+/// Default value for the number of files in a growth sequence
+fn default_growth_frames() -> u32 {
+    24 // One second at 24 fps
+}
+
+/// Default value for the skin depth (bones for the trunk and its direct children)
+fn default_skin_depth() -> u32 {
+    1
+}
+
+fn default_wind_duration() -> f32 {
+    4.0 // Loop every 4 seconds
+}
+
+fn default_wind_direction() -> ForceDirection {
+    ForceDirection { x: 1.0, y: 0.0, z: 0.0 }
 }
 
-fn default_min_branch_pos_pct() -> f32 {
-    10.0 // Default to 10% from start of branch
+/// Default value for the wind strength (2 degrees of trunk sway)
+fn default_wind_strength() -> f32 {
+    2.0
 }
 
-fn default_max_branch_pos_pct() -> f32 {
-    90.0 // Default to 90% from start of branch
+fn default_wind_frequency() -> f32 {
+    0.5 // One sway every 2 seconds
 }
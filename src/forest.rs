@@ -0,0 +1,452 @@
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Serialize;
+use std::error::Error;
+use std::f32::consts::PI;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::format::OutputFormat;
+use crate::tree::{generate_forest_glb, generate_tree_with_format};
+use crate::{BranchConfig, ChildGroup, LeaderConfig, Param, TreeConfig};
+
+/// Number of candidates tried around each point before it stops spawning new ones
+const POISSON_CANDIDATES: usize = 30;
+/// Most cells of the Poisson-disc background grid, which bounds the memory of a large area at a
+/// small spacing
+const MAX_GRID_CELLS: usize = 4_000_000;
+
+/// Ground area the trees of a forest are scattered over
+#[derive(Debug, Clone)]
+pub enum ForestArea {
+    /// Axis-aligned rectangle on the ground plane
+    Rectangle { min: [f32; 2], max: [f32; 2] },
+    /// Simple polygon on the ground plane, in order
+    Polygon(Vec<[f32; 2]>),
+}
+
+/// Configuration of a forest scattered from one base tree config
+#[derive(Debug, Clone)]
+pub struct ForestConfig {
+    /// Area the trees are placed in
+    pub area: ForestArea,
+    /// Number of trees, as many as fit at the spacing when neither this nor the density is set
+    pub count: Option<u32>,
+    /// Trees per square unit, used when no count is given
+    pub density: Option<f32>,
+    /// Minimum distance between two trees
+    pub spacing: f32,
+    /// Relative variation applied to the branch parameters of each tree (0.1 = ±10%)
+    pub jitter: f32,
+    /// Seed of the placement, every tree gets its own seed derived from it
+    pub seed: u64,
+}
+
+/// A single tree of the forest
+#[derive(Debug, Clone)]
+pub struct TreePlacement {
+    /// Jittered config of the tree
+    pub config: TreeConfig,
+    /// Seed the tree is grown with
+    pub seed: u64,
+    /// Position of the tree base on the ground
+    pub position: [f32; 3],
+    /// Rotation around the up axis in radians
+    pub rotation: f32,
+}
+
+/// Placement manifest written next to the trees of a forest folder
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ForestManifest {
+    seed: u64,
+    trees: Vec<ManifestTree>,
+}
+
+/// A tree in the placement manifest
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ManifestTree {
+    /// File of the tree, relative to the manifest
+    file: String,
+    seed: u64,
+    position: [f32; 3],
+    /// Rotation around the up axis in degrees
+    rotation: f32,
+}
+
+impl ForestArea {
+    /// Parse a rectangle from "min_x,min_y,max_x,max_y"
+    pub fn parse_rectangle(text: &str) -> Result<Self, Box<dyn Error>> {
+        let values = parse_values(text)?;
+        let [x0, y0, x1, y1] = values[..] else {
+            return Err(format!("Expected min_x,min_y,max_x,max_y for the forest area: {}", text).into());
+        };
+        Ok(ForestArea::Rectangle {
+            min: [x0.min(x1), y0.min(y1)],
+            max: [x0.max(x1), y0.max(y1)],
+        })
+    }
+
+    /// Parse a polygon from "x,y x,y x,y..." (points may also be separated by semicolons)
+    pub fn parse_polygon(text: &str) -> Result<Self, Box<dyn Error>> {
+        let points = text
+            .split(|c: char| c == ';' || c.is_whitespace())
+            .filter(|point| !point.is_empty())
+            .map(|point| match parse_values(point)?[..] {
+                [x, y] => Ok([x, y]),
+                _ => Err(format!("Expected x,y for a forest polygon point: {}", point).into()),
+            })
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        if points.len() < 3 {
+            return Err(format!("A forest polygon needs at least 3 points: {}", text).into());
+        }
+        Ok(ForestArea::Polygon(points))
+    }
+
+    /// Bounding rectangle as (min, max)
+    fn bounds(&self) -> ([f32; 2], [f32; 2]) {
+        match self {
+            ForestArea::Rectangle { min, max } => (*min, *max),
+            ForestArea::Polygon(points) => points.iter().fold(
+                ([f32::MAX, f32::MAX], [f32::MIN, f32::MIN]),
+                |(min, max), point| {
+                    ([min[0].min(point[0]), min[1].min(point[1])], [max[0].max(point[0]), max[1].max(point[1])])
+                },
+            ),
+        }
+    }
+
+    /// Whether a point lies inside the area
+    fn contains(&self, point: [f32; 2]) -> bool {
+        match self {
+            ForestArea::Rectangle { min, max } => {
+                point[0] >= min[0] && point[0] <= max[0] && point[1] >= min[1] && point[1] <= max[1]
+            }
+            ForestArea::Polygon(points) => {
+                // Even-odd rule, counting the edges crossed by a ray towards +X
+                let mut inside = false;
+                for (i, a) in points.iter().enumerate() {
+                    let b = points[(i + 1) % points.len()];
+                    if (a[1] > point[1]) != (b[1] > point[1]) {
+                        let x = a[0] + (point[1] - a[1]) / (b[1] - a[1]) * (b[0] - a[0]);
+                        if point[0] < x {
+                            inside = !inside;
+                        }
+                    }
+                }
+                inside
+            }
+        }
+    }
+
+    /// Surface of the area
+    fn area(&self) -> f32 {
+        match self {
+            ForestArea::Rectangle { min, max } => (max[0] - min[0]) * (max[1] - min[1]),
+            ForestArea::Polygon(points) => {
+                let twice: f32 = points
+                    .iter()
+                    .enumerate()
+                    .map(|(i, a)| {
+                        let b = points[(i + 1) % points.len()];
+                        a[0] * b[1] - b[0] * a[1]
+                    })
+                    .sum();
+                twice.abs() / 2.0
+            }
+        }
+    }
+}
+
+/// Place the trees of a forest, each with its own seed, jittered config and rotation
+pub fn plan_forest(config: &TreeConfig, forest: &ForestConfig) -> Result<Vec<TreePlacement>, Box<dyn Error>> {
+    if forest.spacing <= 0.0 {
+        return Err(format!("The forest spacing must be positive: {}", forest.spacing).into());
+    }
+
+    let mut rng = ChaCha8Rng::seed_from_u64(forest.seed);
+    let mut points = poisson_disc(&forest.area, forest.spacing, &mut rng)?;
+
+    // Bridson's sampling grows outwards from its first point, so a subset is picked at random
+    // to spread the trees over the whole area
+    let count = match (forest.count, forest.density) {
+        (Some(count), _) => count as usize,
+        (None, Some(density)) => (density * forest.area.area()).round() as usize,
+        (None, None) => points.len(),
+    };
+    if count > points.len() {
+        println!(
+            "Only {} of the {} trees fit in the forest area at a spacing of {}",
+            points.len(), count, forest.spacing
+        );
+    }
+    points.shuffle(&mut rng);
+    points.truncate(count);
+
+    let placements = points
+        .into_iter()
+        .enumerate()
+        .map(|(index, [x, y])| TreePlacement {
+            config: jitter_tree_config(config, forest.jitter, &mut rng),
+            seed: derive_seed(forest.seed, index as u64),
+            position: [x, y, 0.0],
+            rotation: rng.gen_range(0.0..2.0 * PI),
+        })
+        .collect();
+    Ok(placements)
+}
+
+/// Generate a forest and write it to one GLB when the output has a `.glb` extension, or else to a
+/// folder holding one file per tree and a `manifest.json` with their placements
+///
+/// # Arguments
+///
+/// * `config` - Base tree configuration, jittered for every tree
+/// * `forest` - Area, count and spacing of the forest
+/// * `output_path` - GLB file or folder to write the forest to
+/// * `format` - Format of the tree files in a folder (default: GLB)
+pub fn generate_forest(
+    config: &TreeConfig,
+    forest: &ForestConfig,
+    output_path: &Path,
+    format: Option<OutputFormat>,
+) -> Result<(), Box<dyn Error>> {
+    let placements = plan_forest(config, forest)?;
+    if placements.is_empty() {
+        return Err("No tree fits in the forest area, use a smaller spacing".into());
+    }
+    println!("Placing {} trees", placements.len());
+
+    let is_glb = output_path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("glb"));
+    if is_glb && format.is_none() {
+        generate_forest_glb(&placements, output_path)?;
+        println!("Forest of {} trees saved to: {}", placements.len(), output_path.display());
+        return Ok(());
+    }
+
+    fs::create_dir_all(output_path)?;
+    let format = format.unwrap_or(OutputFormat::Glb);
+    let mut trees = Vec::with_capacity(placements.len());
+    for (index, placement) in placements.iter().enumerate() {
+        let file = format!("tree_{:03}.{}", index, format.extension());
+        generate_tree_with_format(&placement.config, Some(placement.seed), Some(&output_path.join(&file)), Some(format))?;
+        trees.push(ManifestTree {
+            file,
+            seed: placement.seed,
+            position: placement.position,
+            rotation: placement.rotation.to_degrees(),
+        });
+    }
+
+    let manifest_path = output_path.join("manifest.json");
+    let manifest = ForestManifest { seed: forest.seed, trees };
+    serde_json::to_writer_pretty(BufWriter::new(File::create(&manifest_path)?), &manifest)?;
+    println!("Forest manifest saved to: {}", manifest_path.display());
+    Ok(())
+}
+
+/// Poisson-disc points filling the area, no two closer than `spacing` (Bridson's algorithm)
+fn poisson_disc(area: &ForestArea, spacing: f32, rng: &mut ChaCha8Rng) -> Result<Vec<[f32; 2]>, Box<dyn Error>> {
+    let (min, max) = area.bounds();
+    if max[0] < min[0] || max[1] < min[1] {
+        return Ok(Vec::new());
+    }
+
+    // A background grid with at most one point per cell speeds up the neighbour search
+    let cell_size = spacing / 2.0_f32.sqrt();
+    let columns = ((max[0] - min[0]) / cell_size).floor() as usize + 1;
+    let rows = ((max[1] - min[1]) / cell_size).floor() as usize + 1;
+    if columns.checked_mul(rows).is_none_or(|cells| cells > MAX_GRID_CELLS) {
+        return Err(format!(
+            "The forest area is too large for a spacing of {}, use a larger spacing or a smaller area",
+            spacing
+        )
+        .into());
+    }
+    let mut grid: Vec<Option<usize>> = vec![None; columns * rows];
+    let cell = |point: [f32; 2]| {
+        let column = (((point[0] - min[0]) / cell_size) as usize).min(columns - 1);
+        let row = (((point[1] - min[1]) / cell_size) as usize).min(rows - 1);
+        (column, row)
+    };
+
+    let mut points: Vec<[f32; 2]> = Vec::new();
+    let mut active: Vec<usize> = Vec::new();
+    let sample = |rng: &mut ChaCha8Rng| [rng.gen_range(min[0]..=max[0]), rng.gen_range(min[1]..=max[1])];
+
+    // The first point is any point inside the area
+    let Some(first) = (0..1000).map(|_| sample(rng)).find(|&point| area.contains(point)) else {
+        return Ok(points);
+    };
+    let (column, row) = cell(first);
+    grid[row * columns + column] = Some(0);
+    points.push(first);
+    active.push(0);
+
+    while !active.is_empty() {
+        let slot = rng.gen_range(0..active.len());
+        let center = points[active[slot]];
+
+        let mut spawned = false;
+        for _ in 0..POISSON_CANDIDATES {
+            let distance = rng.gen_range(spacing..2.0 * spacing);
+            let angle = rng.gen_range(0.0..2.0 * PI);
+            let candidate = [center[0] + distance * angle.cos(), center[1] + distance * angle.sin()];
+            if candidate[0] < min[0] || candidate[0] > max[0] || candidate[1] < min[1] || candidate[1] > max[1] || !area.contains(candidate) {
+                continue;
+            }
+
+            let (column, row) = cell(candidate);
+            let too_close = (row.saturating_sub(2)..(row + 3).min(rows))
+                .flat_map(|r| (column.saturating_sub(2)..(column + 3).min(columns)).map(move |c| r * columns + c))
+                .filter_map(|index| grid[index])
+                .any(|other| {
+                    let (dx, dy) = (points[other][0] - candidate[0], points[other][1] - candidate[1]);
+                    dx * dx + dy * dy < spacing * spacing
+                });
+            if too_close {
+                continue;
+            }
+
+            grid[row * columns + column] = Some(points.len());
+            active.push(points.len());
+            points.push(candidate);
+            spawned = true;
+            break;
+        }
+
+        if !spawned {
+            active.swap_remove(slot);
+        }
+    }
+
+    Ok(points)
+}
+
+/// Seed of the tree at `index`, spread with SplitMix64 so neighbouring trees don't look alike
+fn derive_seed(seed: u64, index: u64) -> u64 {
+    let mut z = seed.wrapping_add(index.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Copy of the tree config with its shape parameters scaled by up to ±`amount`
+fn jitter_tree_config(config: &TreeConfig, amount: f32, rng: &mut ChaCha8Rng) -> TreeConfig {
+    let mut jittered = config.clone();
+    if amount <= 0.0 {
+        return jittered;
+    }
+    jittered.trunk = jitter_branch_config(&config.trunk, amount, rng);
+    if let Some(roots) = &mut jittered.roots {
+        roots.config = jitter_branch_config(&roots.config, amount, rng);
+    }
+    jittered
+}
+
+/// Jitter the shape of a branch config and its children, the mesh resolution is kept
+fn jitter_branch_config(config: &BranchConfig, amount: f32, rng: &mut ChaCha8Rng) -> BranchConfig {
+    BranchConfig {
+        length: jitter_param(&config.length, amount, rng),
+        start_radius: jitter_param(&config.start_radius, amount, rng),
+        end_radius: jitter_param(&config.end_radius, amount, rng),
+        angle: jitter_param(&config.angle, amount, rng),
+        twist: jitter_param(&config.twist, amount, rng),
+        gnarliness: jitter_param(&config.gnarliness, amount, rng),
+        split_angle: jitter_param(&config.split_angle, amount, rng),
+        children: config
+            .children
+            .iter()
+            .map(|group| ChildGroup {
+                count: jitter_param(&group.count, amount, rng),
+                config: jitter_branch_config(&group.config, amount, rng),
+                ..group.clone()
+            })
+            .collect(),
+        leader: config.leader.as_ref().map(|leader| LeaderConfig {
+            length_ratio: jitter_param(&leader.length_ratio, amount, rng),
+        }),
+        ..config.clone()
+    }
+}
+
+/// Scale the value of a parameter by a random factor in `1 ± amount`
+fn jitter_param(param: &Param, amount: f32, rng: &mut ChaCha8Rng) -> Param {
+    Param {
+        value: param.value * (1.0 + rng.gen_range(-amount..=amount)),
+        variance: param.variance,
+    }
+}
+
+/// Parse comma separated numbers
+fn parse_values(text: &str) -> Result<Vec<f32>, Box<dyn Error>> {
+    text.split(',')
+        .map(|value| {
+            let value = value.trim();
+            value.parse::<f32>().map_err(|_| format!("Invalid number in forest area: {}", value).into())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn samples_keep_their_spacing() {
+        let area = ForestArea::parse_polygon("0,0 20,0 20,10 10,20 0,20").unwrap();
+        let spacing = 1.5;
+        let points = poisson_disc(&area, spacing, &mut ChaCha8Rng::seed_from_u64(7)).unwrap();
+        assert!(points.len() > 50, "only {} points", points.len());
+
+        for (i, a) in points.iter().enumerate() {
+            assert!(area.contains(*a), "{:?} is outside the area", a);
+            for b in &points[i + 1..] {
+                let distance = ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt();
+                assert!(distance >= spacing, "{:?} and {:?} are {} apart", a, b, distance);
+            }
+        }
+    }
+
+    #[test]
+    fn grid_size_is_bounded() {
+        let area = ForestArea::parse_rectangle("0,0,100000,100000").unwrap();
+        assert!(poisson_disc(&area, 0.5, &mut ChaCha8Rng::seed_from_u64(7)).is_err());
+    }
+
+    #[test]
+    fn polygon_contains_and_area() {
+        // An L shape, a 4 by 4 square missing its top right quarter
+        let area = ForestArea::parse_polygon("0,0 4,0 4,2 2,2 2,4 0,4").unwrap();
+        assert!((area.area() - 12.0).abs() < 1.0e-5);
+        assert!(area.contains([1.0, 3.0]));
+        assert!(area.contains([3.0, 1.0]));
+        assert!(!area.contains([3.0, 3.0]));
+        assert!(!area.contains([-1.0, 1.0]));
+
+        // The winding doesn't change the area
+        let reversed = ForestArea::parse_polygon("0,4 2,4 2,2 4,2 4,0 0,0").unwrap();
+        assert!((reversed.area() - 12.0).abs() < 1.0e-5);
+
+        let rectangle = ForestArea::parse_rectangle("3,-1,-1,2").unwrap();
+        assert!((rectangle.area() - 12.0).abs() < 1.0e-5);
+        assert!(rectangle.contains([0.0, 0.0]));
+        assert!(!rectangle.contains([0.0, 3.0]));
+    }
+
+    #[test]
+    fn tree_seeds_are_deterministic_and_distinct() {
+        let seeds: Vec<u64> = (0..1000).map(|index| derive_seed(42, index)).collect();
+        assert_eq!(seeds, (0..1000).map(|index| derive_seed(42, index)).collect::<Vec<_>>());
+        assert_eq!(seeds.iter().collect::<HashSet<_>>().len(), seeds.len());
+        assert!(!seeds.contains(&42));
+        assert_ne!(derive_seed(43, 0), seeds[0]);
+    }
+}
@@ -0,0 +1,66 @@
+use std::error::Error;
+use std::path::Path;
+
+/// File format of the exported tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Binary glTF
+    Glb,
+    /// Text glTF with the binary buffer in a separate `.bin` file
+    Gltf,
+    /// Wavefront OBJ with an MTL material library
+    Obj,
+    /// USD text file
+    Usda,
+    /// Binary PLY
+    Ply,
+    /// Binary STL
+    Stl,
+    /// Branch skeleton as JSON, without any mesh
+    SkeletonJson,
+    /// Branch skeleton as OBJ polylines
+    SkeletonObj,
+}
+
+impl OutputFormat {
+    /// Pick the format from the extension of the output file, GLB when it has none
+    pub fn from_path(path: &Path) -> Result<Self, Box<dyn Error>> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) => Self::from_extension(extension),
+            None => Ok(OutputFormat::Glb),
+        }
+    }
+
+    /// Parse a format from a file extension or format name ("glb", "gltf", "obj", "usda", "ply", "stl",
+    /// "json" or "skeleton" for the skeleton JSON, "skeleton-obj" for the skeleton polylines)
+    pub fn from_extension(extension: &str) -> Result<Self, Box<dyn Error>> {
+        match extension.to_ascii_lowercase().as_str() {
+            "glb" => Ok(OutputFormat::Glb),
+            "gltf" => Ok(OutputFormat::Gltf),
+            "obj" => Ok(OutputFormat::Obj),
+            "usda" => Ok(OutputFormat::Usda),
+            "ply" => Ok(OutputFormat::Ply),
+            "stl" => Ok(OutputFormat::Stl),
+            "json" | "skeleton" => Ok(OutputFormat::SkeletonJson),
+            "skeleton-obj" => Ok(OutputFormat::SkeletonObj),
+            _ => Err(format!(
+                "Unsupported output format: {} (expected glb, gltf, obj, usda, ply, stl, skeleton or skeleton-obj)",
+                extension
+            ).into()),
+        }
+    }
+
+    /// File extension of the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Glb => "glb",
+            OutputFormat::Gltf => "gltf",
+            OutputFormat::Obj => "obj",
+            OutputFormat::Usda => "usda",
+            OutputFormat::Ply => "ply",
+            OutputFormat::Stl => "stl",
+            OutputFormat::SkeletonJson => "json",
+            OutputFormat::SkeletonObj => "obj",
+        }
+    }
+}
@@ -0,0 +1,345 @@
+use serde_json::{json, Value};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// GLB header magic
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+/// Chunk type of the JSON chunk ("JSON")
+const CHUNK_JSON: u32 = 0x4E4F_534A;
+/// Chunk type of the binary chunk ("BIN\0")
+const CHUNK_BIN: u32 = 0x004E_4942;
+
+/// Accessor component type for 16-bit unsigned integers
+pub const COMPONENT_UNSIGNED_SHORT: u32 = 5123;
+/// Accessor component type for 32-bit floats
+pub const COMPONENT_FLOAT: u32 = 5126;
+/// Buffer view target for vertex attributes
+pub const TARGET_ARRAY_BUFFER: u32 = 34962;
+/// Buffer view target for vertex indices
+pub const TARGET_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+/// A GLB file in the temporary directory, deleted when dropped
+///
+/// Text glTF is converted from a GLB that mesh-tools can only write to a file. Every file gets its
+/// own name, so several exports in one process don't overwrite each other.
+#[derive(Debug)]
+pub struct TempGlb {
+    path: PathBuf,
+}
+
+impl TempGlb {
+    pub fn in_temp_dir() -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let count = COUNT.fetch_add(1, Ordering::Relaxed);
+        Self {
+            path: std::env::temp_dir().join(format!("tree-maker-{}-{}.glb", std::process::id(), count)),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempGlb {
+    fn drop(&mut self) {
+        // The file isn't there when the export failed before writing it
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// A glTF document read back from an exported GLB file
+///
+/// mesh-tools writes the geometry, materials and node hierarchy. The parts of glTF it
+/// doesn't cover (animations, extras, extensions) are added by editing the document.
+#[derive(Debug, Clone)]
+pub struct GlbDocument {
+    /// The glTF JSON
+    pub json: Value,
+    /// The binary buffer
+    pub bin: Vec<u8>,
+}
+
+impl GlbDocument {
+    /// Read a GLB file
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let data = fs::read(path)?;
+        if data.len() < 12 || &data[0..4] != GLB_MAGIC {
+            return Err(format!("Not a GLB file: {}", path.display()).into());
+        }
+
+        let mut json = None;
+        let mut bin = Vec::new();
+        let mut offset = 12;
+        while offset + 8 <= data.len() {
+            let length = read_u32(&data, offset) as usize;
+            let chunk_type = read_u32(&data, offset + 4);
+            let chunk = data
+                .get(offset + 8..offset + 8 + length)
+                .ok_or_else(|| format!("Truncated chunk in GLB file: {}", path.display()))?;
+            match chunk_type {
+                CHUNK_JSON => json = Some(serde_json::from_slice(chunk)?),
+                CHUNK_BIN => bin = chunk.to_vec(),
+                _ => {} // Unknown chunks must be ignored
+            }
+            offset += 8 + length;
+        }
+
+        let json = json.ok_or_else(|| format!("No JSON chunk in GLB file: {}", path.display()))?;
+        Ok(Self { json, bin })
+    }
+
+    /// Write the document as a GLB file
+    pub fn write_glb(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        self.update_buffer_length();
+
+        let mut json = serde_json::to_vec(&self.json)?;
+        pad_to_four(&mut json, b' ');
+        let mut bin = self.bin.clone();
+        pad_to_four(&mut bin, 0);
+
+        let bin_chunk_length = if bin.is_empty() { 0 } else { 8 + bin.len() };
+        let total_length = 12 + 8 + json.len() + bin_chunk_length;
+
+        let mut data = Vec::with_capacity(total_length);
+        data.extend_from_slice(GLB_MAGIC);
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&(total_length as u32).to_le_bytes());
+        data.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        data.extend_from_slice(&CHUNK_JSON.to_le_bytes());
+        data.extend_from_slice(&json);
+        if !bin.is_empty() {
+            data.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+            data.extend_from_slice(&CHUNK_BIN.to_le_bytes());
+            data.extend_from_slice(&bin);
+        }
+
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    /// Write the document as a text glTF file, with the binary buffer in a `.bin` file next to it
+    ///
+    /// Embedded images are written to their own files so the JSON can be read and diffed as text.
+    pub fn write_gltf(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let stem = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| format!("Invalid output path: {}", path.display()))?
+            .to_string();
+
+        self.extract_images(path, &stem)?;
+
+        if !self.bin.is_empty() {
+            self.update_buffer_length();
+            let bin_name = format!("{}.bin", stem);
+            fs::write(path.with_file_name(&bin_name), &self.bin)?;
+            self.json["buffers"][0]["uri"] = json!(bin_name);
+        }
+
+        fs::write(path, serde_json::to_string_pretty(&self.json)?)?;
+        Ok(())
+    }
+
+    /// Move the images stored in buffer views to files next to `path`, referenced by URI
+    fn extract_images(&mut self, path: &Path, stem: &str) -> Result<(), Box<dyn Error>> {
+        let image_count = self.json["images"].as_array().map_or(0, |images| images.len());
+        for index in 0..image_count {
+            let image = &self.json["images"][index];
+            let view = match image["bufferView"].as_u64() {
+                Some(view) => &self.json["bufferViews"][view as usize],
+                None => continue,
+            };
+            let extension = match image["mimeType"].as_str() {
+                Some("image/jpeg") => "jpg",
+                _ => "png",
+            };
+
+            let offset = view["byteOffset"].as_u64().unwrap_or(0) as usize;
+            let length = view["byteLength"].as_u64().unwrap_or(0) as usize;
+            let bytes = self
+                .bin
+                .get(offset..offset + length)
+                .ok_or_else(|| format!("Image {} is outside the binary buffer", index))?;
+
+            let image_name = format!("{}_image{}.{}", stem, index, extension);
+            fs::write(path.with_file_name(&image_name), bytes)?;
+
+            let image = &mut self.json["images"][index];
+            if let Some(image) = image.as_object_mut() {
+                image.remove("bufferView");
+                image.remove("mimeType");
+            }
+            image["uri"] = json!(image_name);
+        }
+        Ok(())
+    }
+
+    /// Append a value to a top-level array (creating it if needed) and return its index
+    pub fn push(&mut self, key: &str, value: Value) -> usize {
+        if !self.json[key].is_array() {
+            self.json[key] = json!([]);
+        }
+        let array = self.json[key].as_array_mut().expect("array was just created");
+        array.push(value);
+        array.len() - 1
+    }
+
+    /// Append a node to the children of another node
+    pub fn add_child(&mut self, parent: usize, child: usize) {
+        let node = &mut self.json["nodes"][parent];
+        if !node["children"].is_array() {
+            node["children"] = json!([]);
+        }
+        if let Some(children) = node["children"].as_array_mut() {
+            children.push(json!(child));
+        }
+    }
+
+    /// List an extension in `extensionsUsed`, once
+    pub fn use_extension(&mut self, extension: &str) {
+        let used = self.json["extensionsUsed"]
+            .as_array()
+            .is_some_and(|extensions| extensions.iter().any(|used| used == extension));
+        if !used {
+            self.push("extensionsUsed", json!(extension));
+        }
+    }
+
+    /// Append bytes to the binary buffer as a new buffer view and return its index
+    pub fn add_buffer_view(&mut self, bytes: &[u8], target: Option<u32>) -> usize {
+        // Accessors need their data aligned to 4 bytes
+        pad_to_four(&mut self.bin, 0);
+
+        let mut view = json!({
+            "buffer": 0,
+            "byteOffset": self.bin.len(),
+            "byteLength": bytes.len(),
+        });
+        if let Some(target) = target {
+            view["target"] = json!(target);
+        }
+        self.bin.extend_from_slice(bytes);
+        self.update_buffer_length();
+        self.push("bufferViews", view)
+    }
+
+    /// Add a float accessor with `components` floats per element (1 = SCALAR, 2 = VEC2, 3 = VEC3, 4 = VEC4)
+    pub fn add_float_accessor(&mut self, data: &[f32], components: usize, target: Option<u32>) -> usize {
+        // Min and max bounds of every component
+        let mut min = vec![f32::MAX; components];
+        let mut max = vec![f32::MIN; components];
+        for element in data.chunks(components) {
+            for (i, value) in element.iter().enumerate() {
+                min[i] = min[i].min(*value);
+                max[i] = max[i].max(*value);
+            }
+        }
+
+        let bytes: Vec<u8> = data.iter().flat_map(|value| value.to_le_bytes()).collect();
+        let view = self.add_buffer_view(&bytes, target);
+        self.push("accessors", json!({
+            "bufferView": view,
+            "componentType": COMPONENT_FLOAT,
+            "count": data.len() / components,
+            "type": accessor_type(components),
+            "min": min,
+            "max": max,
+        }))
+    }
+
+    /// Read the elements of a float accessor, `components` floats each
+    pub fn read_float_accessor(&self, accessor: usize) -> Option<(Vec<f32>, usize)> {
+        let accessor = &self.json["accessors"][accessor];
+        if accessor["componentType"].as_u64() != Some(COMPONENT_FLOAT as u64) {
+            return None;
+        }
+        let components = match accessor["type"].as_str()? {
+            "SCALAR" => 1,
+            "VEC2" => 2,
+            "VEC3" => 3,
+            "VEC4" => 4,
+            "MAT4" => 16,
+            _ => return None,
+        };
+        let count = accessor["count"].as_u64()? as usize;
+        let view = &self.json["bufferViews"][accessor["bufferView"].as_u64()? as usize];
+        let offset = view["byteOffset"].as_u64().unwrap_or(0) as usize + accessor["byteOffset"].as_u64().unwrap_or(0) as usize;
+        let stride = view["byteStride"].as_u64().map_or(components * 4, |stride| stride as usize);
+
+        let mut values = Vec::with_capacity(count * components);
+        for element in 0..count {
+            for component in 0..components {
+                let start = offset + element * stride + component * 4;
+                let bytes = self.bin.get(start..start + 4)?;
+                values.push(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+            }
+        }
+        Some((values, components))
+    }
+
+    /// Add an unsigned short accessor with `components` values per element (joint indices)
+    pub fn add_u16_accessor(&mut self, data: &[u16], components: usize, target: Option<u32>) -> usize {
+        let bytes: Vec<u8> = data.iter().flat_map(|value| value.to_le_bytes()).collect();
+        let view = self.add_buffer_view(&bytes, target);
+        self.push("accessors", json!({
+            "bufferView": view,
+            "componentType": COMPONENT_UNSIGNED_SHORT,
+            "count": data.len() / components,
+            "type": accessor_type(components),
+        }))
+    }
+
+    /// Make sure the document has a buffer whose length matches the binary data
+    fn update_buffer_length(&mut self) {
+        if self.bin.is_empty() {
+            return;
+        }
+        match self.json["buffers"].as_array() {
+            Some(buffers) if !buffers.is_empty() => {}
+            _ => self.json["buffers"] = json!([{}]),
+        }
+        self.json["buffers"][0]["byteLength"] = json!(self.bin.len());
+    }
+}
+
+/// Accessor type for a number of components per element
+fn accessor_type(components: usize) -> &'static str {
+    match components {
+        1 => "SCALAR",
+        2 => "VEC2",
+        3 => "VEC3",
+        4 => "VEC4",
+        16 => "MAT4",
+        _ => panic!("Unsupported accessor component count: {}", components),
+    }
+}
+
+/// Read a little-endian u32 at `offset`
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+/// Pad data to a multiple of four bytes, as required for GLB chunks and accessors
+fn pad_to_four(data: &mut Vec<u8>, padding: u8) {
+    let padding_length = (4 - data.len() % 4) % 4;
+    data.resize(data.len() + padding_length, padding);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temporary_glb_is_unique_and_removed() {
+        let (first, second) = (TempGlb::in_temp_dir(), TempGlb::in_temp_dir());
+        assert_ne!(first.path(), second.path());
+
+        let path = first.path().to_path_buf();
+        fs::write(&path, b"glTF").unwrap();
+        drop(first);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,179 @@
+use crate::tree::{Branch, BranchTransform, Tree};
+
+/// The tree as it looked at `age` (0.0 = seed, 1.0 = fully grown)
+///
+/// Every branch starts growing once its parent has passed its attachment point, and the whole
+/// tree finishes together at age 1.0. Branches continuing their parent from its tip, like fork
+/// sub-axes and leaders, share its timeline: the parent grows first and hands over when it reaches
+/// its tip, at the same speed along the whole axis. A growing branch is truncated along its path
+/// and its radii scale with its own progress. Branches that haven't started are dropped.
+pub fn tree_at_age(tree: &Tree, age: f32) -> Tree {
+    if age >= 1.0 {
+        return tree.clone();
+    }
+    let age = age.max(0.0);
+    let count = tree.branches.len();
+
+    // Length of each branch with the longest chain of branches continuing it from its tip,
+    // children coming after their parents
+    let lengths: Vec<f32> = tree.branches.iter().map(path_length).collect();
+    let mut axis_lengths = lengths.clone();
+    for index in (0..count).rev() {
+        if let Some(parent) = continued_parent(tree, index) {
+            axis_lengths[parent] = axis_lengths[parent].max(lengths[parent] + axis_lengths[index]);
+        }
+    }
+
+    // Ages each branch starts and finishes growing at, and its index in the grown tree
+    let mut windows = vec![(0.0, 1.0); count];
+    let mut new_indices: Vec<Option<usize>> = vec![None; count];
+    let mut branches: Vec<Branch> = Vec::new();
+
+    // Parents are stored before their children, so their ages and new indices are known
+    for (index, branch) in tree.branches.iter().enumerate() {
+        let start_age = match (branch.parent, branch.attachment_index) {
+            (Some(parent), Some(attachment)) => {
+                let (parent_start, parent_end) = windows[parent];
+                let parent_rings = tree.branches[parent].transforms.len();
+                let t = (attachment as f32 / parent_rings.saturating_sub(1).max(1) as f32).min(1.0);
+                parent_start + (parent_end - parent_start) * t
+            }
+            _ => 0.0,
+        };
+        // The branch's own share of the time left to grow its axis
+        let share = if axis_lengths[index] > f32::EPSILON { lengths[index] / axis_lengths[index] } else { 1.0 };
+        let end_age = start_age + (1.0 - start_age) * share;
+        windows[index] = (start_age, end_age);
+
+        // A branch without its parent hasn't started either
+        let parent = match branch.parent {
+            Some(parent) => match new_indices[parent] {
+                Some(new_parent) => Some(new_parent),
+                None => continue,
+            },
+            None => None,
+        };
+
+        let progress = if end_age - start_age > f32::EPSILON { (age - start_age) / (end_age - start_age) } else { 0.0 };
+        if progress <= f32::EPSILON || branch.transforms.len() < 2 {
+            continue;
+        }
+
+        let mut grown = truncate_branch(branch, progress.min(1.0));
+        grown.parent = parent;
+
+        // The attachment point may be the very end of a parent that only just passed it
+        if let (Some(parent), Some(attachment)) = (parent, grown.attachment_index) {
+            let parent_rings = branches[parent].transforms.len();
+            grown.attachment_index = Some(attachment.min(parent_rings - 1));
+        }
+
+        new_indices[index] = Some(branches.len());
+        branches.push(grown);
+    }
+
+    Tree {
+        branches,
+        ground_clip: tree.ground_clip,
+        ring_angle: tree.ring_angle,
+    }
+}
+
+/// Parent of a branch attached at its parent's tip, continuing it
+fn continued_parent(tree: &Tree, index: usize) -> Option<usize> {
+    let branch = &tree.branches[index];
+    let (parent, attachment) = (branch.parent?, branch.attachment_index?);
+    let parent_rings = tree.branches[parent].transforms.len();
+    (parent_rings > 0 && attachment + 1 >= parent_rings).then_some(parent)
+}
+
+/// Length of the path of a branch
+fn path_length(branch: &Branch) -> f32 {
+    branch.transforms.windows(2).map(|pair| (pair[1].point() - pair[0].point()).norm()).sum()
+}
+
+/// Cut a branch at `progress` (0.0-1.0) of its path and scale its radii by it
+fn truncate_branch(branch: &Branch, progress: f32) -> Branch {
+    let last = branch.transforms.len() - 1;
+    let end = progress * last as f32;
+    let whole = (end.floor() as usize).min(last);
+    let fraction = end - whole as f32;
+
+    let mut transforms: Vec<BranchTransform> = branch.transforms[..=whole].to_vec();
+    let mut radii: Vec<f32> = branch.radii.iter().take(whole + 1).copied().collect();
+
+    // Interpolate the ring the branch currently ends at
+    if fraction > 1.0e-4 && whole < last {
+        let (from, to) = (&branch.transforms[whole], &branch.transforms[whole + 1]);
+        let position = from.point() + (to.point() - from.point()) * fraction;
+        let rotation = from.unit_quaternion().slerp(&to.unit_quaternion(), fraction).into_inner();
+        transforms.push(BranchTransform {
+            position: [position.x, position.y, position.z],
+            rotation: [rotation.i, rotation.j, rotation.k, rotation.w],
+        });
+
+        let from_radius = radii.last().copied().unwrap_or(0.0);
+        let to_radius = branch.radii.get(whole + 1).copied().unwrap_or(from_radius);
+        radii.push(from_radius + (to_radius - from_radius) * fraction);
+    }
+
+    // A branch needs two rings to be meshed, a barely started one is a sliver at its base
+    if transforms.len() < 2 {
+        transforms.push(transforms[0].clone());
+        radii.push(radii.first().copied().unwrap_or(0.0));
+    }
+
+    Branch {
+        transforms,
+        radii: radii.iter().map(|radius| radius * progress).collect(),
+        ..branch.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A trunk with a side child halfway, forked at its tip into two sub-axes, one with a leader
+    fn forked_tree() -> Tree {
+        Tree {
+            branches: vec![
+                Branch::straight(0, None, None, 5),
+                Branch::straight(0, Some(0), Some(2), 3),
+                Branch::straight(0, Some(0), Some(4), 5),
+                Branch::straight(0, Some(0), Some(4), 5),
+                Branch::straight(0, Some(3), Some(4), 3),
+            ],
+            ground_clip: None,
+            ring_angle: None,
+        }
+    }
+
+    #[test]
+    fn fully_grown_tree_keeps_every_branch() {
+        let tree = forked_tree();
+        let grown = tree_at_age(&tree, 1.0);
+        assert_eq!(grown.branches.len(), tree.branches.len());
+        for (grown, branch) in grown.branches.iter().zip(&tree.branches) {
+            assert_eq!(grown.transforms.len(), branch.transforms.len());
+            assert_eq!(grown.radii, branch.radii);
+        }
+    }
+
+    #[test]
+    fn continuations_grow_after_their_parent() {
+        let tree = forked_tree();
+
+        // The axis through the trunk, sub-axis and leader is 4 + 4 + 2 long, the trunk is done at 0.4
+        let young = tree_at_age(&tree, 0.3);
+        assert_eq!(young.branches.len(), 2);
+
+        let older = tree_at_age(&tree, 0.7);
+        assert_eq!(older.branches.len(), 4);
+        assert!(older.branches.iter().all(|branch| branch.transforms.len() >= 2));
+
+        let almost = tree_at_age(&tree, 0.95);
+        assert_eq!(almost.branches.len(), 5);
+        assert!((path_length(&almost.branches[4]) - 1.5).abs() < 1.0e-4);
+    }
+}
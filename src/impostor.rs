@@ -0,0 +1,375 @@
+use nalgebra::{Point3, Quaternion, UnitQuaternion, Vector3};
+use serde_json::{json, Value};
+use std::error::Error;
+use std::f32::consts::PI;
+
+use crate::gltf::{GlbDocument, TARGET_ARRAY_BUFFER, TARGET_ELEMENT_ARRAY_BUFFER};
+use crate::leaves::{leaf_prototype, LeafInstance};
+use crate::raster::{render_view, RasterTriangle, RenderedView, ViewCamera};
+use crate::tree::{BranchKind, Tree, TRUNK_COLOR};
+use crate::{ImpostorConfig, ImpostorKind, TreeConfig};
+
+/// glTF extension listing lower levels of detail of a node
+pub const LOD_EXTENSION: &str = "MSFT_lod";
+/// Samples per pixel along each axis when rendering the views
+const SUPERSAMPLING: usize = 2;
+/// Most views along each side of the atlas
+const MAX_VIEWS: u32 = 32;
+/// Largest size of a view in pixels
+const MAX_RESOLUTION: u32 = 1024;
+/// Largest side of the atlas in pixels, the views are made smaller to fit
+const MAX_ATLAS_SIZE: usize = 4096;
+
+/// A view of the tree rendered into the atlas
+struct AtlasView {
+    camera: ViewCamera,
+    column: usize,
+    row: usize,
+}
+
+/// Render the tree into an impostor atlas and add the impostor as the far level of detail of the
+/// tree's root node
+///
+/// The views are rendered on the CPU into an albedo atlas, with the coverage as alpha, and a
+/// normal atlas in the frame of each view. The impostor mesh, material and atlas images are
+/// embedded in the document, and the root node lists the impostor node with `MSFT_lod`.
+///
+/// # Arguments
+///
+/// * `document` - Exported tree to add the impostor to
+/// * `tree` - Skeleton of the exported tree
+/// * `config` - Tree configuration, for the leaves
+/// * `leaves` - Leaves of the exported tree in the tree's frame
+/// * `impostor` - Views and atlas resolution
+pub fn add_impostor(
+    document: &mut GlbDocument,
+    tree: &Tree,
+    config: &TreeConfig,
+    leaves: &[LeafInstance],
+    impostor: &ImpostorConfig,
+) -> Result<(), Box<dyn Error>> {
+    let triangles = tree_triangles(tree, config, leaves);
+    if triangles.is_empty() {
+        return Ok(());
+    }
+
+    // Every view is framed on the bounding sphere of the tree, so the views line up
+    let mut min = Point3::new(f32::MAX, f32::MAX, f32::MAX);
+    let mut max = Point3::new(f32::MIN, f32::MIN, f32::MIN);
+    for position in triangles.iter().flat_map(|triangle| triangle.positions.iter()) {
+        min = min.inf(position);
+        max = max.sup(position);
+    }
+    let center = nalgebra::center(&min, &max);
+    let radius = triangles
+        .iter()
+        .flat_map(|triangle| triangle.positions.iter())
+        .map(|position| (position - center).norm())
+        .fold(0.0, f32::max);
+
+    let views = impostor.views.clamp(1, MAX_VIEWS) as usize;
+    let (columns, rows, atlas_views): (usize, usize, Vec<AtlasView>) = match impostor.kind {
+        ImpostorKind::Crossed => {
+            let elevations = impostor.elevations.clamp(1, MAX_VIEWS) as usize;
+            let atlas_views = (0..elevations)
+                .flat_map(|row| (0..views).map(move |column| (row, column)))
+                .map(|(row, column)| {
+                    let azimuth = 2.0 * PI * column as f32 / views as f32;
+                    let elevation = 0.5 * PI * row as f32 / elevations as f32;
+                    let direction = Vector3::new(elevation.cos() * azimuth.cos(), elevation.cos() * azimuth.sin(), elevation.sin());
+                    AtlasView { camera: ViewCamera::looking_from(center, direction, radius), column, row }
+                })
+                .collect();
+            (views, elevations, atlas_views)
+        }
+        ImpostorKind::Octahedral => {
+            let atlas_views = (0..views)
+                .flat_map(|row| (0..views).map(move |column| (row, column)))
+                .map(|(row, column)| {
+                    let direction = hemi_octahedral_direction(column, row, views);
+                    AtlasView { camera: ViewCamera::looking_from(center, direction, radius), column, row }
+                })
+                .collect();
+            (views, views, atlas_views)
+        }
+    };
+
+    // Render the views into their tiles of the atlas
+    let tile = (impostor.resolution.clamp(8, MAX_RESOLUTION) as usize).min(MAX_ATLAS_SIZE / columns.max(rows));
+    if tile != impostor.resolution as usize || impostor.views > MAX_VIEWS || impostor.elevations > MAX_VIEWS {
+        println!(
+            "The impostor atlas is limited to {} views and {} pixels per side, rendering {}x{} views of {} pixels",
+            MAX_VIEWS, MAX_ATLAS_SIZE, columns, rows, tile
+        );
+    }
+    let (width, height) = (columns * tile, rows * tile);
+    let mut albedo = vec![0u8; width * height * 4];
+    let mut normal = vec![0u8; width * height * 3];
+    for view in &atlas_views {
+        let rendered = render_view(&triangles, &view.camera, tile, SUPERSAMPLING);
+        copy_tile(&rendered, &mut albedo, &mut normal, width, view.column * tile, view.row * tile);
+    }
+    println!("Rendered {} impostor views into a {}x{} atlas", atlas_views.len(), width, height);
+
+    let albedo_texture = add_png_texture(document, "ImpostorAlbedo", &encode_png(width, height, png::ColorType::Rgba, &albedo)?);
+    let normal_texture = add_png_texture(document, "ImpostorNormal", &encode_png(width, height, png::ColorType::Rgb, &normal)?);
+    let material = document.push("materials", json!({
+        "name": "Impostor",
+        "pbrMetallicRoughness": {
+            "baseColorTexture": { "index": albedo_texture },
+            "metallicFactor": 0.0,
+            "roughnessFactor": 1.0,
+        },
+        "normalTexture": { "index": normal_texture },
+        "alphaMode": "MASK",
+        "alphaCutoff": 0.5,
+    }));
+
+    // Crossed impostors get a quad per horizontal view, octahedral ones a single quad that a
+    // shader turns towards the camera, showing the view towards +X until then
+    let (quads, extras): (Vec<&AtlasView>, Value) = match impostor.kind {
+        ImpostorKind::Crossed => (
+            atlas_views.iter().filter(|view| view.row == 0).collect(),
+            json!({ "type": "crossed", "views": views, "elevations": rows }),
+        ),
+        ImpostorKind::Octahedral => {
+            let (column, row) = hemi_octahedral_tile(&Vector3::x(), views);
+            (
+                atlas_views.iter().filter(|view| view.column == column && view.row == row).collect(),
+                json!({ "type": "octahedral", "frames": views, "hemisphere": true }),
+            )
+        }
+    };
+    let mesh = add_quads_mesh(document, &quads, columns, rows, material, json!({
+        "impostor": {
+            "center": [center.x, center.y, center.z],
+            "radius": radius,
+            "layout": extras,
+        }
+    }));
+
+    let node = document.push("nodes", json!({ "name": "Impostor", "mesh": mesh }));
+    if let Some(root) = document.json["scenes"][0]["nodes"][0].as_u64() {
+        document.json["nodes"][root as usize]["extensions"][LOD_EXTENSION] = json!({ "ids": [node] });
+    }
+    document.use_extension(LOD_EXTENSION);
+    Ok(())
+}
+
+/// The branches and leaves of the tree as triangles in the tree's frame, roots are underground
+fn tree_triangles(tree: &Tree, config: &TreeConfig, leaves: &[LeafInstance]) -> Vec<RasterTriangle> {
+    let mut triangles = Vec::new();
+    let bark = [TRUNK_COLOR[0], TRUNK_COLOR[1], TRUNK_COLOR[2]];
+    for (index, branch) in tree.branches.iter().enumerate() {
+        if branch.kind != BranchKind::Branch {
+            continue;
+        }
+        let (vertices, indices, normals, _) = tree.world_branch_mesh(index);
+        for triangle in &indices {
+            let corners = [triangle.a as usize, triangle.b as usize, triangle.c as usize];
+            triangles.push(RasterTriangle {
+                positions: corners.map(|i| vertices[i]),
+                normals: corners.map(|i| normals[i]),
+                color: bark,
+            });
+        }
+    }
+
+    if let Some(leaves_config) = &config.leaves {
+        let (vertices, indices, normals, _) = leaf_prototype(leaves_config);
+        let color = [leaves_config.color[0], leaves_config.color[1], leaves_config.color[2]];
+        for leaf in leaves {
+            let [x, y, z, w] = leaf.rotation;
+            let rotation = UnitQuaternion::from_quaternion(Quaternion::new(w, x, y, z));
+            let translation = Vector3::from(leaf.translation);
+            let scale = Vector3::from(leaf.scale);
+            for triangle in &indices {
+                let corners = [triangle.a as usize, triangle.b as usize, triangle.c as usize];
+                triangles.push(RasterTriangle {
+                    positions: corners.map(|i| rotation * Point3::from(vertices[i].coords.component_mul(&scale)) + translation),
+                    normals: corners.map(|i| rotation * normals[i]),
+                    color,
+                });
+            }
+        }
+    }
+
+    triangles
+}
+
+/// Copy a rendered view into its tile of the atlas images
+fn copy_tile(view: &RenderedView, albedo: &mut [u8], normal: &mut [u8], width: usize, left: usize, top: usize) {
+    for y in 0..view.size {
+        for x in 0..view.size {
+            let source = y * view.size + x;
+            let target = (top + y) * width + left + x;
+            albedo[target * 4..target * 4 + 4].copy_from_slice(&view.albedo[source]);
+            normal[target * 3..target * 3 + 3].copy_from_slice(&view.normal[source]);
+        }
+    }
+}
+
+/// Encode 8-bit pixels, with the rows from top to bottom, as a PNG image
+fn encode_png(width: usize, height: usize, color: png::ColorType, pixels: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width as u32, height as u32);
+    encoder.set_color(color);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+    writer.finish()?;
+    Ok(png)
+}
+
+/// Embed a PNG image in the document and add a clamped texture sampling it
+fn add_png_texture(document: &mut GlbDocument, name: &str, png: &[u8]) -> usize {
+    let view = document.add_buffer_view(png, None);
+    let image = document.push("images", json!({ "name": name, "bufferView": view, "mimeType": "image/png" }));
+    let sampler = document.push("samplers", json!({
+        "magFilter": 9729, // Linear
+        "minFilter": 9987, // Linear mipmap linear
+        "wrapS": 33071,    // Clamp to edge
+        "wrapT": 33071,
+    }));
+    document.push("textures", json!({ "sampler": sampler, "source": image }))
+}
+
+/// Add a mesh with a quad per view, each facing its camera and mapped to the view's tile
+fn add_quads_mesh(document: &mut GlbDocument, views: &[&AtlasView], columns: usize, rows: usize, material: usize, extras: Value) -> usize {
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut tangents = Vec::new();
+    let mut uvs = Vec::new();
+    let mut indices: Vec<u16> = Vec::new();
+    for view in views {
+        let camera = &view.camera;
+        let base = (positions.len() / 3) as u16;
+        for (x, y) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+            let position = camera.center + (camera.right * x + camera.up * y) * camera.half_size;
+            positions.extend_from_slice(&[position.x, position.y, position.z]);
+            normals.extend_from_slice(&[camera.forward.x, camera.forward.y, camera.forward.z]);
+            // The bitangent (normal x tangent) is the image up direction
+            tangents.extend_from_slice(&[camera.right.x, camera.right.y, camera.right.z, 1.0]);
+            uvs.push((view.column as f32 + (x + 1.0) * 0.5) / columns as f32);
+            uvs.push((view.row as f32 + (1.0 - y) * 0.5) / rows as f32);
+        }
+        indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+
+    let position = document.add_float_accessor(&positions, 3, Some(TARGET_ARRAY_BUFFER));
+    let normal = document.add_float_accessor(&normals, 3, Some(TARGET_ARRAY_BUFFER));
+    let tangent = document.add_float_accessor(&tangents, 4, Some(TARGET_ARRAY_BUFFER));
+    let uv = document.add_float_accessor(&uvs, 2, Some(TARGET_ARRAY_BUFFER));
+    let indices = document.add_u16_accessor(&indices, 1, Some(TARGET_ELEMENT_ARRAY_BUFFER));
+    document.push("meshes", json!({
+        "name": "Impostor",
+        "primitives": [{
+            "attributes": {
+                "POSITION": position,
+                "NORMAL": normal,
+                "TANGENT": tangent,
+                "TEXCOORD_0": uv,
+            },
+            "indices": indices,
+            "material": material,
+        }],
+        "extras": extras,
+    }))
+}
+
+/// Direction of the view at the center of an atlas tile, with the tiles covering the upper
+/// hemisphere as an octahedron unfolded into a square
+fn hemi_octahedral_direction(column: usize, row: usize, frames: usize) -> Vector3<f32> {
+    let u = (column as f32 + 0.5) / frames as f32 * 2.0 - 1.0;
+    let v = (row as f32 + 0.5) / frames as f32 * 2.0 - 1.0;
+    let x = (u + v) * 0.5;
+    let y = (u - v) * 0.5;
+    Vector3::new(x, y, (1.0 - x.abs() - y.abs()).max(0.0)).normalize()
+}
+
+/// Tile of the hemi-octahedral atlas showing a direction
+fn hemi_octahedral_tile(direction: &Vector3<f32>, frames: usize) -> (usize, usize) {
+    let sum = direction.x.abs() + direction.y.abs() + direction.z.max(0.0);
+    let (x, y) = (direction.x / sum, direction.y / sum);
+    let tile = |value: f32| (((value + 1.0) * 0.5 * frames as f32) as usize).min(frames - 1);
+    (tile(x + y), tile(x - y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::trunk_tree_config;
+    use crate::tree::Branch;
+
+    /// Impostor of a bare straight trunk, in a document whose scene holds the tree's root node
+    fn impostor_document(impostor: ImpostorConfig) -> GlbDocument {
+        let tree = Tree { branches: vec![Branch::straight(0, None, None, 5)], ..Default::default() };
+        let config = trunk_tree_config(json!({
+            "length": 4.0, "startRadius": 0.1, "endRadius": 0.1, "lengthSegments": 4,
+            "radialSegments": 8, "angle": 0.0, "twist": 0.0, "gnarliness": 0.0, "children": 0
+        }));
+        let mut document = GlbDocument { json: json!({ "scenes": [{ "nodes": [0] }] }), bin: Vec::new() };
+        document.push("nodes", json!({ "name": "Tree" }));
+        add_impostor(&mut document, &tree, &config, &[], &impostor).unwrap();
+        document
+    }
+
+    /// Size of an embedded PNG image
+    fn image_size(document: &GlbDocument, image: usize) -> (u32, u32) {
+        let view = &document.json["bufferViews"][document.json["images"][image]["bufferView"].as_u64().unwrap() as usize];
+        let offset = view["byteOffset"].as_u64().unwrap() as usize;
+        let length = view["byteLength"].as_u64().unwrap() as usize;
+        let reader = png::Decoder::new(&document.bin[offset..offset + length]).read_info().unwrap();
+        (reader.info().width, reader.info().height)
+    }
+
+    #[test]
+    fn impostor_is_the_far_level_of_detail() {
+        let document = impostor_document(ImpostorConfig { kind: ImpostorKind::Crossed, views: 4, elevations: 2, resolution: 16 });
+        let json = &document.json;
+
+        let lod = json["nodes"][0]["extensions"][LOD_EXTENSION]["ids"].as_array().unwrap();
+        assert_eq!(lod.len(), 1);
+        let node = &json["nodes"][lod[0].as_u64().unwrap() as usize];
+        assert_eq!(node["name"], "Impostor");
+        assert!(json["extensionsUsed"].as_array().unwrap().contains(&json!(LOD_EXTENSION)));
+
+        // A quad per horizontal view, mapped into the 4x2 atlas of 16 pixel views
+        let primitive = &json["meshes"][node["mesh"].as_u64().unwrap() as usize]["primitives"][0];
+        let (positions, _) = document.read_float_accessor(primitive["attributes"]["POSITION"].as_u64().unwrap() as usize).unwrap();
+        assert_eq!(positions.len(), 4 * 4 * 3);
+        let (uvs, _) = document.read_float_accessor(primitive["attributes"]["TEXCOORD_0"].as_u64().unwrap() as usize).unwrap();
+        assert!(uvs.iter().all(|uv| (0.0..=1.0).contains(uv)));
+        assert!(uvs.chunks(2).all(|uv| uv[1] <= 0.5), "the quads show the bottom row of views");
+
+        let material = &json["materials"][primitive["material"].as_u64().unwrap() as usize];
+        assert_eq!(material["alphaMode"], "MASK");
+        assert_eq!(json["images"].as_array().unwrap().len(), 2);
+        assert_eq!(image_size(&document, 0), (64, 32));
+        assert_eq!(image_size(&document, 1), (64, 32));
+    }
+
+    #[test]
+    fn atlas_is_clamped() {
+        // Views and view size are clamped, then the views shrink to fit the largest atlas
+        let document = impostor_document(ImpostorConfig { kind: ImpostorKind::Crossed, views: 100, elevations: 1, resolution: 5000 });
+        let tile = MAX_ATLAS_SIZE as u32 / MAX_VIEWS;
+        assert_eq!(image_size(&document, 0), (MAX_VIEWS * tile, tile));
+        assert_eq!(document.json["meshes"][0]["extras"]["impostor"]["layout"]["views"], MAX_VIEWS);
+
+        let document = impostor_document(ImpostorConfig { kind: ImpostorKind::Octahedral, views: 0, elevations: 0, resolution: 1 });
+        assert_eq!(image_size(&document, 0), (8, 8));
+    }
+
+    #[test]
+    fn hemi_octahedral_tiles_round_trip() {
+        let frames = 8;
+        for row in 0..frames {
+            for column in 0..frames {
+                let direction = hemi_octahedral_direction(column, row, frames);
+                assert!(direction.z >= 0.0);
+                assert_eq!(hemi_octahedral_tile(&direction, frames), (column, row));
+            }
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use mesh_tools::Triangle;
+use nalgebra::{Point3, Quaternion, UnitQuaternion, Vector3};
+use rand::Rng;
+use serde_json::json;
+use std::f32::consts::PI;
+
+use crate::gltf::GlbDocument;
+use crate::tree::{BranchKind, MeshData, Tree};
+use crate::{Billboard, LeafShape, LeavesConfig};
+
+/// glTF extension placing copies of a mesh from per-instance transforms
+pub const GPU_INSTANCING: &str = "EXT_mesh_gpu_instancing";
+
+/// A copy of the prototype leaf
+#[derive(Debug, Clone, Copy)]
+pub struct LeafInstance {
+    /// Position of the leaf base
+    pub translation: [f32; 3],
+    /// Rotation of the leaf (x, y, z, w)
+    pub rotation: [f32; 4],
+    /// Scale of the unit-length prototype
+    pub scale: [f32; 3],
+}
+
+/// Instanced leaves added to the exported scene
+#[derive(Debug, Clone)]
+pub struct LeafNodes {
+    /// Index of the prototype leaf mesh
+    pub mesh: usize,
+    /// Each node instancing the prototype, with its copies in the node's frame
+    pub nodes: Vec<(usize, Vec<LeafInstance>)>,
+}
+
+/// Width of the prototype leaf quad, relative to its unit length
+pub fn leaf_width(shape: LeafShape) -> f32 {
+    match shape {
+        LeafShape::Broad => 0.5,
+        LeafShape::Needle => 0.1,
+    }
+}
+
+/// Mesh of the prototype leaf: a unit-length quad along +Z with its base at the origin, or two
+/// crossed quads for double billboards
+pub fn leaf_prototype(leaves: &LeavesConfig) -> MeshData {
+    let half_width = leaf_width(leaves.shape) * 0.5;
+    let planes: &[(Vector3<f32>, Vector3<f32>)] = match leaves.billboard {
+        Billboard::Single => &[(Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, -1.0, 0.0))],
+        Billboard::Double => &[
+            (Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, -1.0, 0.0)),
+            (Vector3::new(0.0, 1.0, 0.0), Vector3::new(1.0, 0.0, 0.0)),
+        ],
+    };
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    for (side, normal) in planes {
+        let base = vertices.len() as u32;
+        for (u, v) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+            vertices.push(Point3::from(side * (u - 0.5) * 2.0 * half_width + Vector3::z() * v));
+            normals.push(*normal);
+            uvs.push([u, v]);
+        }
+        indices.push(Triangle::new(base, base + 1, base + 2));
+        indices.push(Triangle::new(base, base + 2, base + 3));
+    }
+
+    (vertices, indices, normals, uvs)
+}
+
+/// Scatter the leaves over the outermost branches, grouped by branch in each branch's frame
+///
+/// The leaves are spread by length over the part of each outermost branch past `start`, and
+/// point away from the branch at `angle` degrees around a random azimuth.
+pub fn place_leaves<R: Rng + ?Sized>(tree: &Tree, leaves: &LeavesConfig, rng: &mut R) -> Vec<(usize, Vec<LeafInstance>)> {
+    let mut has_children = vec![false; tree.branches.len()];
+    for branch in &tree.branches {
+        if let Some(parent) = branch.parent {
+            has_children[parent] = true;
+        }
+    }
+
+    // Points along each outermost branch with the distance from its base
+    let mut candidates = Vec::new();
+    let mut total_weight = 0.0;
+    for (index, branch) in tree.branches.iter().enumerate() {
+        if branch.kind != BranchKind::Branch || has_children[index] || branch.transforms.len() < 2 {
+            continue;
+        }
+        let points: Vec<Point3<f32>> = branch.transforms.iter().map(|transform| transform.point()).collect();
+        let mut distances = vec![0.0];
+        for pair in points.windows(2) {
+            distances.push(distances[distances.len() - 1] + (pair[1] - pair[0]).norm());
+        }
+        let length = distances[distances.len() - 1];
+        if length <= f32::EPSILON {
+            continue;
+        }
+        total_weight += length * (1.0 - leaves.start);
+        candidates.push((index, points, distances, total_weight));
+    }
+
+    let mut placed: Vec<(usize, Vec<LeafInstance>)> = candidates.iter().map(|(index, ..)| (*index, Vec::new())).collect();
+    if total_weight <= 0.0 {
+        return placed;
+    }
+
+    for _ in 0..leaves.count {
+        let weight = rng.gen_range(0.0..total_weight);
+        let slot = candidates.partition_point(|candidate| candidate.3 <= weight).min(candidates.len() - 1);
+        let (_, points, distances, _) = &candidates[slot];
+
+        // Point and tangent at the sampled distance along the branch
+        let length = distances[distances.len() - 1];
+        let distance = length * (leaves.start + (1.0 - leaves.start) * rng.gen_range(0.0..=1.0));
+        let segment = distances.partition_point(|d| *d < distance).clamp(1, points.len() - 1) - 1;
+        let segment_length = distances[segment + 1] - distances[segment];
+        let t = if segment_length > f32::EPSILON { (distance - distances[segment]) / segment_length } else { 0.0 };
+        let position = points[segment] + (points[segment + 1] - points[segment]) * t;
+        let tangent = (points[segment + 1] - points[segment]).try_normalize(1.0e-9).unwrap_or_else(Vector3::z);
+
+        let along = UnitQuaternion::rotation_between(&Vector3::z(), &tangent)
+            .unwrap_or_else(|| UnitQuaternion::from_axis_angle(&Vector3::x_axis(), PI));
+        let azimuth = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), rng.gen_range(0.0..2.0 * PI));
+        let tilt = UnitQuaternion::from_axis_angle(&Vector3::x_axis(), leaves.angle.to_radians());
+        let rotation = (along * azimuth * tilt).into_inner();
+        let scale = leaves.size * (1.0 + rng.gen_range(-leaves.size_variance..=leaves.size_variance));
+
+        placed[slot].1.push(LeafInstance {
+            translation: position.into(),
+            rotation: [rotation.i, rotation.j, rotation.k, rotation.w],
+            scale: [scale; 3],
+        });
+    }
+
+    placed.retain(|(_, instances)| !instances.is_empty());
+    placed
+}
+
+/// Move leaves grouped by branch into the tree's frame
+pub fn leaves_in_tree_frame(tree: &Tree, placed: &[(usize, Vec<LeafInstance>)]) -> Vec<LeafInstance> {
+    placed
+        .iter()
+        .flat_map(|(branch, instances)| {
+            let world = tree.world_transform(*branch);
+            instances.iter().map(move |instance| {
+                let [x, y, z, w] = instance.rotation;
+                let rotation = world.rotation * UnitQuaternion::from_quaternion(Quaternion::new(w, x, y, z));
+                let rotation = rotation.into_inner();
+                LeafInstance {
+                    translation: (world * Point3::from(instance.translation)).into(),
+                    rotation: [rotation.i, rotation.j, rotation.k, rotation.w],
+                    scale: instance.scale,
+                }
+            })
+        })
+        .collect()
+}
+
+/// Write the leaf copies as `EXT_mesh_gpu_instancing` attributes of their nodes
+///
+/// The leaf material is made double sided since the billboards are single quads. Viewers without
+/// the extension show one leaf per node. Without any leaf the document is left as is.
+pub fn add_leaf_instances(document: &mut GlbDocument, leaves: &LeafNodes) {
+    if leaves.nodes.is_empty() {
+        return;
+    }
+
+    for (node, instances) in &leaves.nodes {
+        let translations: Vec<f32> = instances.iter().flat_map(|instance| instance.translation).collect();
+        let rotations: Vec<f32> = instances.iter().flat_map(|instance| instance.rotation).collect();
+        let scales: Vec<f32> = instances.iter().flat_map(|instance| instance.scale).collect();
+
+        let translation = document.add_float_accessor(&translations, 3, None);
+        let rotation = document.add_float_accessor(&rotations, 4, None);
+        let scale = document.add_float_accessor(&scales, 3, None);
+        document.json["nodes"][*node]["extensions"][GPU_INSTANCING] = json!({
+            "attributes": {
+                "TRANSLATION": translation,
+                "ROTATION": rotation,
+                "SCALE": scale,
+            }
+        });
+    }
+
+    if let Some(material) = document.json["meshes"][leaves.mesh]["primitives"][0]["material"].as_u64() {
+        document.json["materials"][material as usize]["doubleSided"] = json!(true);
+    }
+
+    document.use_extension(GPU_INSTANCING);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instancing_is_only_used_with_leaves() {
+        let mut document = GlbDocument { json: json!({ "nodes": [{}] }), bin: Vec::new() };
+        add_leaf_instances(&mut document, &LeafNodes { mesh: 0, nodes: Vec::new() });
+        assert!(document.json["extensionsUsed"].is_null());
+
+        let leaf = LeafInstance { translation: [0.0; 3], rotation: [0.0, 0.0, 0.0, 1.0], scale: [1.0; 3] };
+        add_leaf_instances(&mut document, &LeafNodes { mesh: 0, nodes: vec![(0, vec![leaf])] });
+        assert_eq!(document.json["extensionsUsed"], json!([GPU_INSTANCING]));
+        assert!(document.json["nodes"][0]["extensions"][GPU_INSTANCING]["attributes"]["TRANSLATION"].is_u64());
+    }
+}
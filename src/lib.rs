@@ -1,11 +1,403 @@
 // Public modules
 pub mod tree;
 pub mod config;
+pub mod gltf;
+pub mod wind;
+pub mod skin;
+pub mod growth;
+pub mod format;
+pub mod obj;
+pub mod usd;
+pub mod ply;
+pub mod stl;
+pub mod weld;
+pub mod skeleton;
+pub mod qsm;
+pub mod forest;
+pub mod leaves;
+pub mod raster;
+pub mod impostor;
+pub mod collision;
+pub mod budget;
+pub mod stats;
+pub mod bounds;
+pub mod orientation;
+pub mod sag;
 
+use rand::Rng;
+
+/// Configuration of a whole tree: its branches, roots and animation
+#[derive(Debug, Clone)]
+pub struct TreeConfig {
+    /// Trunk configuration (root branch)
+    pub trunk: BranchConfig,
+    /// Roots grown below the trunk
+    pub roots: Option<RootsConfig>,
+    /// Wind animation written to the exported file
+    pub wind: Option<WindConfig>,
+    /// Skeleton the exported mesh is skinned to
+    pub skin: Option<SkinConfig>,
+    /// Export the tree at a younger age or as a growth sequence
+    pub growth: Option<GrowthConfig>,
+    /// Fuse the branches into a single solid for PLY and STL output
+    pub weld: Option<WeldConfig>,
+    /// Leaves instanced along the tips of the branches
+    pub leaves: Option<LeavesConfig>,
+    /// Impostor rendered as the far level of detail
+    pub impostor: Option<ImpostorConfig>,
+    /// Collision proxies derived from the branch skeleton
+    pub collision: Option<CollisionConfig>,
+    /// Only mesh the rings needed to follow the bends of the branches
+    pub adaptive_segments: Option<AdaptiveSegmentsConfig>,
+    /// Placement of the exported tree
+    pub export: Option<ExportConfig>,
+    /// Branches bending under their own weight
+    pub sag: Option<SagConfig>,
+}
+
+/// A numeric parameter that is sampled per branch as `value ± variance`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Param {
+    pub value: f32,
+    pub variance: f32,
+}
+
+impl Param {
+    /// Create a parameter without any variance
+    pub fn fixed(value: f32) -> Self {
+        Self { value, variance: 0.0 }
+    }
+
+    /// Sample a value uniformly from `[value - variance, value + variance]`
+    ///
+    /// Fixed parameters don't consume any randomness, so configs without variance
+    /// produce the same trees for a given seed as before.
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f32 {
+        if self.variance.abs() < f32::EPSILON {
+            return self.value;
+        }
+        let variance = self.variance.abs();
+        rng.gen_range((self.value - variance)..=(self.value + variance))
+    }
+
+    /// Sample a value that can't go below zero (lengths, radii, gnarliness)
+    pub fn sample_non_negative<R: Rng + ?Sized>(&self, rng: &mut R) -> f32 {
+        self.sample(rng).max(0.0)
+    }
+
+    /// Sample a value and round it to a non-negative integer (segment and children counts)
+    pub fn sample_u32<R: Rng + ?Sized>(&self, rng: &mut R) -> u32 {
+        self.sample(rng).round().max(0.0) as u32
+    }
+}
+
+impl From<f32> for Param {
+    fn from(value: f32) -> Self {
+        Self::fixed(value)
+    }
+}
 
 /// Configuration for tree generation
 #[derive(Debug, Clone)]
 pub struct BranchConfig {
+    pub length: Param,
+    pub start_radius: Param,
+    pub end_radius: Param,
+    pub length_segments: Param,
+    pub radial_segments: Param,
+    pub angle: Param,
+    pub twist: Param,
+    pub gnarliness: Param,
+    pub min_rotation: Param,
+    pub max_rotation: Param,
+    pub children: Vec<ChildGroup>,
+    pub splits: Param,
+    pub split_angle: Param,
+    pub split_pos_pct: Param,
+    pub leader: Option<LeaderConfig>,
+}
+
+impl BranchConfig {
+    /// Sample the concrete values used to build a single branch
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> BranchParams {
+        BranchParams {
+            length: self.length.sample_non_negative(rng),
+            start_radius: self.start_radius.sample_non_negative(rng),
+            end_radius: self.end_radius.sample_non_negative(rng),
+            length_segments: self.length_segments.sample_u32(rng),
+            radial_segments: self.radial_segments.sample_u32(rng),
+            angle: self.angle.sample(rng),
+            twist: self.twist.sample(rng),
+            gnarliness: self.gnarliness.sample_non_negative(rng),
+            min_rotation: self.min_rotation.sample(rng),
+            max_rotation: self.max_rotation.sample(rng),
+            splits: self.splits.sample_u32(rng),
+            split_angle: self.split_angle.sample(rng),
+            split_pos_pct: self.split_pos_pct.sample(rng),
+        }
+    }
+}
+
+/// A group of child branches sharing one config and one placement range along the parent
+#[derive(Debug, Clone)]
+pub struct ChildGroup {
+    /// Number of children in the group
+    pub count: Param,
+    /// Configuration of every child in the group
+    pub config: BranchConfig,
+    /// Minimum percentage position along the parent where the children can appear (0-100)
+    pub min_branch_pos_pct: Param,
+    /// Maximum percentage position along the parent where the children can appear (0-100)
+    pub max_branch_pos_pct: Param,
+}
+
+/// A leader that continues the branch past its tip along the final tangent
+#[derive(Debug, Clone)]
+pub struct LeaderConfig {
+    /// Length of the leader relative to the length of the branch it continues
+    pub length_ratio: Param,
+}
+
+/// A constant force bending branches while they grow
+#[derive(Debug, Clone, Copy)]
+pub struct Force {
+    /// Direction the branches are pulled towards
+    pub direction: [f32; 3],
+    /// Strength of the pull per unit of branch length (0.0-1.0)
+    pub strength: f32,
+}
+
+/// Configuration for the roots grown below the trunk
+#[derive(Debug, Clone)]
+pub struct RootsConfig {
+    /// Number of main roots around the trunk base
+    pub count: Param,
+    /// Configuration of each main root, its children describe the finer roots
+    pub config: BranchConfig,
+    /// Exponent of the radius falloff along each root (1.0 = linear)
+    pub taper: f32,
+    /// Force pulling the roots down while they grow
+    pub force: Force,
+    /// Color of the roots material (RGBA)
+    pub color: [f32; 4],
+    /// Height of the ground plane, root geometry above it is clipped
+    pub ground_clip: Option<f32>,
+}
+
+/// Configuration of the looping wind animation
+#[derive(Debug, Clone, Copy)]
+pub struct WindConfig {
+    /// Length of the animation loop in seconds
+    pub duration: f32,
+    /// Direction the wind blows towards
+    pub direction: [f32; 3],
+    /// Sway amplitude of the trunk in degrees, thinner and higher level branches sway more
+    pub strength: f32,
+    /// Sway frequency of the trunk in Hz, rounded so the loop is seamless
+    pub frequency: f32,
+    /// How the wind is exported
+    pub mode: WindMode,
+}
+
+/// How the wind is exported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindMode {
+    /// Animation channels rotating every branch node
+    Nodes,
+    /// Branches merged into one mesh with `_WIND_*` vertex attributes for wind shaders
+    Vertex,
+}
+
+/// Configuration of the skinned skeleton export
+#[derive(Debug, Clone, Copy)]
+pub struct SkinConfig {
+    /// Deepest branch level that gets its own bone chain, deeper branches follow their ancestor's bone
+    pub depth: u32,
+}
+
+/// Configuration of the growth export
+#[derive(Debug, Clone, Copy)]
+pub struct GrowthConfig {
+    /// Age of the exported tree (0.0 = seed, 1.0 = fully grown)
+    pub age: f32,
+    /// Age at the end of the growth sequence, a single tree is exported when unset
+    pub end_age: Option<f32>,
+    /// Number of files in the growth sequence
+    pub frames: u32,
+}
+
+/// Configuration of the welded solid written for 3D printing
+#[derive(Debug, Clone, Copy)]
+pub struct WeldConfig {
+    /// Number of grid cells along the longest side of the tree
+    pub resolution: u32,
+    /// Minimum thickness of every branch, two grid cells when unset
+    pub min_thickness: Option<f32>,
+}
+
+/// Configuration of the gravity sag, bending the branches under their own weight and the weight
+/// they carry
+#[derive(Debug, Clone, Copy)]
+pub struct SagConfig {
+    /// Young's modulus of the wood in GPa, lower values droop more
+    pub stiffness: f32,
+    /// Density of the wood in kg/m³
+    pub density: f32,
+}
+
+/// Placement and axis convention of the exported tree
+#[derive(Debug, Clone, Copy)]
+pub struct ExportConfig {
+    /// Point of the tree put at the origin of the scene
+    pub pivot: Pivot,
+    /// Axis pointing up in the exported file
+    pub up_axis: UpAxis,
+    /// Handedness of the exported coordinates
+    pub handedness: Handedness,
+    /// Unit of the exported lengths
+    pub units: Units,
+}
+
+/// Point of the tree put at the origin of the scene
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pivot {
+    /// Keep the origin of the tree's frame
+    Origin,
+    /// The base of the trunk, for skeletons not grown at the origin
+    TrunkBase,
+}
+
+/// Axis pointing up in the exported file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpAxis {
+    /// The generator's own +Z up
+    Z,
+    /// +Y up, as glTF and most game engines expect
+    Y,
+}
+
+/// Handedness of the exported coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Handedness {
+    /// Right-handed, as generated
+    Right,
+    /// Left-handed, mirrored along X
+    Left,
+}
+
+/// Unit of the exported lengths, the generator's being meters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Units {
+    Meters,
+    Centimeters,
+    Inches,
+}
+
+impl Units {
+    /// Number of these units in a meter
+    pub fn per_meter(&self) -> f32 {
+        match self {
+            Units::Meters => 1.0,
+            Units::Centimeters => 100.0,
+            Units::Inches => 1.0 / 0.0254,
+        }
+    }
+}
+
+/// Configuration of the adaptive length segmentation
+///
+/// The branches still grow `length_segments` transforms, but a ring is only meshed where the
+/// branch has turned by more than `angle` since the previous meshed ring.
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveSegmentsConfig {
+    /// Largest bend between two meshed rings, in degrees
+    pub angle: f32,
+}
+
+/// Configuration of the leaves, written as instances of one prototype mesh
+#[derive(Debug, Clone, Copy)]
+pub struct LeavesConfig {
+    /// Shape of the prototype leaf
+    pub shape: LeafShape,
+    /// Number of quads in the prototype leaf
+    pub billboard: Billboard,
+    /// Angle between each leaf and its branch in degrees
+    pub angle: f32,
+    /// Total number of leaves on the tree
+    pub count: u32,
+    /// Where the leaves start along the outermost branches (0.0 = base, 1.0 = tip)
+    pub start: f32,
+    /// Length of a leaf
+    pub size: f32,
+    /// Relative variation of the leaf size (0.0-1.0)
+    pub size_variance: f32,
+    /// Color of the leaves material (RGBA)
+    pub color: [f32; 4],
+}
+
+/// Shape of the prototype leaf
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeafShape {
+    /// Broad leaf, half as wide as it is long
+    Broad,
+    /// Needle, a tenth as wide as it is long
+    Needle,
+}
+
+/// Number of quads in the prototype leaf
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Billboard {
+    /// One quad
+    Single,
+    /// Two crossed quads, so the leaf has volume from every side
+    Double,
+}
+
+/// Configuration of the impostor rendered as the far level of detail
+#[derive(Debug, Clone, Copy)]
+pub struct ImpostorConfig {
+    /// Layout of the views and shape of the impostor mesh
+    pub kind: ImpostorKind,
+    /// Number of azimuths, or of frames along each side of an octahedral atlas
+    pub views: u32,
+    /// Number of rows of views from the horizon up, for crossed impostors
+    pub elevations: u32,
+    /// Size of each view in the atlas in pixels
+    pub resolution: u32,
+}
+
+/// Layout of the impostor views
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImpostorKind {
+    /// Views around the tree, with a quad through the trunk facing each horizontal view
+    Crossed,
+    /// Views over the upper hemisphere in an octahedral grid, for impostor shaders
+    Octahedral,
+}
+
+/// Configuration of the compound collider derived from the branch skeleton
+#[derive(Debug, Clone, Copy)]
+pub struct CollisionConfig {
+    /// Shape of every collider
+    pub shape: ColliderShape,
+    /// Thinnest branch part that still gets a collider
+    pub min_radius: f32,
+    /// Number of branch segments covered by each collider
+    pub segments: u32,
+}
+
+/// Shape of the colliders
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColliderShape {
+    /// Tapered capsule, rounded past the ends of its segments
+    Capsule,
+    /// Tapered cylinder with flat caps
+    Cylinder,
+}
+
+/// Concrete values of a BranchConfig, sampled for one branch
+#[derive(Debug, Clone)]
+pub struct BranchParams {
     pub length: f32,
     pub start_radius: f32,
     pub end_radius: f32,
@@ -16,8 +408,33 @@ pub struct BranchConfig {
     pub gnarliness: f32,
     pub min_rotation: f32,
     pub max_rotation: f32,
-    pub min_branch_pos_pct: f32,
-    pub max_branch_pos_pct: f32,
-    pub children: u32,
-    pub children_config: Option<Box<BranchConfig>>,
-}
\ No newline at end of file
+    pub splits: u32,
+    pub split_angle: f32,
+    pub split_pos_pct: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn non_negative_samples_stay_at_or_above_zero() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let param = Param { value: 0.1, variance: 0.5 };
+        let samples: Vec<f32> = (0..1000).map(|_| param.sample_non_negative(&mut rng)).collect();
+        assert!(samples.iter().all(|sample| *sample >= 0.0));
+        assert!(samples.contains(&0.0));
+        assert!(samples.iter().any(|sample| *sample > 0.5));
+    }
+
+    #[test]
+    fn fixed_params_keep_their_value() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        assert_eq!(Param::fixed(2.5).sample_non_negative(&mut rng), 2.5);
+        assert_eq!(Param::fixed(-1.0).sample(&mut rng), -1.0);
+        assert_eq!(Param::fixed(-1.0).sample_non_negative(&mut rng), 0.0);
+        assert_eq!(Param::fixed(2.6).sample_u32(&mut rng), 3);
+    }
+}
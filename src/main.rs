@@ -1,42 +1,209 @@
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
 use std::error::Error;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 // Import from library interface
-use tree_maker::tree::generate_tree;
-use tree_maker::config::{read_config_from_file, get_branch_config};
+use tree_maker::tree::{generate_tree_with_format, mesh_tree};
+use tree_maker::config::{read_config_from_file, get_tree_config};
+use tree_maker::TreeConfig;
+use tree_maker::format::OutputFormat;
+use tree_maker::skeleton::read_skeleton;
+use tree_maker::forest::{generate_forest, ForestArea, ForestConfig};
+use tree_maker::budget::fit_triangle_budget;
+use tree_maker::stats::{print_stats, tree_stats, write_stats_json};
 
 /// A Rust library and CLI tool for generating 3D tree models
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    
     /// Path to the JSON configuration file
     #[arg(required = true)]
-    config_file: PathBuf,
+    config_file: Option<PathBuf>,
     
-    /// Output file path (default: tree.glb)
+    /// Output file path, the format is picked from the extension: .glb, .gltf, .obj, .usda, .ply, .stl or .json (default: tree.glb)
     #[arg(short, long)]
     output: Option<PathBuf>,
+    
+    /// Output format (glb, gltf, obj, usda, ply, stl, skeleton or skeleton-obj), overrides the extension of the output file
+    #[arg(short, long)]
+    format: Option<String>,
+    
+    /// Mesh this skeleton instead of growing one: a skeleton JSON, or a QSM cylinder list (TreeQSM, AdTree...)
+    #[arg(short, long)]
+    skeleton: Option<PathBuf>,
+    
+    /// Lower the segment counts, smallest branches first, until the predicted triangle count fits
+    #[arg(long, conflicts_with = "skeleton")]
+    max_triangles: Option<u64>,
+    
+    /// Also place fewer leaves when the branches can't be reduced enough to fit --max-triangles
+    #[arg(long, requires = "max_triangles")]
+    reduce_leaves: bool,
+    
+    /// Print statistics and metrics of the exported tree
+    #[arg(long)]
+    stats: bool,
+    
+    /// Write the statistics and metrics of the exported tree as JSON to this file
+    #[arg(long, value_name = "FILE")]
+    stats_json: Option<PathBuf>,
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let cli = Cli::parse();
+#[derive(Subcommand)]
+enum Command {
+    /// Scatter many varied trees grown from one config over an area
+    Forest(ForestArgs),
+}
 
+#[derive(Args)]
+struct ForestArgs {
+    /// Path to the JSON configuration file every tree is derived from
+    config_file: PathBuf,
+    
+    /// Number of trees (default: as many as fit at the spacing)
+    #[arg(short, long)]
+    count: Option<u32>,
+    
+    /// Trees per square unit, used when no count is given
+    #[arg(short, long)]
+    density: Option<f32>,
+    
+    /// Rectangle the trees are placed in, as "min_x,min_y,max_x,max_y"
+    #[arg(short, long, default_value = "-25,-25,25,25", allow_hyphen_values = true)]
+    area: String,
+    
+    /// Polygon the trees are placed in, as "x,y x,y x,y...", replaces the rectangle
+    #[arg(short, long, allow_hyphen_values = true)]
+    polygon: Option<String>,
+    
+    /// Minimum distance between two trees
+    #[arg(short, long, default_value_t = 5.0)]
+    spacing: f32,
+    
+    /// Relative variation of the branch parameters of each tree (0.1 = ±10%)
+    #[arg(short, long, default_value_t = 0.1)]
+    jitter: f32,
+    
+    /// Seed of the placement, every tree gets its own seed derived from it
+    #[arg(long, default_value_t = 123456)]
+    seed: u64,
+    
+    /// Output .glb file holding every tree, or a folder to write one file per tree and a manifest.json to
+    #[arg(short, long, default_value = "forest.glb")]
+    output: PathBuf,
+    
+    /// Format of the tree files written to a folder (glb, gltf, obj, usda, ply, stl, skeleton or skeleton-obj)
+    #[arg(short, long)]
+    format: Option<String>,
+}
+
+/// Read the tree configuration from a JSON file
+fn read_tree_config(config_file: &Path) -> Result<TreeConfig, Box<dyn Error>> {
     // Check if config file exists
-    if !cli.config_file.exists() {
-        return Err(format!("Config file not found: {}", cli.config_file.display()).into());
+    if !config_file.exists() {
+        return Err(format!("Config file not found: {}", config_file.display()).into());
     }
     
-    println!("Reading configuration from file: {}", cli.config_file.display());
+    println!("Reading configuration from file: {}", config_file.display());
     
     // Read and parse JSON configuration
-    let json_config = read_config_from_file(&cli.config_file)?;
+    let json_config = read_config_from_file(config_file)?;
     
-    // Get the branch configuration from JSON
-    let branch_config = get_branch_config(&json_config);
+    // Get the tree configuration from JSON
+    Ok(get_tree_config(&json_config))
+}
+
+/// Generate a forest from the forest subcommand arguments
+fn run_forest(args: ForestArgs) -> Result<(), Box<dyn Error>> {
+    let tree_config = read_tree_config(&args.config_file)?;
+    let area = match &args.polygon {
+        Some(polygon) => ForestArea::parse_polygon(polygon)?,
+        None => ForestArea::parse_rectangle(&args.area)?,
+    };
+    let forest = ForestConfig {
+        area,
+        count: args.count,
+        density: args.density,
+        spacing: args.spacing,
+        jitter: args.jitter,
+        seed: args.seed,
+    };
+    let format = args.format.as_deref().map(OutputFormat::from_extension).transpose()?;
+    generate_forest(&tree_config, &forest, &args.output, format)
+}
 
-    // Generate the tree
-    generate_tree(branch_config, Some(123456), cli.output.as_deref())?;
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    
+    let config_file = match cli.command {
+        Some(Command::Forest(args)) => return run_forest(args),
+        None => cli.config_file.ok_or("No configuration file given")?,
+    };
+    let mut tree_config = read_tree_config(&config_file)?;
+    if let Some(max_triangles) = cli.max_triangles {
+        tree_config = fit_triangle_budget(&tree_config, max_triangles, cli.reduce_leaves);
+    }
+
+    // An explicit format replaces the extension of the output file
+    let format = cli.format.as_deref().map(OutputFormat::from_extension).transpose()?;
+    let output = match format {
+        Some(format) => {
+            let output = cli.output.unwrap_or_else(|| PathBuf::from("tree"));
+            Some(output.with_extension(format.extension()))
+        }
+        None => cli.output,
+    };
+
+    // Mesh the imported skeleton, or generate the tree
+    let exported = match &cli.skeleton {
+        Some(skeleton_path) => {
+            println!("Reading skeleton from file: {}", skeleton_path.display());
+            let radial_segments = tree_config.trunk.radial_segments.value.round().max(3.0) as u32;
+            let tree = read_skeleton(skeleton_path, radial_segments)?;
+            mesh_tree(&tree, &tree_config, Some(123456), output.as_deref(), format)?
+        }
+        None => generate_tree_with_format(&tree_config, Some(123456), output.as_deref(), format)?,
+    };
+    
+    if cli.stats || cli.stats_json.is_some() {
+        let stats = tree_stats(&exported, &tree_config);
+        if cli.stats {
+            print_stats(&stats);
+        }
+        if let Some(stats_path) = &cli.stats_json {
+            write_stats_json(&stats, stats_path)?;
+            println!("Statistics saved to: {}", stats_path.display());
+        }
+    }
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn cli_is_consistent() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn stats_flag_keeps_the_config_file() {
+        let cli = Cli::try_parse_from(["tree-maker", "--stats", "tree.json"]).unwrap();
+        assert!(cli.stats);
+        assert_eq!(cli.config_file, Some(PathBuf::from("tree.json")));
+        assert_eq!(cli.stats_json, None);
+
+        let cli = Cli::try_parse_from(["tree-maker", "tree.json", "--stats-json", "stats.json"]).unwrap();
+        assert!(!cli.stats);
+        assert_eq!(cli.config_file, Some(PathBuf::from("tree.json")));
+        assert_eq!(cli.stats_json, Some(PathBuf::from("stats.json")));
+
+        assert!(Cli::try_parse_from(["tree-maker", "--stats-json", "stats.json"]).is_err());
+    }
+}
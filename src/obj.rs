@@ -0,0 +1,148 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::orientation::ExportFrame;
+use crate::tree::{BranchKind, Tree, TRUNK_COLOR};
+use crate::TreeConfig;
+
+/// Write the tree as a Wavefront OBJ file with an MTL material library next to it
+///
+/// Each branch is written as its own group, named like its glTF node and using the "Trunk" or
+/// "Roots" material. The export settings are baked into the vertices. Leaves are not written. The
+/// MTL file has the same name as the OBJ file.
+///
+/// # Arguments
+///
+/// * `tree` - Skeleton to mesh and write
+/// * `config` - Tree configuration, for the material colors and export settings
+/// * `output_path` - Path of the OBJ file
+pub fn write_obj(tree: &Tree, config: &TreeConfig, output_path: &Path) -> Result<(), Box<dyn Error>> {
+    let mtl_path = output_path.with_extension("mtl");
+    let mtl_name = mtl_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| format!("Invalid output path: {}", output_path.display()))?;
+
+    let mut obj = BufWriter::new(File::create(output_path)?);
+    writeln!(obj, "# Generated by tree-maker")?;
+    writeln!(obj, "mtllib {}", mtl_name)?;
+    writeln!(obj, "o Tree")?;
+
+    // OBJ indices are 1-based and shared by the whole file
+    let frame = ExportFrame::of_tree(tree, config.export.as_ref());
+    let names = tree.branch_names();
+    let mut offset = 1;
+    for (index, branch) in tree.branches.iter().enumerate() {
+        let (vertices, indices, normals, uvs) = frame.mesh(tree.world_branch_mesh(index));
+        if vertices.is_empty() {
+            continue;
+        }
+
+        writeln!(obj, "g {}", names[index])?;
+        let material = match branch.kind {
+            BranchKind::Root if config.roots.is_some() => "Roots",
+            _ => "Trunk",
+        };
+        writeln!(obj, "usemtl {}", material)?;
+
+        for vertex in &vertices {
+            writeln!(obj, "v {} {} {}", vertex.x, vertex.y, vertex.z)?;
+        }
+        for uv in &uvs {
+            writeln!(obj, "vt {} {}", uv[0], uv[1])?;
+        }
+        for normal in &normals {
+            writeln!(obj, "vn {} {} {}", normal.x, normal.y, normal.z)?;
+        }
+        for triangle in &indices {
+            let (a, b, c) = (triangle.a + offset, triangle.b + offset, triangle.c + offset);
+            writeln!(obj, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+        }
+        offset += vertices.len() as u32;
+    }
+    obj.flush()?;
+
+    let mut mtl = BufWriter::new(File::create(&mtl_path)?);
+    writeln!(mtl, "# Generated by tree-maker")?;
+    write_material(&mut mtl, "Trunk", TRUNK_COLOR)?;
+    if let Some(roots) = &config.roots {
+        write_material(&mut mtl, "Roots", roots.color)?;
+    }
+    mtl.flush()?;
+
+    Ok(())
+}
+
+/// Write a diffuse material to an MTL file
+fn write_material(mtl: &mut impl Write, name: &str, color: [f32; 4]) -> Result<(), Box<dyn Error>> {
+    writeln!(mtl)?;
+    writeln!(mtl, "newmtl {}", name)?;
+    writeln!(mtl, "Ka 0 0 0")?;
+    writeln!(mtl, "Kd {} {} {}", color[0], color[1], color[2])?;
+    writeln!(mtl, "Ks 0 0 0")?;
+    writeln!(mtl, "d {}", color[3])?;
+    writeln!(mtl, "illum 1")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::trunk_tree_config;
+    use crate::tree::Branch;
+    use crate::{Billboard, LeafShape, LeavesConfig};
+    use serde_json::json;
+    use std::fs;
+
+    #[test]
+    fn branches_are_named_groups() {
+        // A trunk continued by a leader, with a side branch
+        let tree = Tree {
+            branches: vec![
+                Branch::straight(0, None, None, 3),
+                Branch::straight(0, Some(0), Some(2), 3),
+                Branch::straight(1, Some(0), Some(1), 3),
+            ],
+            ..Default::default()
+        };
+        let mut config = trunk_tree_config(json!({
+            "length": 2.0, "startRadius": 0.1, "endRadius": 0.1, "lengthSegments": 2,
+            "radialSegments": 8, "angle": 0.0, "twist": 0.0, "gnarliness": 0.0, "children": 0
+        }));
+        config.leaves = Some(LeavesConfig {
+            shape: LeafShape::Broad,
+            billboard: Billboard::Double,
+            angle: 30.0,
+            count: 20,
+            start: 0.5,
+            size: 0.1,
+            size_variance: 0.0,
+            color: [0.0, 1.0, 0.0, 1.0],
+        });
+
+        let path = std::env::temp_dir().join(format!("tree-maker-{}-groups.obj", std::process::id()));
+        write_obj(&tree, &config, &path).unwrap();
+        let obj = fs::read_to_string(&path).unwrap();
+        let mtl = fs::read_to_string(path.with_extension("mtl")).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(path.with_extension("mtl")).unwrap();
+
+        let groups: Vec<&str> = obj.lines().filter_map(|line| line.strip_prefix("g ")).collect();
+        assert_eq!(groups, tree.branch_names());
+        assert_eq!(groups, vec!["Trunk", "Trunk_leader", "Branch_L1_2"]);
+        assert!(obj.contains("mtllib tree-maker-"));
+        assert!(mtl.contains("newmtl Trunk") && !mtl.contains("Leaves"));
+
+        // The faces of every branch, and nothing for the leaves, index the vertices from 1
+        let vertices = obj.lines().filter(|line| line.starts_with("v ")).count();
+        let triangles: usize = (0..tree.branches.len()).map(|index| tree.branch_mesh(index).1.len()).sum();
+        let faces: Vec<&str> = obj.lines().filter(|line| line.starts_with("f ")).collect();
+        assert_eq!(faces.len(), triangles);
+        for corner in faces.iter().flat_map(|face| face.split_whitespace().skip(1)) {
+            let index: usize = corner.split('/').next().unwrap().parse().unwrap();
+            assert!((1..=vertices).contains(&index), "corner {}", corner);
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use nalgebra::{Point3, UnitQuaternion, Vector3};
+use serde_json::json;
+use std::f32::consts::FRAC_PI_2;
+
+use crate::gltf::GlbDocument;
+use crate::impostor::LOD_EXTENSION;
+use crate::tree::{BranchKind, MeshData, Tree};
+use crate::{ExportConfig, Handedness, Pivot, UpAxis};
+
+/// Rotation turning the generator's +Z up into glTF's +Y up: (x, y, z) becomes (x, z, -y)
+pub fn z_up_to_y_up() -> UnitQuaternion<f32> {
+    UnitQuaternion::from_axis_angle(&Vector3::x_axis(), -FRAC_PI_2)
+}
+
+/// Base of the trunk in the tree's frame, the origin for a tree without one
+pub fn trunk_base(tree: &Tree) -> Point3<f32> {
+    tree.branches
+        .iter()
+        .position(|branch| branch.kind == BranchKind::Branch && branch.parent.is_none())
+        .map_or(Point3::origin(), |trunk| tree.world_transform(trunk) * Point3::origin())
+}
+
+/// Transform from the tree's frame to the exported one, applied as scale, rotation, then
+/// translation like a glTF node
+///
+/// The scale holds the units and, for left-handed coordinates, the mirror along X. The rotation
+/// only turns about X, so X stays the same axis in both frames.
+#[derive(Debug, Clone, Copy)]
+pub struct ExportFrame {
+    pub translation: Vector3<f32>,
+    pub rotation: UnitQuaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl ExportFrame {
+    /// The frame of the export settings with a pivot point put at the origin, the tree's own frame
+    /// without settings
+    pub fn new(export: Option<&ExportConfig>, pivot: Point3<f32>) -> Self {
+        let Some(export) = export else {
+            return Self {
+                translation: Vector3::zeros(),
+                rotation: UnitQuaternion::identity(),
+                scale: Vector3::repeat(1.0),
+            };
+        };
+
+        let rotation = match export.up_axis {
+            UpAxis::Z => UnitQuaternion::identity(),
+            UpAxis::Y => z_up_to_y_up(),
+        };
+        let units = export.units.per_meter();
+        let mirror = match export.handedness {
+            Handedness::Right => 1.0,
+            Handedness::Left => -1.0,
+        };
+        let scale = Vector3::new(mirror * units, units, units);
+        Self {
+            translation: -(rotation * scale.component_mul(&pivot.coords)),
+            rotation,
+            scale,
+        }
+    }
+
+    /// The frame of the export settings for a tree, with its pivot
+    pub fn of_tree(tree: &Tree, export: Option<&ExportConfig>) -> Self {
+        let pivot = match export.map(|export| export.pivot) {
+            Some(Pivot::TrunkBase) => trunk_base(tree),
+            _ => Point3::origin(),
+        };
+        Self::new(export, pivot)
+    }
+
+    /// Whether the frame mirrors, which reverses the winding of the triangles
+    pub fn mirrored(&self) -> bool {
+        self.scale.x * self.scale.y * self.scale.z < 0.0
+    }
+
+    pub fn point(&self, point: &Point3<f32>) -> Point3<f32> {
+        Point3::from(self.translation + self.rotation * self.scale.component_mul(&point.coords))
+    }
+
+    pub fn normal(&self, normal: &Vector3<f32>) -> Vector3<f32> {
+        (self.rotation * normal.component_div(&self.scale)).normalize()
+    }
+
+    /// A length in the exported units
+    pub fn length(&self, length: f32) -> f32 {
+        length * self.scale.y.abs()
+    }
+
+    /// A mesh moved into the frame, its triangles turned back to face outwards when mirrored
+    pub fn mesh(&self, mesh: MeshData) -> MeshData {
+        let (vertices, mut indices, normals, uvs) = mesh;
+        if self.mirrored() {
+            indices.iter_mut().for_each(|triangle| std::mem::swap(&mut triangle.b, &mut triangle.c));
+        }
+        (
+            vertices.iter().map(|vertex| self.point(vertex)).collect(),
+            indices,
+            normals.iter().map(|normal| self.normal(normal)).collect(),
+            uvs,
+        )
+    }
+}
+
+/// Place the tree's root node (`scenes[0].nodes[0]`) in the exported frame: pivot at the origin,
+/// up axis, handedness and units
+///
+/// The levels of detail replacing the root node get the same transform. A mirroring scale is left
+/// to the viewer, which turns the triangles around as glTF requires.
+pub fn orient_root(document: &mut GlbDocument, tree: &Tree, export: &ExportConfig) {
+    let Some(root) = document.json["scenes"][0]["nodes"][0].as_u64().map(|root| root as usize) else { return };
+
+    let mut nodes = vec![root];
+    if let Some(ids) = document.json["nodes"][root]["extensions"][LOD_EXTENSION]["ids"].as_array() {
+        nodes.extend(ids.iter().filter_map(|id| id.as_u64()).map(|id| id as usize));
+    }
+    let frame = ExportFrame::of_tree(tree, Some(export));
+    for node in nodes {
+        set_node_frame(document, node, &frame);
+    }
+}
+
+/// Set a node's translation, rotation and scale to a frame
+pub fn set_node_frame(document: &mut GlbDocument, node: usize, frame: &ExportFrame) {
+    let (translation, quat, scale) = (frame.translation, frame.rotation.into_inner(), frame.scale);
+    document.json["nodes"][node]["translation"] = json!([translation.x, translation.y, translation.z]);
+    document.json["nodes"][node]["rotation"] = json!([quat.i, quat.j, quat.k, quat.w]);
+    if scale != Vector3::repeat(1.0) {
+        document.json["nodes"][node]["scale"] = json!([scale.x, scale.y, scale.z]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Units;
+    use mesh_tools::Triangle;
+
+    fn export(up_axis: UpAxis, handedness: Handedness, units: Units) -> ExportConfig {
+        ExportConfig { pivot: Pivot::Origin, up_axis, handedness, units }
+    }
+
+    fn assert_close(actual: Vector3<f32>, expected: Vector3<f32>) {
+        assert!((actual - expected).norm() < 1.0e-5, "{:?} instead of {:?}", actual, expected);
+    }
+
+    #[test]
+    fn points_and_normals_follow_the_axis_convention() {
+        let point = Point3::new(1.0, 2.0, 3.0);
+        let normal = Vector3::new(0.6, 0.8, 0.0);
+        let cases = [
+            (UpAxis::Z, Handedness::Right, [1.0, 2.0, 3.0], [0.6, 0.8, 0.0]),
+            (UpAxis::Y, Handedness::Right, [1.0, 3.0, -2.0], [0.6, 0.0, -0.8]),
+            (UpAxis::Z, Handedness::Left, [-1.0, 2.0, 3.0], [-0.6, 0.8, 0.0]),
+            (UpAxis::Y, Handedness::Left, [-1.0, 3.0, -2.0], [-0.6, 0.0, -0.8]),
+        ];
+        for (up_axis, handedness, expected_point, expected_normal) in cases {
+            let frame = ExportFrame::new(Some(&export(up_axis, handedness, Units::Meters)), Point3::origin());
+            assert_close(frame.point(&point).coords, Vector3::from(expected_point));
+            assert_close(frame.normal(&normal), Vector3::from(expected_normal));
+
+            // The pivot lands on the origin, in any units
+            let pivot = Point3::new(0.5, -1.0, 2.0);
+            let frame = ExportFrame::new(Some(&export(up_axis, handedness, Units::Centimeters)), pivot);
+            assert_close(frame.point(&pivot).coords, Vector3::zeros());
+        }
+    }
+
+    #[test]
+    fn winding_flips_exactly_when_mirrored() {
+        // A triangle facing +Z, wound counter-clockwise seen from its normal
+        let mesh = || -> MeshData {
+            (
+                vec![Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 0.0), Point3::new(0.0, 1.0, 0.0)],
+                vec![Triangle::new(0, 1, 2)],
+                vec![Vector3::z(); 3],
+                vec![[0.0, 0.0]; 3],
+            )
+        };
+        for up_axis in [UpAxis::Z, UpAxis::Y] {
+            for handedness in [Handedness::Right, Handedness::Left] {
+                let frame = ExportFrame::new(Some(&export(up_axis, handedness, Units::Inches)), Point3::origin());
+                assert_eq!(frame.mirrored(), handedness == Handedness::Left);
+
+                let (vertices, indices, normals, _) = frame.mesh(mesh());
+                let triangle = &indices[0];
+                assert_eq!((triangle.b, triangle.c) == (2, 1), frame.mirrored());
+
+                // The winding still faces the way of the normals
+                let [a, b, c] = [triangle.a, triangle.b, triangle.c].map(|i| vertices[i as usize]);
+                assert!((b - a).cross(&(c - a)).dot(&normals[0]) > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn lengths_are_scaled_to_the_units() {
+        for (units, expected) in [(Units::Meters, 2.0), (Units::Centimeters, 200.0), (Units::Inches, 2.0 / 0.0254)] {
+            for handedness in [Handedness::Right, Handedness::Left] {
+                let frame = ExportFrame::new(Some(&export(UpAxis::Y, handedness, units)), Point3::origin());
+                assert!((frame.length(2.0) - expected).abs() < 1.0e-3, "{:?}: {}", units, frame.length(2.0));
+            }
+        }
+        assert_eq!(ExportFrame::new(None, Point3::new(1.0, 1.0, 1.0)).length(2.0), 2.0);
+    }
+}
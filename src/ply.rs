@@ -0,0 +1,103 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::tree::MeshData;
+
+/// Write a mesh as a binary little-endian PLY file
+///
+/// Vertices carry their position and normal, and their UV (`s`, `t`) when the mesh has UVs.
+/// Faces are triangles.
+///
+/// # Arguments
+///
+/// * `mesh` - Mesh to write, in the tree's frame
+/// * `output_path` - Path of the PLY file
+pub fn write_ply(mesh: &MeshData, output_path: &Path) -> Result<(), Box<dyn Error>> {
+    let (vertices, indices, normals, uvs) = mesh;
+    let has_uvs = !uvs.is_empty();
+
+    let mut ply = BufWriter::new(File::create(output_path)?);
+    writeln!(ply, "ply")?;
+    writeln!(ply, "format binary_little_endian 1.0")?;
+    writeln!(ply, "comment Generated by tree-maker")?;
+    writeln!(ply, "element vertex {}", vertices.len())?;
+    for property in ["x", "y", "z", "nx", "ny", "nz"] {
+        writeln!(ply, "property float {}", property)?;
+    }
+    if has_uvs {
+        writeln!(ply, "property float s")?;
+        writeln!(ply, "property float t")?;
+    }
+    writeln!(ply, "element face {}", indices.len())?;
+    writeln!(ply, "property list uchar uint vertex_indices")?;
+    writeln!(ply, "end_header")?;
+
+    for (i, vertex) in vertices.iter().enumerate() {
+        let normal = normals.get(i).copied().unwrap_or_default();
+        for value in [vertex.x, vertex.y, vertex.z, normal.x, normal.y, normal.z] {
+            ply.write_all(&value.to_le_bytes())?;
+        }
+        if has_uvs {
+            let uv = uvs.get(i).copied().unwrap_or_default();
+            ply.write_all(&uv[0].to_le_bytes())?;
+            ply.write_all(&uv[1].to_le_bytes())?;
+        }
+    }
+    for triangle in indices {
+        ply.write_all(&[3u8])?;
+        for index in [triangle.a, triangle.b, triangle.c] {
+            ply.write_all(&index.to_le_bytes())?;
+        }
+    }
+
+    ply.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::{Branch, Tree};
+    use std::fs;
+
+    /// Little-endian float at a byte offset
+    fn float(bytes: &[u8], offset: usize) -> f32 {
+        f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn binary_layout_follows_the_header() {
+        let tree = Tree { branches: vec![Branch::straight(0, None, None, 3)], ..Default::default() };
+        let mesh = tree.branch_mesh(0);
+        let (vertices, indices, normals, uvs) = &mesh;
+        let path = std::env::temp_dir().join(format!("tree-maker-{}-layout.ply", std::process::id()));
+        write_ply(&mesh, &path).unwrap();
+        let ply = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let end = b"end_header\n";
+        let body = ply.windows(end.len()).position(|window| window == end).unwrap() + end.len();
+        let header = std::str::from_utf8(&ply[..body]).unwrap();
+        assert!(header.starts_with("ply\nformat binary_little_endian 1.0\n"));
+        assert!(header.contains(&format!("element vertex {}\n", vertices.len())));
+        assert!(header.contains(&format!("element face {}\n", indices.len())));
+        assert!(header.contains("property float s\nproperty float t\n"));
+
+        // x y z nx ny nz s t per vertex, then a count and three indices per face
+        let stride = 8 * 4;
+        let faces = body + vertices.len() * stride;
+        assert_eq!(ply.len(), faces + indices.len() * 13);
+        let last = vertices.len() - 1;
+        let vertex = body + last * stride;
+        assert_eq!(float(&ply, vertex + 8), vertices[last].z);
+        assert_eq!(float(&ply, vertex + 20), normals[last].z);
+        assert_eq!(float(&ply, vertex + 28), uvs[last][1]);
+        for (triangle, face) in indices.iter().zip(ply[faces..].chunks(13)) {
+            assert_eq!(face[0], 3);
+            let corners: Vec<u32> = face[1..].chunks(4).map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap())).collect();
+            assert_eq!(corners, vec![triangle.a, triangle.b, triangle.c]);
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use nalgebra::{Isometry3, Point3, Translation3, Unit, UnitQuaternion, Vector3};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::f32::consts::PI;
+use std::fs;
+use std::path::Path;
+
+use crate::tree::{Branch, BranchKind, BranchTransform, Tree};
+
+/// Column order of TreeQSM cylinder text files, used when the file has no header
+///
+/// The columns after `position_in_branch` (mad, surface coverage...) aren't used.
+const TREEQSM_COLUMNS: [&str; 13] = [
+    "radius", "length", "startx", "starty", "startz", "axisx", "axisy", "axisz", "parent", "extension", "branch",
+    "branchorder", "positioninbranch",
+];
+
+/// A cylinder of a quantitative structure model
+#[derive(Debug, Clone)]
+struct Cylinder {
+    radius: f32,
+    length: f32,
+    start: Point3<f32>,
+    axis: Unit<Vector3<f32>>,
+    /// Index of the parent cylinder
+    parent: Option<usize>,
+    /// Index of the cylinder continuing this one
+    extension: Option<usize>,
+    /// Id of the branch the cylinder belongs to
+    branch: Option<usize>,
+    /// Position of the cylinder along its branch
+    position_in_branch: Option<usize>,
+}
+
+impl Cylinder {
+    fn end(&self) -> Point3<f32> {
+        self.start + self.axis.into_inner() * self.length
+    }
+}
+
+/// Read a QSM cylinder list (TreeQSM, AdTree...) and build the branch hierarchy from it
+///
+/// The file has one cylinder per line, with values separated by commas, tabs or spaces. A header
+/// line names the columns: `radius`, `length`, `start_x`/`start_y`/`start_z`, `axis_x`/`axis_y`/`axis_z`
+/// and `parent`, optionally `extension`, `branch` and `position_in_branch`. Without a header the
+/// TreeQSM column order is assumed, ending with `branch`, `branch_order` and `position_in_branch`.
+/// Cylinder indices are 1-based with 0 for none, as in TreeQSM.
+///
+/// Cylinders of the same branch (or, without a branch column, each cylinder and its extension or
+/// first child) are chained into one tube, with rings at the cylinder ends.
+pub fn read_qsm_cylinders(path: &Path, radial_segments: u32) -> Result<Tree, Box<dyn Error>> {
+    let cylinders = parse_cylinders(&fs::read_to_string(path)?)?;
+    if cylinders.is_empty() {
+        return Err(format!("No cylinders in QSM file: {}", path.display()).into());
+    }
+    println!("Read {} cylinders from {}", cylinders.len(), path.display());
+
+    // The cylinder continuing each one along its branch
+    let mut next: Vec<Option<usize>> = vec![None; cylinders.len()];
+    if cylinders.iter().all(|cylinder| cylinder.branch.is_some()) {
+        let mut by_branch: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (index, cylinder) in cylinders.iter().enumerate() {
+            by_branch.entry(cylinder.branch.unwrap_or(0)).or_default().push(index);
+        }
+        for members in by_branch.values_mut() {
+            members.sort_by_key(|&index| cylinders[index].position_in_branch.unwrap_or(index));
+            for pair in members.windows(2) {
+                next[pair[0]] = Some(pair[1]);
+            }
+        }
+    } else {
+        for (index, cylinder) in cylinders.iter().enumerate() {
+            if let Some(parent) = cylinder.parent {
+                let continues = match cylinders[parent].extension {
+                    Some(extension) => extension == index,
+                    None => next[parent].is_none(),
+                };
+                if continues {
+                    next[parent] = Some(index);
+                }
+            }
+        }
+    }
+
+    // Chains of cylinders, each one becomes a branch
+    let mut is_continuation = vec![false; cylinders.len()];
+    for &following in next.iter().flatten() {
+        is_continuation[following] = true;
+    }
+    let mut chains: Vec<Vec<usize>> = Vec::new();
+    let mut chain_of = vec![(0, 0); cylinders.len()];
+    for start in (0..cylinders.len()).filter(|&index| !is_continuation[index]) {
+        let mut chain = Vec::new();
+        let mut current = Some(start);
+        while let Some(index) = current {
+            if chain.len() > cylinders.len() {
+                return Err("Cycle in the QSM cylinder chains".into());
+            }
+            chain_of[index] = (chains.len(), chain.len());
+            chain.push(index);
+            current = next[index];
+        }
+        chains.push(chain);
+    }
+
+    // Order the chains so parents come before their children
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); chains.len()];
+    let mut queue = VecDeque::new();
+    for (chain_index, chain) in chains.iter().enumerate() {
+        match cylinders[chain[0]].parent {
+            Some(parent) => children[chain_of[parent].0].push(chain_index),
+            None => queue.push_back(chain_index),
+        }
+    }
+
+    let mut tree = Tree::default();
+    let mut branch_of_chain = vec![None; chains.len()];
+    let mut worlds: Vec<Isometry3<f32>> = Vec::new();
+    while let Some(chain_index) = queue.pop_front() {
+        let chain = &chains[chain_index];
+        let first = &cylinders[chain[0]];
+
+        // Parent branch and the ring at the end of the parent cylinder
+        let parent = first.parent.and_then(|parent| {
+            let (parent_chain, position) = chain_of[parent];
+            branch_of_chain[parent_chain].map(|branch: usize| (branch, position + 1))
+        });
+
+        let mut points = vec![first.start];
+        let mut radii = vec![first.radius];
+        for (i, &index) in chain.iter().enumerate() {
+            let cylinder = &cylinders[index];
+            points.push(cylinder.end());
+            let radius = match chain.get(i + 1) {
+                Some(&following) => (cylinder.radius + cylinders[following].radius) / 2.0,
+                None => cylinder.radius,
+            };
+            radii.push(radius);
+        }
+
+        // The branch frame sits at the base of the chain with +Z along the first cylinder
+        let world_rotation = UnitQuaternion::rotation_between(&Vector3::z(), &first.axis)
+            .unwrap_or_else(|| UnitQuaternion::from_axis_angle(&Vector3::x_axis(), PI));
+        let world = Isometry3::from_parts(Translation3::from(first.start.coords), world_rotation);
+        let parent_world = parent.map_or(Isometry3::identity(), |(branch, _)| worlds[branch]);
+        let local = parent_world.inverse() * world;
+
+        let local_points: Vec<Point3<f32>> = points.iter().map(|point| world.inverse_transform_point(point)).collect();
+        let transforms = transported_transforms(&local_points);
+
+        let level = parent.map_or(0, |(branch, _)| tree.branches[branch].level + 1);
+        branch_of_chain[chain_index] = Some(tree.branches.len());
+        worlds.push(world);
+        tree.branches.push(Branch {
+            kind: BranchKind::Branch,
+            level,
+            parent: parent.map(|(branch, _)| branch),
+            attachment_index: parent.map(|(_, ring)| ring),
+            position: Point3::from(local.translation.vector),
+            rotation: local.rotation,
+            transforms,
+            radii,
+            radial_segments,
+            noise_level: 0.0,
+            noise_seed: chain_index as u64,
+        });
+
+        queue.extend(children[chain_index].iter().copied());
+    }
+
+    println!("Built {} branches from the QSM cylinders", tree.branches.len());
+    Ok(tree)
+}
+
+/// Ring transforms along a polyline, rotated by parallel transport so the rings don't twist
+fn transported_transforms(points: &[Point3<f32>]) -> Vec<BranchTransform> {
+    let directions: Vec<Vector3<f32>> = points
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).try_normalize(1.0e-9).unwrap_or_else(Vector3::z))
+        .collect();
+
+    let mut rotation = UnitQuaternion::identity();
+    let mut previous_direction = Vector3::z();
+    points
+        .iter()
+        .enumerate()
+        .map(|(i, point)| {
+            // Each ring faces halfway between the segments it joins
+            let direction = match (i.checked_sub(1).map(|j| directions[j]), directions.get(i)) {
+                (Some(before), Some(after)) => (before + after).try_normalize(1.0e-9).unwrap_or(*after),
+                (Some(before), None) => before,
+                (None, Some(after)) => *after,
+                (None, None) => Vector3::z(),
+            };
+            let turn = UnitQuaternion::rotation_between(&previous_direction, &direction)
+                .unwrap_or_else(UnitQuaternion::identity);
+            rotation = turn * rotation;
+            previous_direction = direction;
+
+            let quat = rotation.into_inner();
+            BranchTransform {
+                position: [point.x, point.y, point.z],
+                rotation: [quat.i, quat.j, quat.k, quat.w],
+            }
+        })
+        .collect()
+}
+
+/// Parse the cylinder lines, with or without a header
+fn parse_cylinders(text: &str) -> Result<Vec<Cylinder>, Box<dyn Error>> {
+    let mut lines = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('%'))
+        .map(|line| line.split(|c: char| c == ',' || c == ';' || c.is_whitespace()).filter(|value| !value.is_empty()).collect::<Vec<_>>())
+        .peekable();
+
+    // A first line that isn't all numbers names the columns
+    let columns: Vec<String> = match lines.peek() {
+        Some(first) if first.iter().any(|value| value.parse::<f32>().is_err()) => {
+            let header = lines.next().unwrap_or_default();
+            header.iter().map(|name| name.to_ascii_lowercase().replace(['_', '-', '.'], "")).collect()
+        }
+        _ => TREEQSM_COLUMNS.iter().map(|name| name.to_string()).collect(),
+    };
+    let column = |names: &[&str]| columns.iter().position(|column| names.contains(&column.as_str()));
+    let required = |names: &[&str]| {
+        column(names).ok_or_else(|| format!("Missing column in QSM file: {}", names[0]))
+    };
+
+    let radius = required(&["radius"])?;
+    let length = required(&["length"])?;
+    let start = [required(&["startx", "x"])?, required(&["starty", "y"])?, required(&["startz", "z"])?];
+    let axis = [required(&["axisx", "dx"])?, required(&["axisy", "dy"])?, required(&["axisz", "dz"])?];
+    let parent = required(&["parent"])?;
+    let extension = column(&["extension"]);
+    let branch = column(&["branch"]);
+    let position_in_branch = column(&["positioninbranch"]);
+
+    let mut cylinders = Vec::new();
+    for (line_index, values) in lines.enumerate() {
+        let value = |index: usize| -> Result<f32, Box<dyn Error>> {
+            let text = values
+                .get(index)
+                .ok_or_else(|| format!("Cylinder {} has only {} values", line_index + 1, values.len()))?;
+            Ok(text.parse::<f32>().map_err(|_| format!("Invalid value in cylinder {}: {}", line_index + 1, text))?)
+        };
+        // Optional columns may be left out at the end of the line, like in older TreeQSM files
+        let optional = |column: Option<usize>| column.filter(|&column| column < values.len());
+        // 1-based indices with 0 for none
+        let index = |column: Option<usize>| -> Result<Option<usize>, Box<dyn Error>> {
+            match column {
+                Some(column) => Ok((value(column)?.round() as usize).checked_sub(1)),
+                None => Ok(None),
+            }
+        };
+
+        let axis_vector = Vector3::new(value(axis[0])?, value(axis[1])?, value(axis[2])?);
+        cylinders.push(Cylinder {
+            radius: value(radius)?,
+            length: value(length)?,
+            start: Point3::new(value(start[0])?, value(start[1])?, value(start[2])?),
+            axis: Unit::try_new(axis_vector, 1.0e-9).unwrap_or_else(Vector3::z_axis),
+            parent: index(Some(parent))?,
+            extension: index(optional(extension))?,
+            branch: match optional(branch) {
+                Some(column) => Some(value(column)?.round() as usize),
+                None => None,
+            },
+            position_in_branch: match optional(position_in_branch) {
+                Some(column) => Some(value(column)?.round() as usize),
+                None => None,
+            },
+        });
+    }
+
+    // References outside the list are treated as missing
+    let count = cylinders.len();
+    for (index, cylinder) in cylinders.iter_mut().enumerate() {
+        cylinder.parent = cylinder.parent.filter(|&parent| parent < count && parent != index);
+        cylinder.extension = cylinder.extension.filter(|&extension| extension < count && extension != index);
+    }
+
+    Ok(cylinders)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headerless_files_follow_the_treeqsm_columns() {
+        // radius length start axis parent extension branch branch_order position_in_branch mad
+        let text = "0.2 1 0 0 0 0 0 1 0 2 1 0 1 0.01\n0.1 1 0 0 1 0 0 1 1 0 1 0 2 0.01\n";
+        let cylinders = parse_cylinders(text).unwrap();
+        assert_eq!(cylinders.len(), 2);
+        assert_eq!(cylinders[0].extension, Some(1));
+        assert_eq!(cylinders[1].parent, Some(0));
+        assert_eq!(cylinders[0].branch, Some(1));
+        assert_eq!(cylinders[0].position_in_branch, Some(1));
+        assert_eq!(cylinders[1].position_in_branch, Some(2));
+
+        // Files without the last columns still read
+        let cylinders = parse_cylinders("0.2 1 0 0 0 0 0 1 0 0 1\n").unwrap();
+        assert_eq!(cylinders[0].branch, Some(1));
+        assert_eq!(cylinders[0].position_in_branch, None);
+    }
+
+    #[test]
+    fn headers_name_the_columns() {
+        let text = "parent,radius,length,start_x,start_y,start_z,axis_x,axis_y,axis_z,position_in_branch\n\
+                    0,0.2,1,0,0,0,0,0,1,1\n\
+                    1,0.1,1,0,0,1,0,0,1,2\n";
+        let cylinders = parse_cylinders(text).unwrap();
+        assert_eq!(cylinders.len(), 2);
+        assert_eq!(cylinders[1].parent, Some(0));
+        assert_eq!(cylinders[1].radius, 0.1);
+        assert_eq!(cylinders[1].start, Point3::new(0.0, 0.0, 1.0));
+        assert_eq!(cylinders[1].position_in_branch, Some(2));
+        assert_eq!(cylinders[1].branch, None);
+    }
+}
@@ -0,0 +1,294 @@
+use nalgebra::{Point3, Vector3};
+
+/// A triangle to rasterize, in the tree's frame
+#[derive(Debug, Clone, Copy)]
+pub struct RasterTriangle {
+    pub positions: [Point3<f32>; 3],
+    pub normals: [Vector3<f32>; 3],
+    /// Albedo of the triangle (RGB)
+    pub color: [f32; 3],
+}
+
+/// Orthographic camera looking at the tree
+#[derive(Debug, Clone, Copy)]
+pub struct ViewCamera {
+    /// Point at the center of the image
+    pub center: Point3<f32>,
+    /// Image right direction
+    pub right: Vector3<f32>,
+    /// Image up direction
+    pub up: Vector3<f32>,
+    /// Direction from the tree towards the camera
+    pub forward: Vector3<f32>,
+    /// Half the width of the square area covered by the image
+    pub half_size: f32,
+}
+
+impl ViewCamera {
+    /// Camera looking at `center` from `forward` (pointing away from the tree), with +Z up in the image
+    pub fn looking_from(center: Point3<f32>, forward: Vector3<f32>, half_size: f32) -> Self {
+        let forward = forward.normalize();
+        let right = Vector3::z().cross(&forward).try_normalize(1.0e-6).unwrap_or_else(Vector3::x);
+        let up = forward.cross(&right);
+        Self { center, right, up, forward, half_size }
+    }
+
+    /// Position in the image plane (-1..1 along right and up) and depth towards the camera
+    fn project(&self, point: &Point3<f32>) -> (f32, f32, f32) {
+        let offset = point - self.center;
+        (
+            offset.dot(&self.right) / self.half_size,
+            offset.dot(&self.up) / self.half_size,
+            offset.dot(&self.forward),
+        )
+    }
+}
+
+/// A rendered view: albedo with coverage as alpha, and normals in the camera's frame
+#[derive(Debug, Clone)]
+pub struct RenderedView {
+    pub size: usize,
+    /// RGBA pixels from the top row down
+    pub albedo: Vec<[u8; 4]>,
+    /// Normals as RGB (right, up, towards the camera), encoded as `0.5 + 0.5 * n`
+    pub normal: Vec<[u8; 3]>,
+}
+
+/// Render triangles with a depth buffer, supersampled to smooth the edges
+///
+/// Back faces are shaded like front faces, as leaves are seen from both sides. The colors of the
+/// empty pixels are spread from their neighbours so texture filtering doesn't darken the edges.
+pub fn render_view(triangles: &[RasterTriangle], camera: &ViewCamera, size: usize, samples: usize) -> RenderedView {
+    let samples = samples.max(1);
+    let full = size * samples;
+    let mut depth = vec![f32::MIN; full * full];
+    let mut color = vec![[0.0f32; 3]; full * full];
+    let mut normal = vec![Vector3::zeros(); full * full];
+
+    // Pixel coordinates of the image plane, y down
+    let to_pixel = |x: f32, y: f32| ((x + 1.0) * 0.5 * full as f32, (1.0 - y) * 0.5 * full as f32);
+
+    for triangle in triangles {
+        let projected = triangle.positions.map(|position| camera.project(&position));
+        let pixels = projected.map(|(x, y, _)| to_pixel(x, y));
+
+        let area = edge(pixels[0], pixels[1], pixels[2]);
+        if area.abs() < 1.0e-12 {
+            continue;
+        }
+        let min_x = pixels.iter().map(|p| p.0).fold(f32::MAX, f32::min).floor().max(0.0) as usize;
+        let max_x = (pixels.iter().map(|p| p.0).fold(f32::MIN, f32::max).ceil().max(0.0) as usize).min(full);
+        let min_y = pixels.iter().map(|p| p.1).fold(f32::MAX, f32::min).floor().max(0.0) as usize;
+        let max_y = (pixels.iter().map(|p| p.1).fold(f32::MIN, f32::max).ceil().max(0.0) as usize).min(full);
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let center = (x as f32 + 0.5, y as f32 + 0.5);
+                let weights = [
+                    edge(pixels[1], pixels[2], center) / area,
+                    edge(pixels[2], pixels[0], center) / area,
+                    edge(pixels[0], pixels[1], center) / area,
+                ];
+                if weights.iter().any(|weight| *weight < 0.0) {
+                    continue;
+                }
+
+                let z = weights[0] * projected[0].2 + weights[1] * projected[1].2 + weights[2] * projected[2].2;
+                let index = y * full + x;
+                if z <= depth[index] {
+                    continue;
+                }
+                depth[index] = z;
+                color[index] = triangle.color;
+
+                let world = triangle.normals[0] * weights[0] + triangle.normals[1] * weights[1] + triangle.normals[2] * weights[2];
+                let mut view = Vector3::new(world.dot(&camera.right), world.dot(&camera.up), world.dot(&camera.forward));
+                if view.z < 0.0 {
+                    view = -view;
+                }
+                normal[index] = view.try_normalize(1.0e-9).unwrap_or_else(Vector3::z);
+            }
+        }
+    }
+
+    // Average the samples of each pixel, the alpha is the covered fraction
+    let mut covered = vec![false; size * size];
+    let mut albedo = vec![[0.0f32; 4]; size * size];
+    let mut normals = vec![Vector3::zeros(); size * size];
+    for y in 0..size {
+        for x in 0..size {
+            let mut count = 0;
+            let mut sum = [0.0; 3];
+            let mut normal_sum = Vector3::zeros();
+            for sy in 0..samples {
+                for sx in 0..samples {
+                    let index = (y * samples + sy) * full + x * samples + sx;
+                    if depth[index] > f32::MIN {
+                        count += 1;
+                        for c in 0..3 {
+                            sum[c] += color[index][c];
+                        }
+                        normal_sum += normal[index];
+                    }
+                }
+            }
+            if count > 0 {
+                let index = y * size + x;
+                covered[index] = true;
+                let total = (samples * samples) as f32;
+                albedo[index] = [sum[0] / count as f32, sum[1] / count as f32, sum[2] / count as f32, count as f32 / total];
+                normals[index] = normal_sum.try_normalize(1.0e-9).unwrap_or_else(Vector3::z);
+            }
+        }
+    }
+
+    dilate(&mut albedo, &mut normals, &mut covered, size, 4);
+
+    RenderedView {
+        size,
+        albedo: albedo
+            .iter()
+            .map(|pixel| pixel.map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8))
+            .collect(),
+        normal: normals
+            .iter()
+            .map(|normal| [normal.x, normal.y, normal.z].map(|value| ((value * 0.5 + 0.5).clamp(0.0, 1.0) * 255.0).round() as u8))
+            .collect(),
+    }
+}
+
+/// Spread the colors and normals of covered pixels into the empty ones around them, keeping their
+/// alpha at zero
+fn dilate(albedo: &mut [[f32; 4]], normals: &mut [Vector3<f32>], covered: &mut [bool], size: usize, passes: usize) {
+    for _ in 0..passes {
+        let filled = covered.to_vec();
+        for y in 0..size {
+            for x in 0..size {
+                let index = y * size + x;
+                if filled[index] {
+                    continue;
+                }
+                let mut count = 0;
+                let mut sum = [0.0; 3];
+                let mut normal_sum = Vector3::zeros();
+                for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                    let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                    if nx < 0 || ny < 0 || nx >= size as i64 || ny >= size as i64 {
+                        continue;
+                    }
+                    let neighbour = ny as usize * size + nx as usize;
+                    if filled[neighbour] {
+                        count += 1;
+                        for c in 0..3 {
+                            sum[c] += albedo[neighbour][c];
+                        }
+                        normal_sum += normals[neighbour];
+                    }
+                }
+                if count > 0 {
+                    albedo[index] = [sum[0] / count as f32, sum[1] / count as f32, sum[2] / count as f32, 0.0];
+                    normals[index] = normal_sum.try_normalize(1.0e-9).unwrap_or_else(Vector3::z);
+                    covered[index] = true;
+                }
+            }
+        }
+    }
+
+    // Pixels far from the tree face the camera
+    for (normal, covered) in normals.iter_mut().zip(covered.iter()) {
+        if !covered {
+            *normal = Vector3::z();
+        }
+    }
+}
+
+/// Twice the signed area of the triangle (a, b, c) in pixel coordinates
+fn edge(a: (f32, f32), b: (f32, f32), c: (f32, f32)) -> f32 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two triangles covering x in x0..x1 and z in z0..z1 at depth y
+    fn quad(x: (f32, f32), z: (f32, f32), y: f32, color: [f32; 3], normal: Vector3<f32>) -> [RasterTriangle; 2] {
+        let corners = [
+            Point3::new(x.0, y, z.0),
+            Point3::new(x.1, y, z.0),
+            Point3::new(x.1, y, z.1),
+            Point3::new(x.0, y, z.1),
+        ];
+        [
+            RasterTriangle { positions: [corners[0], corners[1], corners[2]], normals: [normal; 3], color },
+            RasterTriangle { positions: [corners[0], corners[2], corners[3]], normals: [normal; 3], color },
+        ]
+    }
+
+    /// Camera on +Y looking at the origin: -X to the right of the image and +Z up
+    fn camera() -> ViewCamera {
+        ViewCamera::looking_from(Point3::origin(), Vector3::y(), 1.0)
+    }
+
+    #[test]
+    fn covers_the_projected_area() {
+        let triangles = quad((-1.0, 1.0), (0.0, 1.0), 0.0, [1.0, 0.0, 0.0], Vector3::y());
+        let view = render_view(&triangles, &camera(), 8, 2);
+        for y in 0..8 {
+            for x in 0..8 {
+                let pixel = view.albedo[y * 8 + x];
+                if y < 4 {
+                    assert_eq!(pixel, [255, 0, 0, 255], "pixel {} {}", x, y);
+                } else {
+                    assert_eq!(pixel[3], 0, "pixel {} {}", x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn partly_covered_pixels_are_translucent() {
+        // The edge runs through the middle of the pixels of row 3
+        let triangles = quad((-1.0, 1.0), (0.125, 1.0), 0.0, [1.0, 1.0, 1.0], Vector3::y());
+        let view = render_view(&triangles, &camera(), 8, 2);
+        assert_eq!(view.albedo[2 * 8][3], 255);
+        assert_eq!(view.albedo[3 * 8][3], 128);
+        assert_eq!(view.albedo[4 * 8][3], 0);
+    }
+
+    #[test]
+    fn nearest_triangle_wins() {
+        let far = quad((-1.0, 1.0), (-1.0, 1.0), 0.0, [1.0, 0.0, 0.0], Vector3::y());
+        let near = quad((-1.0, 1.0), (-1.0, 1.0), 0.5, [0.0, 1.0, 0.0], Vector3::y());
+        for triangles in [[far, near].concat(), [near, far].concat()] {
+            let view = render_view(&triangles, &camera(), 4, 1);
+            assert!(view.albedo.iter().all(|pixel| *pixel == [0, 255, 0, 255]));
+        }
+    }
+
+    #[test]
+    fn normals_face_the_camera() {
+        // Back faces are turned around like front faces
+        for normal in [Vector3::y(), -Vector3::y()] {
+            let triangles = quad((-1.0, 1.0), (-1.0, 1.0), 0.0, [1.0, 1.0, 1.0], normal);
+            let view = render_view(&triangles, &camera(), 4, 1);
+            assert!(view.normal.iter().all(|pixel| *pixel == [128, 128, 255]));
+        }
+
+        // A normal towards +X points left in the image
+        let triangles = quad((-1.0, 1.0), (-1.0, 1.0), 0.0, [1.0, 1.0, 1.0], Vector3::new(1.0, 1.0, 0.0).normalize());
+        let view = render_view(&triangles, &camera(), 4, 1);
+        assert!(view.normal.iter().all(|pixel| pixel[0] < 128 && pixel[1] == 128 && pixel[2] > 128));
+    }
+
+    #[test]
+    fn empty_pixels_take_the_color_around_them() {
+        let triangles = quad((-1.0, 1.0), (0.0, 1.0), 0.0, [0.0, 0.0, 1.0], Vector3::y());
+        let view = render_view(&triangles, &camera(), 16, 1);
+        // Within the dilated rows below the tree the color is spread with no coverage
+        assert_eq!(view.albedo[9 * 16 + 5], [0, 0, 255, 0]);
+        // Past them the pixels stay empty
+        assert_eq!(view.albedo[15 * 16 + 5], [0, 0, 0, 0]);
+        assert_eq!(view.normal[15 * 16 + 5], [128, 128, 255]);
+    }
+}
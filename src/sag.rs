@@ -0,0 +1,260 @@
+use nalgebra::{Isometry3, Point3, Translation3, UnitQuaternion, Vector3};
+use std::f32::consts::PI;
+
+use crate::tree::{BranchKind, BranchTransform, Tree};
+use crate::SagConfig;
+
+/// Acceleration of gravity in m/s²
+const GRAVITY: f32 = 9.81;
+/// Thinnest radius used for the stiffness, so the very tips don't fold over
+const MIN_RADIUS: f32 = 0.001;
+
+/// Bend the branches of a tree down under their own weight and the weight they carry
+///
+/// Each segment of a branch is a frustum of wood weighing `density` kg/m³. At every ring, the
+/// weight of the rest of the branch and of the branches attached beyond it makes a bending moment
+/// about the ring, which curves the next segment by `moment / (E × I)` per meter: a cantilever
+/// beam with the wood's Young's modulus `E` and the ring's second moment of area
+/// `I = π r⁴ / 4`. The bends are computed from the unbent shape and no segment is turned past
+/// hanging straight down. Children follow the ring they're attached to. Roots are held by the
+/// ground and keep their shape.
+pub fn sag_tree(tree: &Tree, sag: &SagConfig) -> Tree {
+    let stiffness = sag.stiffness * 1.0e9;
+    let count = tree.branches.len();
+
+    let mut children = vec![Vec::new(); count];
+    for (index, branch) in tree.branches.iter().enumerate() {
+        if let Some(parent) = branch.parent {
+            children[parent].push(index);
+        }
+    }
+
+    // Mass of every branch with its descendants and their center of mass in the branch's frame,
+    // children coming after their parents
+    let mut loads = vec![(0.0, Point3::origin()); count];
+    for index in (0..count).rev() {
+        let mut mass = 0.0;
+        let mut moment = Vector3::zeros();
+        for (segment_mass, center) in segment_masses(tree, index, sag.density) {
+            mass += segment_mass;
+            moment += center.coords * segment_mass;
+        }
+        for &child in &children[index] {
+            let (child_mass, child_center) = loads[child];
+            mass += child_mass;
+            moment += (tree.branches[child].local_transform() * child_center).coords * child_mass;
+        }
+        let center = if mass > 0.0 { Point3::from(moment / mass) } else { Point3::origin() };
+        loads[index] = (mass, center);
+    }
+
+    let mut sagged = tree.clone();
+    let mut worlds: Vec<Isometry3<f32>> = Vec::with_capacity(count);
+    for (index, branch_children) in children.iter().enumerate() {
+        // The branch moves with the ring of its parent it's attached to
+        if let Some(parent) = tree.branches[index].parent {
+            let attachment = tree.branches[index].attachment_index.unwrap_or(0);
+            let (old, new) = (&tree.branches[parent].transforms, &sagged.branches[parent].transforms);
+            if let (Some(old), Some(new)) = (old.get(attachment), new.get(attachment)) {
+                let moved = ring_frame(new) * ring_frame(old).inverse() * tree.branches[index].local_transform();
+                sagged.branches[index].position = Point3::from(moved.translation.vector);
+                sagged.branches[index].rotation = moved.rotation;
+            }
+        }
+        let world = match sagged.branches[index].parent {
+            Some(parent) => worlds[parent] * sagged.branches[index].local_transform(),
+            None => sagged.branches[index].local_transform(),
+        };
+        worlds.push(world);
+
+        if tree.branches[index].kind == BranchKind::Branch {
+            let down = world.rotation.inverse() * -Vector3::z();
+            sagged.branches[index].transforms = bend_branch(tree, index, branch_children, &loads, down, stiffness, sag.density);
+        }
+    }
+    sagged
+}
+
+/// Mass and center of each segment of a branch, in the branch's frame
+fn segment_masses(tree: &Tree, index: usize, density: f32) -> Vec<(f32, Point3<f32>)> {
+    let branch = &tree.branches[index];
+    if branch.kind != BranchKind::Branch {
+        return Vec::new();
+    }
+    let radius = |i: usize| branch.radii.get(i).copied().unwrap_or(0.0);
+    branch
+        .transforms
+        .windows(2)
+        .enumerate()
+        .map(|(i, pair)| {
+            let (start, end) = (pair[0].point(), pair[1].point());
+            let (r0, r1) = (radius(i), radius(i + 1));
+            let volume = PI / 3.0 * (end - start).norm() * (r0 * r0 + r0 * r1 + r1 * r1);
+            (density * volume, nalgebra::center(&start, &end))
+        })
+        .collect()
+}
+
+/// Path of a branch bent by the weight it carries, with `down` the direction of gravity in the
+/// branch's frame
+fn bend_branch(
+    tree: &Tree,
+    index: usize,
+    children: &[usize],
+    loads: &[(f32, Point3<f32>)],
+    down: Vector3<f32>,
+    stiffness: f32,
+    density: f32,
+) -> Vec<BranchTransform> {
+    let branch = &tree.branches[index];
+    let points: Vec<Point3<f32>> = branch.transforms.iter().map(|transform| transform.point()).collect();
+    if points.len() < 2 {
+        return branch.transforms.clone();
+    }
+
+    // Weights hanging off the branch: its own segments, and its children at their attachment
+    let mut weights: Vec<(usize, f32, Point3<f32>)> = segment_masses(tree, index, density)
+        .into_iter()
+        .enumerate()
+        .map(|(i, (mass, center))| (i, mass, center))
+        .collect();
+    for &child in children {
+        let (mass, center) = loads[child];
+        let attachment = tree.branches[child].attachment_index.unwrap_or(0);
+        weights.push((attachment, mass, tree.branches[child].local_transform() * center));
+    }
+
+    // The rotation of each segment, adding up the bends at every ring before it
+    let mut rotations = Vec::with_capacity(points.len() - 1);
+    let mut rotation = UnitQuaternion::identity();
+    for i in 0..points.len() - 1 {
+        let segment = points[i + 1] - points[i];
+        let length = segment.norm();
+        if length > f32::EPSILON {
+            let direction = segment / length;
+
+            // Moment of the weights beyond the ring, without the twist along the segment
+            let torque: Vector3<f32> = weights
+                .iter()
+                .filter(|(ring, _, _)| *ring >= i)
+                .map(|&(_, mass, center)| (center - points[i]).cross(&(down * mass * GRAVITY)))
+                .sum();
+            let bending = torque - direction * torque.dot(&direction);
+
+            let radius = branch.radii.get(i).copied().unwrap_or(0.0).max(MIN_RADIUS);
+            let second_moment = PI * radius.powi(4) / 4.0;
+            let angle = bending.norm() / (stiffness * second_moment) * length;
+
+            if let Some(axis) = nalgebra::Unit::try_new(rotation * bending, 1.0e-12) {
+                // No further than hanging straight down
+                let hanging = (rotation * direction).angle(&down);
+                rotation = UnitQuaternion::from_axis_angle(&axis, angle.min(hanging)) * rotation;
+            }
+        }
+        rotations.push(rotation);
+    }
+
+    // Move the rings along the bent segments, each turned halfway between its two segments
+    let mut bent = Vec::with_capacity(points.len());
+    let mut position = points[0];
+    for (i, transform) in branch.transforms.iter().enumerate() {
+        if i > 0 {
+            position += rotations[i - 1] * (points[i] - points[i - 1]);
+        }
+        let before = if i > 0 { rotations[i - 1] } else { UnitQuaternion::identity() };
+        let after = rotations.get(i).copied().unwrap_or(before);
+        let turn = before.slerp(&after, 0.5);
+        let ring = (turn * transform.unit_quaternion()).into_inner();
+        bent.push(BranchTransform {
+            position: [position.x, position.y, position.z],
+            rotation: [ring.i, ring.j, ring.k, ring.w],
+        });
+    }
+    bent
+}
+
+/// Frame of a ring in its branch's frame
+fn ring_frame(transform: &BranchTransform) -> Isometry3<f32> {
+    Isometry3::from_parts(Translation3::from(transform.point().coords), transform.unit_quaternion())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::Branch;
+    use std::f32::consts::FRAC_PI_2;
+
+    /// Soft wood, so the bends are large
+    const SOFT: SagConfig = SagConfig { stiffness: 0.01, density: 700.0 };
+
+    /// A 6 unit trunk with a horizontal 5 unit branch along +X from its ring at `attachment`
+    fn tree_with_horizontal_branch(kind: BranchKind, attachment: usize) -> Tree {
+        let trunk = Branch { radii: vec![0.3; 7], ..Branch::straight(0, None, None, 7) };
+        let branch = Branch {
+            kind,
+            position: Point3::new(0.0, 0.0, attachment as f32),
+            rotation: UnitQuaternion::from_axis_angle(&Vector3::y_axis(), FRAC_PI_2),
+            radii: vec![0.05; 6],
+            ..Branch::straight(1, Some(0), Some(attachment), 6)
+        };
+        Tree { branches: vec![trunk, branch], ..Default::default() }
+    }
+
+    /// Position of a ring of a branch in the tree's frame
+    fn ring(tree: &Tree, index: usize, ring: usize) -> Point3<f32> {
+        tree.world_transform(index) * tree.branches[index].transforms[ring].point()
+    }
+
+    #[test]
+    fn horizontal_branch_tip_drops() {
+        let tree = tree_with_horizontal_branch(BranchKind::Branch, 3);
+        let sagged = sag_tree(&tree, &SOFT);
+        let (tip, sagged_tip) = (ring(&tree, 1, 5), ring(&sagged, 1, 5));
+        assert!((tip.z - 3.0).abs() < 1.0e-4);
+        assert!(sagged_tip.z < tip.z - 0.1, "tip at {:?}", sagged_tip);
+    }
+
+    #[test]
+    fn vertical_trunk_keeps_its_shape() {
+        let tree = Tree { branches: vec![Branch::straight(0, None, None, 7)], ..Default::default() };
+        let sagged = sag_tree(&tree, &SOFT);
+        for i in 0..7 {
+            assert!((ring(&sagged, 0, i) - ring(&tree, 0, i)).norm() < 1.0e-4);
+        }
+    }
+
+    #[test]
+    fn segments_hang_no_further_than_straight_down() {
+        let tree = tree_with_horizontal_branch(BranchKind::Branch, 3);
+        let sagged = sag_tree(&tree, &SagConfig { stiffness: 1.0e-6, ..SOFT });
+        for i in 0..5 {
+            let segment = (ring(&sagged, 1, i + 1) - ring(&sagged, 1, i)).normalize();
+            assert!(segment.x > -1.0e-3, "segment {} points back along {:?}", i, segment);
+        }
+
+        // Wood this soft hangs from the first ring on
+        let tip = ring(&sagged, 1, 5) - ring(&sagged, 1, 0);
+        assert!(tip.z < -4.0, "tip at {:?}", tip);
+    }
+
+    #[test]
+    fn children_follow_their_attachment_ring() {
+        // A twig at the third ring of the horizontal branch
+        let mut tree = tree_with_horizontal_branch(BranchKind::Branch, 3);
+        tree.branches.push(Branch { position: Point3::new(0.0, 0.0, 2.0), ..Branch::straight(2, Some(1), Some(2), 3) });
+        let sagged = sag_tree(&tree, &SOFT);
+
+        let base = sagged.world_transform(2) * Point3::origin();
+        assert!((base - ring(&sagged, 1, 2)).norm() < 1.0e-4, "twig at {:?}", base);
+        assert!((base - ring(&tree, 1, 2)).norm() > 0.01, "the twig moved down with the branch");
+    }
+
+    #[test]
+    fn roots_keep_their_shape() {
+        let tree = tree_with_horizontal_branch(BranchKind::Root, 0);
+        let sagged = sag_tree(&tree, &SOFT);
+        for i in 0..6 {
+            assert!((ring(&sagged, 1, i) - ring(&tree, 1, i)).norm() < 1.0e-4);
+        }
+    }
+}
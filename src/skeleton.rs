@@ -0,0 +1,258 @@
+use nalgebra::{Point3, Quaternion, UnitQuaternion};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::orientation::ExportFrame;
+use crate::qsm::read_qsm_cylinders;
+use crate::tree::{Branch, BranchKind, BranchTransform, Tree};
+
+/// The branch skeleton of a tree, without any mesh
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonSkeleton {
+    /// Branches, parents are always listed before their children
+    pub branches: Vec<JsonSkeletonBranch>,
+    /// Height of the ground plane the roots are clipped to
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub ground_clip: Option<f32>,
+}
+
+/// A single branch of the skeleton
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonSkeletonBranch {
+    /// Index of the branch in the skeleton
+    pub id: usize,
+    /// "branch" or "root"
+    pub kind: BranchKind,
+    /// Depth in the hierarchy (0 = trunk or main root)
+    pub level: u32,
+    /// Id of the parent branch
+    pub parent: Option<usize>,
+    /// Index of the parent's transform the branch is attached to
+    pub attachment_index: Option<usize>,
+    /// Position of the branch base in the parent's frame
+    pub position: [f32; 3],
+    /// Rotation of the branch in the parent's frame (x, y, z, w)
+    pub rotation: [f32; 4],
+    /// Path of the branch in its own frame
+    pub transforms: Vec<BranchTransform>,
+    /// Radius at each transform
+    pub radii: Vec<f32>,
+    /// Number of segments around the branch circumference
+    #[serde(default = "default_radial_segments")]
+    pub radial_segments: u32,
+    /// Amount of random variation (0.0-1.0) applied to the mesh vertices
+    #[serde(default)]
+    pub noise_level: f32,
+    /// Seed of the vertex noise, the branch id when missing
+    #[serde(default)]
+    pub noise_seed: Option<u64>,
+    /// Position of each transform in the tree's frame, for tools that don't walk the hierarchy
+    #[serde(default)]
+    pub world_points: Vec<[f32; 3]>,
+}
+
+/// Convert a tree to its JSON skeleton
+pub fn tree_to_skeleton(tree: &Tree) -> JsonSkeleton {
+    let branches = tree
+        .branches
+        .iter()
+        .enumerate()
+        .map(|(index, branch)| {
+            let world = tree.world_transform(index);
+            let rotation = branch.rotation.into_inner();
+            JsonSkeletonBranch {
+                id: index,
+                kind: branch.kind,
+                level: branch.level,
+                parent: branch.parent,
+                attachment_index: branch.attachment_index,
+                position: branch.position.into(),
+                rotation: [rotation.i, rotation.j, rotation.k, rotation.w],
+                transforms: branch.transforms.clone(),
+                radii: branch.radii.clone(),
+                radial_segments: branch.radial_segments,
+                noise_level: branch.noise_level,
+                noise_seed: Some(branch.noise_seed),
+                world_points: branch
+                    .transforms
+                    .iter()
+                    .map(|transform| (world * transform.point()).into())
+                    .collect(),
+            }
+        })
+        .collect();
+
+    JsonSkeleton {
+        branches,
+        ground_clip: tree.ground_clip,
+    }
+}
+
+/// Build a tree from a JSON skeleton, branch ids may be in any order as long as parents come first
+pub fn skeleton_to_tree(skeleton: &JsonSkeleton) -> Result<Tree, Box<dyn Error>> {
+    let mut indices: HashMap<usize, usize> = HashMap::new();
+    let mut branches: Vec<Branch> = Vec::with_capacity(skeleton.branches.len());
+
+    for branch in &skeleton.branches {
+        let parent = match branch.parent {
+            Some(parent) => Some(*indices.get(&parent).ok_or_else(|| {
+                format!("Branch {} is listed before its parent {}", branch.id, parent)
+            })?),
+            None => None,
+        };
+        if let (Some(parent), Some(attachment)) = (parent, branch.attachment_index) {
+            let parent_transforms = branches[parent].transforms.len();
+            if attachment >= parent_transforms {
+                return Err(format!(
+                    "Branch {} is attached to transform {} but its parent only has {}",
+                    branch.id, attachment, parent_transforms
+                ).into());
+            }
+        }
+        if branch.radii.len() != branch.transforms.len() {
+            return Err(format!(
+                "Branch {} has {} radii for {} transforms",
+                branch.id, branch.radii.len(), branch.transforms.len()
+            ).into());
+        }
+
+        let [x, y, z, w] = branch.rotation;
+        let rotation = Quaternion::new(w, x, y, z);
+        if !branch.rotation.iter().all(|value| value.is_finite()) || rotation.norm() < 1.0e-6 {
+            return Err(format!("Branch {} has an invalid rotation {:?}", branch.id, branch.rotation).into());
+        }
+        indices.insert(branch.id, branches.len());
+        branches.push(Branch {
+            kind: branch.kind,
+            level: branch.level,
+            parent,
+            attachment_index: branch.attachment_index,
+            position: Point3::from(branch.position),
+            rotation: UnitQuaternion::from_quaternion(rotation),
+            transforms: branch.transforms.clone(),
+            radii: branch.radii.clone(),
+            radial_segments: branch.radial_segments,
+            noise_level: branch.noise_level,
+            noise_seed: branch.noise_seed.unwrap_or(branch.id as u64),
+        });
+    }
+
+    Ok(Tree {
+        branches,
+        ground_clip: skeleton.ground_clip,
+        ring_angle: None,
+    })
+}
+
+/// Read a skeleton to mesh: a JSON skeleton (`.json`) or a QSM cylinder list (any other extension)
+///
+/// `radial_segments` is used for the QSM cylinders, which don't specify it.
+pub fn read_skeleton(path: &Path, radial_segments: u32) -> Result<Tree, Box<dyn Error>> {
+    let is_json = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+
+    if is_json {
+        let skeleton: JsonSkeleton = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        skeleton_to_tree(&skeleton)
+    } else {
+        read_qsm_cylinders(path, radial_segments)
+    }
+}
+
+/// Write the skeleton of the tree as JSON
+pub fn write_skeleton_json(tree: &Tree, output_path: &Path) -> Result<(), Box<dyn Error>> {
+    let file = BufWriter::new(File::create(output_path)?);
+    serde_json::to_writer_pretty(file, &tree_to_skeleton(tree))?;
+    Ok(())
+}
+
+/// Write the skeleton of the tree as OBJ polylines, one object per branch in the exported frame
+///
+/// OBJ lines have no thickness, so the radius of each point is written as a comment.
+pub fn write_skeleton_obj(tree: &Tree, frame: &ExportFrame, output_path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut obj = BufWriter::new(File::create(output_path)?);
+    writeln!(obj, "# Generated by tree-maker")?;
+
+    // OBJ indices are 1-based and shared by the whole file
+    let mut offset = 1;
+    for (index, branch) in tree.branches.iter().enumerate() {
+        if branch.transforms.is_empty() {
+            continue;
+        }
+
+        let world = tree.world_transform(index);
+        writeln!(obj, "o {}_L{}_{}", branch.kind.name(), branch.level, index)?;
+        writeln!(obj, "# radii {}", branch.radii.iter().map(|radius| frame.length(*radius).to_string()).collect::<Vec<_>>().join(" "))?;
+        for transform in &branch.transforms {
+            let point = frame.point(&(world * transform.point()));
+            writeln!(obj, "v {} {} {}", point.x, point.y, point.z)?;
+        }
+
+        let line: Vec<String> = (0..branch.transforms.len()).map(|i| (offset + i).to_string()).collect();
+        writeln!(obj, "l {}", line.join(" "))?;
+        offset += branch.transforms.len();
+    }
+
+    obj.flush()?;
+    Ok(())
+}
+
+/// Default number of radial segments for skeletons that don't specify it
+fn default_radial_segments() -> u32 {
+    8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A trunk with a noisy side branch
+    fn tree() -> Tree {
+        let mut branch = Branch::straight(1, Some(0), Some(1), 3);
+        branch.rotation = UnitQuaternion::from_euler_angles(0.5, 0.2, 0.0);
+        branch.noise_level = 0.5;
+        branch.noise_seed = 987_654_321;
+        Tree { branches: vec![Branch::straight(0, None, None, 3), branch], ..Default::default() }
+    }
+
+    #[test]
+    fn round_trip_keeps_the_noise_seed() {
+        let tree = tree();
+        let json = serde_json::to_value(tree_to_skeleton(&tree)).unwrap();
+        assert_eq!(json["branches"][1]["noiseSeed"], 987_654_321);
+
+        let read = skeleton_to_tree(&serde_json::from_value(json).unwrap()).unwrap();
+        assert_eq!(read.branches[1].noise_seed, 987_654_321);
+        assert!(read.branches[1].rotation.angle_to(&tree.branches[1].rotation) < 1.0e-6);
+        assert_eq!(read.branch_mesh(1).0, tree.branch_mesh(1).0);
+
+        // Skeletons written without seeds seed each branch with its id
+        let mut skeleton = tree_to_skeleton(&tree);
+        skeleton.branches.iter_mut().for_each(|branch| branch.noise_seed = None);
+        let read = skeleton_to_tree(&skeleton).unwrap();
+        assert_eq!(read.branches[1].noise_seed, 1);
+    }
+
+    #[test]
+    fn invalid_rotations_are_rejected() {
+        for rotation in [[0.0; 4], [f32::NAN, 0.0, 0.0, 1.0], [0.0, f32::INFINITY, 0.0, 1.0]] {
+            let mut skeleton = tree_to_skeleton(&tree());
+            skeleton.branches[1].rotation = rotation;
+            assert!(skeleton_to_tree(&skeleton).is_err(), "{:?} was accepted", rotation);
+        }
+
+        // Rotations only need a length, they're normalized
+        let mut skeleton = tree_to_skeleton(&tree());
+        skeleton.branches[1].rotation = [0.0, 0.0, 0.0, 2.0];
+        let read = skeleton_to_tree(&skeleton).unwrap();
+        assert_eq!(read.branches[1].rotation, UnitQuaternion::identity());
+    }
+}
@@ -0,0 +1,171 @@
+use nalgebra::{Isometry3, Translation3};
+use serde_json::json;
+use std::error::Error;
+
+use crate::gltf::{GlbDocument, TARGET_ARRAY_BUFFER};
+use crate::tree::{MergedMesh, Tree};
+use crate::SkinConfig;
+
+/// Most joints a skin can have, as glTF stores the joint indices of the vertices in 16 bits
+const MAX_JOINTS: usize = u16::MAX as usize + 1;
+
+/// Add a skin with one bone chain per branch down to `skin.depth` and bind the merged meshes to it
+///
+/// Every transform of a branch becomes a joint, parented to the previous joint of the branch. The
+/// first joint of a branch hangs off the parent's joint at the attachment point. Each vertex is bound
+/// to the joint of its ring; branches deeper than `skin.depth` follow the joint they're attached to.
+/// Fails when the skin would need more joints than glTF can index, lower `skin.depth` then.
+///
+/// # Arguments
+///
+/// * `document` - Exported GLB document containing the merged meshes
+/// * `tree` - Skeleton the document was built from
+/// * `root_node` - Tree root node, the joints are added below it
+/// * `merged_meshes` - The merged meshes to bind, with the branch of each of their vertices
+/// * `skin` - Skin configuration
+pub fn add_skin(
+    document: &mut GlbDocument,
+    tree: &Tree,
+    root_node: usize,
+    merged_meshes: &[MergedMesh],
+    skin: &SkinConfig,
+) -> Result<(), Box<dyn Error>> {
+    let joint_count: usize = tree
+        .branches
+        .iter()
+        .filter(|branch| branch.level <= skin.depth)
+        .map(|branch| branch.transforms.len())
+        .sum();
+    if joint_count > MAX_JOINTS {
+        return Err(format!(
+            "The skin needs {} joints but glTF allows at most {}, lower the skin depth",
+            joint_count, MAX_JOINTS
+        )
+        .into());
+    }
+
+    // Joint index in the skin of every transform of every skinned branch
+    let mut branch_joints: Vec<Vec<usize>> = vec![Vec::new(); tree.branches.len()];
+    let mut joint_nodes = Vec::new();
+    let mut joint_worlds: Vec<Isometry3<f32>> = Vec::new();
+
+    // Parents are stored before their children, so their joints already exist
+    for (index, branch) in tree.branches.iter().enumerate() {
+        if branch.level > skin.depth {
+            continue;
+        }
+
+        let world = tree.world_transform(index);
+        let mut parent_joint = match (branch.parent, branch.attachment_index) {
+            (Some(parent), Some(attachment)) => branch_joints[parent].get(attachment).copied(),
+            _ => None,
+        };
+
+        for (ring, transform) in branch.transforms.iter().enumerate() {
+            let joint_world = world
+                * Isometry3::from_parts(Translation3::from(transform.point().coords), transform.unit_quaternion());
+            let local = match parent_joint {
+                Some(parent) => joint_worlds[parent].inverse() * joint_world,
+                None => joint_world,
+            };
+
+            let translation = local.translation.vector;
+            let rotation = local.rotation.into_inner();
+            let node = document.push("nodes", json!({
+                "name": format!("{}_{}_Joint_{}", branch.kind.name(), index, ring),
+                "translation": [translation.x, translation.y, translation.z],
+                "rotation": [rotation.i, rotation.j, rotation.k, rotation.w],
+            }));
+            match parent_joint {
+                Some(parent) => document.add_child(joint_nodes[parent], node),
+                None => document.add_child(root_node, node),
+            }
+
+            let joint = joint_nodes.len();
+            joint_nodes.push(node);
+            joint_worlds.push(joint_world);
+            branch_joints[index].push(joint);
+            parent_joint = Some(joint);
+        }
+    }
+
+    if joint_nodes.is_empty() {
+        return Ok(());
+    }
+
+    // Inverse bind matrices bring the vertices from the tree's frame to each joint's frame
+    let inverse_bind_matrices: Vec<f32> = joint_worlds
+        .iter()
+        .flat_map(|world| world.inverse().to_homogeneous().as_slice().to_vec())
+        .collect();
+    let inverse_bind_matrices = document.add_float_accessor(&inverse_bind_matrices, 16, None);
+    let skin_index = document.push("skins", json!({
+        "name": "Tree",
+        "inverseBindMatrices": inverse_bind_matrices,
+        "joints": joint_nodes,
+        "skeleton": root_node,
+    }));
+
+    for merged in merged_meshes {
+        let mut joints = Vec::with_capacity(merged.vertex_branches.len() * 4);
+        let mut weights = Vec::with_capacity(merged.vertex_branches.len() * 4);
+        for (&index, &distance) in merged.vertex_branches.iter().zip(&merged.vertex_distances) {
+            // The distance along the branch is the ring position, caps included
+            let rings = tree.branches[index].transforms.len();
+            let ring = (distance * rings.saturating_sub(1) as f32).round() as usize;
+            let joint = vertex_joint(tree, &branch_joints, index, ring);
+            joints.extend_from_slice(&[joint as u16, 0, 0, 0]);
+            weights.extend_from_slice(&[1.0, 0.0, 0.0, 0.0]);
+        }
+
+        let joints = document.add_u16_accessor(&joints, 4, Some(TARGET_ARRAY_BUFFER));
+        let weights = document.add_float_accessor(&weights, 4, Some(TARGET_ARRAY_BUFFER));
+        let attributes = &mut document.json["meshes"][merged.mesh]["primitives"][0]["attributes"];
+        attributes["JOINTS_0"] = json!(joints);
+        attributes["WEIGHTS_0"] = json!(weights);
+        document.json["nodes"][merged.node]["skin"] = json!(skin_index);
+    }
+
+    println!("Adding skin with {} joints", joint_nodes.len());
+    Ok(())
+}
+
+/// Joint driving a ring of a branch, walking up to the attachment point of unskinned branches
+fn vertex_joint(tree: &Tree, branch_joints: &[Vec<usize>], index: usize, ring: usize) -> usize {
+    let joints = &branch_joints[index];
+    if !joints.is_empty() {
+        return joints[ring.min(joints.len() - 1)];
+    }
+    match (tree.branches[index].parent, tree.branches[index].attachment_index) {
+        (Some(parent), Some(attachment)) => vertex_joint(tree, branch_joints, parent, attachment),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::Branch;
+
+    fn tree_with_rings(rings: usize) -> Tree {
+        Tree { branches: vec![Branch::straight(0, None, None, rings)], ..Default::default() }
+    }
+
+    fn empty_document() -> (GlbDocument, usize) {
+        let mut document = GlbDocument { json: json!({}), bin: Vec::new() };
+        let root = document.push("nodes", json!({ "name": "Tree" }));
+        (document, root)
+    }
+
+    #[test]
+    fn joints_fit_the_vertex_indices() {
+        let skin = SkinConfig { depth: 1 };
+        let (mut document, root) = empty_document();
+        add_skin(&mut document, &tree_with_rings(MAX_JOINTS), root, &[], &skin).unwrap();
+        assert_eq!(document.json["skins"][0]["joints"].as_array().map(Vec::len), Some(MAX_JOINTS));
+
+        let (mut document, root) = empty_document();
+        assert!(add_skin(&mut document, &tree_with_rings(MAX_JOINTS + 1), root, &[], &skin).is_err());
+        assert!(document.json["skins"].is_null());
+    }
+}
@@ -0,0 +1,308 @@
+use nalgebra::{Point2, Point3, Quaternion, UnitQuaternion};
+use serde::Serialize;
+use std::error::Error;
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::leaves::{leaf_prototype, leaf_width, LeafInstance};
+use crate::tree::{BranchKind, ExportedTree, Tree};
+use crate::TreeConfig;
+
+/// Height of the trunk diameter measurement, in meters
+const BREAST_HEIGHT: f32 = 1.3;
+
+/// Statistics and metrics of an exported tree, lengths in the tree's units (meters)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TreeStats {
+    pub vertices: usize,
+    pub triangles: usize,
+    /// Branches and mesh sizes of each level of the branches and roots
+    pub levels: Vec<LevelStats>,
+    /// Mesh sizes of each material, leaves counted as rendered rather than as one instanced mesh
+    pub materials: Vec<MaterialStats>,
+    /// Volume of the branches, as truncated cones between their rings
+    pub wood_volume: f32,
+    /// Bark area of the branches
+    pub wood_surface_area: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root_volume: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root_surface_area: Option<f32>,
+    pub bounds_min: [f32; 3],
+    pub bounds_max: [f32; 3],
+    /// Height of the top of the tree above its base
+    pub height: f32,
+    /// Height of the lowest branch or leaf, where the crown starts
+    pub crown_base: f32,
+    pub crown_height: f32,
+    /// Mean of the crown's extents along X and Y
+    pub crown_width: f32,
+    /// Area of the crown's outline seen from above
+    pub crown_projected_area: f32,
+    /// Trunk diameter at breast height (1.3 m), the equivalent diameter of all stems for a trunk
+    /// forking below it, or none for trees shorter than that
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dbh: Option<f32>,
+    pub leaves: usize,
+    /// One-sided area of the leaves
+    pub leaf_area: f32,
+    /// Leaf area per crown projected area
+    pub leaf_area_index: f32,
+}
+
+/// Branches and mesh sizes of one level of a hierarchy
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LevelStats {
+    pub kind: BranchKind,
+    pub level: u32,
+    pub branches: usize,
+    pub vertices: usize,
+    pub triangles: usize,
+}
+
+/// Mesh sizes of one material
+#[derive(Debug, Clone, Serialize)]
+pub struct MaterialStats {
+    pub material: &'static str,
+    pub vertices: usize,
+    pub triangles: usize,
+}
+
+/// Measure an exported tree from its branch meshes, skeleton and leaves
+pub fn tree_stats(exported: &ExportedTree, config: &TreeConfig) -> TreeStats {
+    let tree = &exported.tree;
+    let leaves = exported.leaves(config);
+
+    // Mesh sizes by level and material, and the points bounding the tree and its crown
+    let mut levels: Vec<LevelStats> = Vec::new();
+    let mut materials = vec![
+        MaterialStats { material: "Trunk", vertices: 0, triangles: 0 },
+        MaterialStats { material: "Roots", vertices: 0, triangles: 0 },
+        MaterialStats { material: "Leaves", vertices: 0, triangles: 0 },
+    ];
+    let mut points: Vec<Point3<f32>> = Vec::new();
+    let mut crown: Vec<Point3<f32>> = Vec::new();
+    for (index, branch) in tree.branches.iter().enumerate() {
+        let (vertices, indices, ..) = tree.world_branch_mesh(index);
+        let level = match levels.iter_mut().find(|level| level.kind == branch.kind && level.level == branch.level) {
+            Some(level) => level,
+            None => {
+                levels.push(LevelStats { kind: branch.kind, level: branch.level, branches: 0, vertices: 0, triangles: 0 });
+                levels.last_mut().unwrap()
+            }
+        };
+        level.branches += 1;
+        level.vertices += vertices.len();
+        level.triangles += indices.len();
+
+        let material = &mut materials[if branch.kind == BranchKind::Root { 1 } else { 0 }];
+        material.vertices += vertices.len();
+        material.triangles += indices.len();
+
+        if branch.kind == BranchKind::Branch && branch.level > 0 {
+            crown.extend(&vertices);
+        }
+        points.extend(vertices);
+    }
+    levels.sort_by_key(|level| (level.kind == BranchKind::Root, level.level));
+
+    let mut leaf_area = 0.0;
+    if let Some(leaves_config) = &config.leaves {
+        let (prototype, prototype_indices, ..) = leaf_prototype(leaves_config);
+        for leaf in &leaves {
+            let corners = leaf_vertices(&prototype, leaf);
+            crown.extend(&corners);
+            points.extend(corners);
+            // The crossed quad of a double billboard shows the same leaf
+            leaf_area += leaf_width(leaves_config.shape) * leaf.scale[0] * leaf.scale[2];
+        }
+        materials[2].vertices = prototype.len() * leaves.len();
+        materials[2].triangles = prototype_indices.len() * leaves.len();
+    }
+    materials.retain(|material| material.vertices > 0);
+
+    let (bounds_min, bounds_max) = bounds(&points);
+    let (wood_volume, wood_surface_area) = frusta(tree, BranchKind::Branch);
+    let has_roots = tree.branches.iter().any(|branch| branch.kind == BranchKind::Root);
+    let (root_volume, root_surface_area) = frusta(tree, BranchKind::Root);
+
+    // A tree without side branches or leaves is all crown
+    if crown.is_empty() {
+        crown = points.iter().filter(|point| point.z >= 0.0).copied().collect();
+    }
+    let (crown_min, crown_max) = bounds(&crown);
+    let crown_projected_area = hull_area(&crown.iter().map(|point| Point2::new(point.x, point.y)).collect::<Vec<_>>());
+
+    TreeStats {
+        vertices: materials.iter().map(|material| material.vertices).sum(),
+        triangles: materials.iter().map(|material| material.triangles).sum(),
+        levels,
+        materials,
+        wood_volume,
+        wood_surface_area,
+        root_volume: has_roots.then_some(root_volume),
+        root_surface_area: has_roots.then_some(root_surface_area),
+        bounds_min,
+        bounds_max,
+        height: bounds_max[2].max(0.0),
+        crown_base: crown_min[2],
+        crown_height: bounds_max[2] - crown_min[2],
+        crown_width: ((crown_max[0] - crown_min[0]) + (crown_max[1] - crown_min[1])) * 0.5,
+        crown_projected_area,
+        dbh: diameter_at(tree, BREAST_HEIGHT),
+        leaves: leaves.len(),
+        leaf_area,
+        leaf_area_index: if crown_projected_area > 0.0 { leaf_area / crown_projected_area } else { 0.0 },
+    }
+}
+
+/// Print the statistics as a readable summary
+pub fn print_stats(stats: &TreeStats) {
+    println!("Tree statistics:");
+    println!("  Vertices: {}, triangles: {}", stats.vertices, stats.triangles);
+    for level in &stats.levels {
+        println!(
+            "  {} level {}: {} branches, {} vertices, {} triangles",
+            level.kind.name(), level.level, level.branches, level.vertices, level.triangles
+        );
+    }
+    for material in &stats.materials {
+        println!("  {} material: {} vertices, {} triangles", material.material, material.vertices, material.triangles);
+    }
+    println!("  Wood volume: {:.4}, surface area: {:.3}", stats.wood_volume, stats.wood_surface_area);
+    if let (Some(volume), Some(area)) = (stats.root_volume, stats.root_surface_area) {
+        println!("  Root volume: {:.4}, surface area: {:.3}", volume, area);
+    }
+    println!(
+        "  Bounds: ({:.3}, {:.3}, {:.3}) to ({:.3}, {:.3}, {:.3})",
+        stats.bounds_min[0], stats.bounds_min[1], stats.bounds_min[2],
+        stats.bounds_max[0], stats.bounds_max[1], stats.bounds_max[2]
+    );
+    println!("  Height: {:.3}", stats.height);
+    println!(
+        "  Crown: base at {:.3}, height {:.3}, width {:.3}, projected area {:.3}",
+        stats.crown_base, stats.crown_height, stats.crown_width, stats.crown_projected_area
+    );
+    match stats.dbh {
+        Some(dbh) => println!("  DBH: {:.4}", dbh),
+        None => println!("  DBH: none, the tree is shorter than {} m", BREAST_HEIGHT),
+    }
+    println!(
+        "  Leaves: {}, leaf area: {:.3}, leaf area index: {:.3}",
+        stats.leaves, stats.leaf_area, stats.leaf_area_index
+    );
+}
+
+/// Write the statistics as JSON
+pub fn write_stats_json(stats: &TreeStats, path: &Path) -> Result<(), Box<dyn Error>> {
+    let file = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(file, stats)?;
+    Ok(())
+}
+
+/// Vertices of the prototype leaf placed as one leaf copy
+fn leaf_vertices(prototype: &[Point3<f32>], leaf: &LeafInstance) -> Vec<Point3<f32>> {
+    let [x, y, z, w] = leaf.rotation;
+    let rotation = UnitQuaternion::from_quaternion(Quaternion::new(w, x, y, z));
+    prototype
+        .iter()
+        .map(|vertex| {
+            let scaled = Point3::new(vertex.x * leaf.scale[0], vertex.y * leaf.scale[1], vertex.z * leaf.scale[2]);
+            Point3::from(leaf.translation) + rotation * scaled.coords
+        })
+        .collect()
+}
+
+/// Volume and lateral area of the truncated cones between the rings of every branch of one kind
+fn frusta(tree: &Tree, kind: BranchKind) -> (f32, f32) {
+    let mut volume = 0.0;
+    let mut area = 0.0;
+    for branch in tree.branches.iter().filter(|branch| branch.kind == kind) {
+        for (pair, radii) in branch.transforms.windows(2).zip(branch.radii.windows(2)) {
+            let height = (pair[1].point() - pair[0].point()).norm();
+            let (r1, r2) = (radii[0], radii[1]);
+            volume += PI * height / 3.0 * (r1 * r1 + r1 * r2 + r2 * r2);
+            area += PI * (r1 + r2) * (height * height + (r1 - r2) * (r1 - r2)).sqrt();
+        }
+    }
+    (volume, area)
+}
+
+/// Diameter of the trunk where it crosses a height, combining the stems of a fork by their area
+fn diameter_at(tree: &Tree, height: f32) -> Option<f32> {
+    let mut area = 0.0;
+    let mut crossed = false;
+    for (index, branch) in tree.branches.iter().enumerate() {
+        if branch.kind != BranchKind::Branch || branch.level != 0 {
+            continue;
+        }
+        let world = tree.world_transform(index);
+        for (i, pair) in branch.transforms.windows(2).enumerate() {
+            let (z1, z2) = ((world * pair[0].point()).z, (world * pair[1].point()).z);
+            if z1 <= height && height < z2 {
+                let t = (height - z1) / (z2 - z1);
+                let radius = branch.radii.get(i).copied().unwrap_or(0.0) * (1.0 - t)
+                    + branch.radii.get(i + 1).copied().unwrap_or(0.0) * t;
+                area += radius * radius;
+                crossed = true;
+            }
+        }
+    }
+    crossed.then(|| 2.0 * area.sqrt())
+}
+
+/// Corners of the box around points, zero for no points
+fn bounds(points: &[Point3<f32>]) -> ([f32; 3], [f32; 3]) {
+    if points.is_empty() {
+        return ([0.0; 3], [0.0; 3]);
+    }
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for point in points {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(point[axis]);
+            max[axis] = max[axis].max(point[axis]);
+        }
+    }
+    (min, max)
+}
+
+/// Area of the convex hull of points, with Andrew's monotone chain
+fn hull_area(points: &[Point2<f32>]) -> f32 {
+    let mut sorted = points.to_vec();
+    sorted.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    sorted.dedup();
+    if sorted.len() < 3 {
+        return 0.0;
+    }
+
+    // The lower and upper halves each end where the other starts
+    let mut hull = half_hull(sorted.iter());
+    hull.pop();
+    let mut upper = half_hull(sorted.iter().rev());
+    upper.pop();
+    hull.extend(upper);
+
+    let mut area = 0.0;
+    for i in 0..hull.len() {
+        let (a, b) = (hull[i], hull[(i + 1) % hull.len()]);
+        area += a.x * b.y - b.x * a.y;
+    }
+    area.abs() * 0.5
+}
+
+/// Points of a convex hull turning left, from sorted points
+fn half_hull<'a>(points: impl Iterator<Item = &'a Point2<f32>>) -> Vec<Point2<f32>> {
+    let mut hull: Vec<Point2<f32>> = Vec::new();
+    for point in points {
+        while hull.len() >= 2 && (hull[hull.len() - 1] - hull[hull.len() - 2]).perp(&(point - hull[hull.len() - 2])) <= 0.0 {
+            hull.pop();
+        }
+        hull.push(*point);
+    }
+    hull
+}
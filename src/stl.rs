@@ -0,0 +1,82 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::tree::MeshData;
+
+/// Write a mesh as a binary STL file
+///
+/// STL only stores triangles with a facet normal, computed from the winding of each triangle.
+///
+/// # Arguments
+///
+/// * `mesh` - Mesh to write, in the tree's frame
+/// * `output_path` - Path of the STL file
+pub fn write_stl(mesh: &MeshData, output_path: &Path) -> Result<(), Box<dyn Error>> {
+    let (vertices, indices, _, _) = mesh;
+
+    let mut stl = BufWriter::new(File::create(output_path)?);
+    let mut header = [0u8; 80];
+    let title = b"Generated by tree-maker";
+    header[..title.len()].copy_from_slice(title);
+    stl.write_all(&header)?;
+    stl.write_all(&(indices.len() as u32).to_le_bytes())?;
+
+    for triangle in indices {
+        let (a, b, c) = (
+            vertices[triangle.a as usize],
+            vertices[triangle.b as usize],
+            vertices[triangle.c as usize],
+        );
+        let normal = (b - a).cross(&(c - a)).try_normalize(1.0e-12).unwrap_or_default();
+        for value in [normal.x, normal.y, normal.z, a.x, a.y, a.z, b.x, b.y, b.z, c.x, c.y, c.z] {
+            stl.write_all(&value.to_le_bytes())?;
+        }
+        // Attribute byte count, unused
+        stl.write_all(&0u16.to_le_bytes())?;
+    }
+
+    stl.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::{Branch, Tree};
+    use nalgebra::Vector3;
+    use std::fs;
+
+    #[test]
+    fn facets_face_outward() {
+        let tree = Tree { branches: vec![Branch::straight(0, None, None, 3)], ..Default::default() };
+        let mesh = tree.branch_mesh(0);
+        let path = std::env::temp_dir().join(format!("tree-maker-{}-facets.stl", std::process::id()));
+        write_stl(&mesh, &path).unwrap();
+        let stl = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(stl.starts_with(b"Generated by tree-maker"));
+        let count = u32::from_le_bytes(stl[80..84].try_into().unwrap()) as usize;
+        assert_eq!(count, mesh.1.len());
+        assert_eq!(stl.len(), 84 + 50 * count);
+
+        for facet in stl[84..].chunks(50) {
+            let values: Vec<f32> = facet[..48].chunks(4).map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap())).collect();
+            let normal = Vector3::new(values[0], values[1], values[2]);
+            let center = (Vector3::new(values[3], values[4], values[5])
+                + Vector3::new(values[6], values[7], values[8])
+                + Vector3::new(values[9], values[10], values[11]))
+                / 3.0;
+            // The caps at both ends face along the branch, the sides away from its axis
+            let outward = match center.z {
+                z if z < 1.0e-5 => -Vector3::z(),
+                z if z > 2.0 - 1.0e-5 => Vector3::z(),
+                _ => Vector3::new(center.x, center.y, 0.0),
+            };
+            assert!((normal.norm() - 1.0).abs() < 1.0e-5);
+            assert!(normal.dot(&outward) > 0.0, "facet at {:?} faces {:?}", center, normal);
+        }
+    }
+}
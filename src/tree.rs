@@ -1,31 +1,69 @@
 use mesh_tools::{GltfBuilder, Triangle};
-use nalgebra::{Point3, Vector3, Vector2, Quaternion, UnitQuaternion, Unit, UnitVector3, Matrix3, Rotation3};
+use nalgebra::{Point3, Vector3, Vector2, Quaternion, UnitQuaternion, Unit, UnitVector3, Matrix3, Rotation3, Isometry3, Translation3};
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::path::Path;
 use std::f32::consts::PI;
 
-use crate::BranchConfig;
+use crate::{BranchConfig, BranchParams, Force, GrowthConfig, LeavesConfig, Param, RootsConfig, TreeConfig, WindMode};
+use crate::gltf::{GlbDocument, TempGlb};
+use crate::skin::add_skin;
+use crate::growth::tree_at_age;
+use crate::sag::sag_tree;
+use crate::format::OutputFormat;
+use crate::forest::TreePlacement;
+use crate::bounds::add_bounds;
+use crate::orientation::{orient_root, set_node_frame, ExportFrame};
+use crate::collision::{add_colliders, Collider, build_colliders, colliders_path, write_colliders_json};
+use crate::impostor::add_impostor;
+use crate::leaves::{add_leaf_instances, leaf_prototype, leaves_in_tree_frame, place_leaves, LeafInstance, LeafNodes};
+use crate::obj::write_obj;
+use crate::usd::write_usda;
+use crate::ply::write_ply;
+use crate::stl::write_stl;
+use crate::weld::solid_mesh;
+use crate::skeleton::{write_skeleton_json, write_skeleton_obj};
+use crate::wind::{add_wind_animation, add_wind_vertex_attributes};
+
+/// Color of the trunk and branches material
+pub const TRUNK_COLOR: [f32; 4] = [0.55, 0.27, 0.07, 1.0]; // Brown
+/// Color of the leaves material
+pub const LEAVES_COLOR: [f32; 4] = [0.1, 0.6, 0.1, 1.0]; // Green
+/// Thinnest tip of a branch continued by a leader, relative to its start radius
+const LEADER_MIN_RADIUS_RATIO: f32 = 0.2;
 
 // Common tree generation logic
 struct TreeGenerator {
+    seed: u64,
     rng: ChaCha8Rng,
     builder: GltfBuilder,
 }
 
+/// Materials shared by the meshes of a tree
+struct Materials {
+    trunk: usize,
+    leaves: usize,
+    roots: Option<usize>,
+}
+
 impl TreeGenerator {
     fn new(seed: Option<u64>) -> Self {
-        let rng = match seed {
-            Some(s) => ChaCha8Rng::seed_from_u64(s),
-            None => ChaCha8Rng::from_entropy(),
-        };
+        let seed = seed.unwrap_or_else(rand::random);
 
         Self {
-            rng,
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
             builder: GltfBuilder::new(),
         }
     }
+    
+    /// Random number generator of the leaves, a separate stream so they don't change the branches
+    /// and stay in place across a growth sequence
+    fn leaves_rng(&self) -> ChaCha8Rng {
+        leaves_rng(self.seed)
+    }
 
     fn random_f32(&mut self, min: f32, max: f32) -> f32 {
         // Handle the case where min == max to avoid the 'cannot sample empty range' error
@@ -43,7 +81,14 @@ impl TreeGenerator {
     fn create_trunk_material(&mut self) -> usize {
         self.builder.create_basic_material(
             Some("Trunk".to_string().into()),
-            [0.55, 0.27, 0.07, 1.0], // Brown
+            TRUNK_COLOR,
+        )
+    }
+
+    fn create_roots_material(&mut self, color: [f32; 4]) -> usize {
+        self.builder.create_basic_material(
+            Some("Roots".to_string()),
+            color,
         )
     }
 
@@ -177,111 +222,1128 @@ pub fn branch_maker(start_radius: f32, end_radius: f32, height: f32, height_segm
     (vertices, indices, normals, uvs)
 }
 
+/// What a branch hierarchy grows: the tree above ground or its roots
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BranchKind {
+    Branch,
+    Root,
+}
+
+impl BranchKind {
+    /// Prefix used for mesh and node names
+    pub fn name(&self) -> &'static str {
+        match self {
+            BranchKind::Branch => "Branch",
+            BranchKind::Root => "Root",
+        }
+    }
+}
+
+/// A single generated branch of the tree skeleton
+#[derive(Debug, Clone)]
+pub struct Branch {
+    /// Whether the branch grows above ground or is a root
+    pub kind: BranchKind,
+    /// Depth in the hierarchy (0 = trunk or main root)
+    pub level: u32,
+    /// Index of the parent branch in `Tree::branches`
+    pub parent: Option<usize>,
+    /// Index of the parent's transform the branch is attached to
+    pub attachment_index: Option<usize>,
+    /// Position of the branch base in the parent's frame
+    pub position: Point3<f32>,
+    /// Rotation of the branch in the parent's frame
+    pub rotation: UnitQuaternion<f32>,
+    /// Path of the branch in its own frame
+    pub transforms: Vec<BranchTransform>,
+    /// Radius of the ring at each transform
+    pub radii: Vec<f32>,
+    /// Number of segments around the branch circumference
+    pub radial_segments: u32,
+    /// Amount of random variation (0.0-1.0) applied to the vertices
+    pub noise_level: f32,
+    /// Seed of the vertex noise, so the branch looks the same every time it's meshed
+    pub noise_seed: u64,
+}
+
+impl Branch {
+    /// Transform from the branch's own frame to its parent's frame
+    pub fn local_transform(&self) -> Isometry3<f32> {
+        Isometry3::from_parts(Translation3::from(self.position.coords), self.rotation)
+    }
+}
+
+#[cfg(test)]
+impl Branch {
+    /// A straight branch for tests, with rings one unit apart along +Z and a radius of 0.1
+    pub(crate) fn straight(level: u32, parent: Option<usize>, attachment_index: Option<usize>, rings: usize) -> Self {
+        Branch {
+            kind: BranchKind::Branch,
+            level,
+            parent,
+            attachment_index,
+            position: Point3::origin(),
+            rotation: UnitQuaternion::identity(),
+            transforms: (0..rings)
+                .map(|i| BranchTransform {
+                    position: [0.0, 0.0, i as f32],
+                    rotation: [0.0, 0.0, 0.0, 1.0],
+                })
+                .collect(),
+            radii: vec![0.1; rings],
+            radial_segments: 8,
+            noise_level: 0.0,
+            noise_seed: 0,
+        }
+    }
+}
+
+/// The generated skeleton of a tree, parents are always stored before their children
+#[derive(Debug, Clone, Default)]
+pub struct Tree {
+    pub branches: Vec<Branch>,
+    /// Height of the ground plane the roots are clipped to
+    pub ground_clip: Option<f32>,
+    /// Largest bend between two meshed rings in radians, every ring is meshed when unset
+    pub ring_angle: Option<f32>,
+}
+
+impl Tree {
+    /// Transform from a branch's own frame to the tree's frame
+    pub fn world_transform(&self, index: usize) -> Isometry3<f32> {
+        let branch = &self.branches[index];
+        match branch.parent {
+            Some(parent) => self.world_transform(parent) * branch.local_transform(),
+            None => branch.local_transform(),
+        }
+    }
+
+    /// Names of the branch nodes and prims, unique within the tree
+    ///
+    /// The first trunk is "Trunk". A branch continuing its parent from the tip at the same level is
+    /// named after it: a single one is its leader ("Trunk_leader"), several are the sub-axes of a
+    /// fork ("Trunk_fork1", "Trunk_fork2"...). The others are named after their kind, level and index.
+    pub fn branch_names(&self) -> Vec<String> {
+        let continued_parent = |index: usize| {
+            let branch = &self.branches[index];
+            let (parent, attachment) = (branch.parent?, branch.attachment_index?);
+            let parent_branch = &self.branches[parent];
+            let continues = parent_branch.kind == branch.kind
+                && parent_branch.level == branch.level
+                && attachment + 1 >= parent_branch.transforms.len();
+            continues.then_some(parent)
+        };
+        let mut continuations = vec![0; self.branches.len()];
+        for index in 0..self.branches.len() {
+            if let Some(parent) = continued_parent(index) {
+                continuations[parent] += 1;
+            }
+        }
+
+        let mut forks = vec![0; self.branches.len()];
+        let mut has_trunk = false;
+        let mut names: Vec<String> = Vec::with_capacity(self.branches.len());
+        for (index, branch) in self.branches.iter().enumerate() {
+            let name = match continued_parent(index) {
+                Some(parent) if continuations[parent] == 1 => format!("{}_leader", names[parent]),
+                Some(parent) => {
+                    forks[parent] += 1;
+                    format!("{}_fork{}", names[parent], forks[parent])
+                }
+                None if branch.kind == BranchKind::Branch && branch.parent.is_none() && !has_trunk => {
+                    has_trunk = true;
+                    "Trunk".to_string()
+                }
+                None => format!("{}_L{}_{}", branch.kind.name(), branch.level, index),
+            };
+            names.push(name);
+        }
+        names
+    }
+
+    /// Build the mesh of a branch in its own frame
+    pub fn branch_mesh(&self, index: usize) -> MeshData {
+        let branch = &self.branches[index];
+        let (mut vertices, indices, normals, uvs) = match self.ring_angle {
+            Some(max_angle) => adaptive_branch_mesh(branch, max_angle),
+            None => create_transform_based_mesh(
+                &branch.transforms,
+                &branch.radii,
+                branch.radial_segments as usize,
+                branch.noise_level,
+                branch.noise_seed,
+            ),
+        };
+        
+        if let (BranchKind::Root, Some(ground_height)) = (branch.kind, self.ground_clip) {
+            clip_to_ground(&mut vertices, &self.world_transform(index), ground_height);
+        }
+        
+        (vertices, indices, normals, uvs)
+    }
+
+    /// Build the mesh of a branch in the tree's frame
+    pub fn world_branch_mesh(&self, index: usize) -> MeshData {
+        let world = self.world_transform(index);
+        let (vertices, indices, normals, uvs) = self.branch_mesh(index);
+        (
+            vertices.iter().map(|vertex| world * vertex).collect(),
+            indices,
+            normals.iter().map(|normal| world.rotation * normal).collect(),
+            uvs,
+        )
+    }
+
+    /// Merge the meshes of every branch of one kind into a single mesh in the tree's frame
+    ///
+    /// Also returns the index of the branch each vertex comes from.
+    pub fn merged_mesh(&self, kind: BranchKind) -> (MeshData, Vec<usize>) {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut normals = Vec::new();
+        let mut uvs = Vec::new();
+        let mut vertex_branches = Vec::new();
+        
+        for (index, branch) in self.branches.iter().enumerate() {
+            if branch.kind != kind {
+                continue;
+            }
+            
+            let (branch_vertices, branch_indices, branch_normals, branch_uvs) = self.world_branch_mesh(index);
+            let offset = vertices.len() as u32;
+            
+            vertices.extend(branch_vertices);
+            normals.extend(branch_normals);
+            uvs.extend(branch_uvs);
+            indices.extend(branch_indices.iter().map(|triangle| {
+                Triangle::new(triangle.a + offset, triangle.b + offset, triangle.c + offset)
+            }));
+            vertex_branches.resize(vertices.len(), index);
+        }
+        
+        ((vertices, indices, normals, uvs), vertex_branches)
+    }
+}
+
+/// A mesh merging several branches, with the branch and position along it of every vertex
+#[derive(Debug, Clone)]
+pub struct MergedMesh {
+    /// Index of the mesh in the exported document
+    pub mesh: usize,
+    /// Index of the node holding the mesh
+    pub node: usize,
+    /// Branch each vertex comes from
+    pub vertex_branches: Vec<usize>,
+    /// Distance of each vertex from the base of its branch, normalized 0..1
+    pub vertex_distances: Vec<f32>,
+}
+
+/// Mesh data as (vertices, indices, normals, uvs)
+pub type MeshData = (Vec<Point3<f32>>, Vec<Triangle>, Vec<Vector3<f32>>, Vec<[f32; 2]>);
+
+/// A tree as written to the output, the last frame of a growth sequence
+#[derive(Debug, Clone)]
+pub struct ExportedTree {
+    pub tree: Tree,
+    /// Seed the tree was generated with, which also places its leaves
+    pub seed: u64,
+}
+
+impl ExportedTree {
+    /// Leaves of the tree in the tree's frame, as they were exported
+    pub fn leaves(&self, config: &TreeConfig) -> Vec<LeafInstance> {
+        match &config.leaves {
+            Some(leaves) => leaves_in_tree_frame(&self.tree, &place_leaves(&self.tree, leaves, &mut leaves_rng(self.seed))),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Random number generator of the leaves for a seed, on its own stream
+fn leaves_rng(seed: u64) -> ChaCha8Rng {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    rng.set_stream(1);
+    rng
+}
+
+/// Settings shared by every branch of one hierarchy
+struct HierarchyStyle<'a> {
+    kind: BranchKind,
+    taper: f32,
+    force: Option<&'a Force>,
+}
+
 pub fn generate_tree(
-    config: BranchConfig,
+    config: &TreeConfig,
     seed: Option<u64>,
     output_path: Option<&Path>,
 ) -> Result<(), Box<dyn Error>> {
+    generate_tree_with_format(config, seed, output_path, None)?;
+    Ok(())
+}
+
+/// Generate a tree and export it in the given format, or the one matching the output file extension
+pub fn generate_tree_with_format(
+    config: &TreeConfig,
+    seed: Option<u64>,
+    output_path: Option<&Path>,
+    format: Option<OutputFormat>,
+) -> Result<ExportedTree, Box<dyn Error>> {
     let mut generator = TreeGenerator::new(seed);
     
-    // Create materials
-    let trunk_material = generator.create_trunk_material();
-    let leaves_material = generator.create_leaf_material([0.1, 0.6, 0.1, 1.0]); // Green
-    
-    // Create a root node for the tree
-    let root_node = generator.builder.add_node(
-        Some("Tree".to_string()),
-        None,
-        None,
-        None,
-        None
-    );
+    // Grow the skeleton first, the meshes are built from it afterwards
+    let tree = generator.grow(config);
+    let tree = generator.write_outputs(&tree, config, output_path, format)?;
+    Ok(ExportedTree { tree, seed: generator.seed })
+}
+
+/// Mesh and export a skeleton that wasn't grown from the config, such as an imported scan
+///
+/// The config still provides the materials, animation and export settings; its branch
+/// parameters are not used.
+pub fn mesh_tree(
+    tree: &Tree,
+    config: &TreeConfig,
+    seed: Option<u64>,
+    output_path: Option<&Path>,
+    format: Option<OutputFormat>,
+) -> Result<ExportedTree, Box<dyn Error>> {
+    let mut generator = TreeGenerator::new(seed);
+    let mut tree = tree.clone();
+    tree.ring_angle = config.adaptive_segments.map(|adaptive| adaptive.angle.to_radians());
+    let tree = generator.write_outputs(&tree, config, output_path, format)?;
+    Ok(ExportedTree { tree, seed: generator.seed })
+}
+
+/// Generate every tree of a forest into one GLB, each under its own node placed on the ground
+///
+/// The trees share one set of materials, and the trees with the same leaf shape and color share a
+/// prototype leaf. Wind, skin, colliders and growth sequences need a file per tree and are not
+/// written; a growth age is applied to every tree.
+pub fn generate_forest_glb(placements: &[TreePlacement], output_path: &Path) -> Result<(), Box<dyn Error>> {
+    let Some(first) = placements.first() else {
+        return Err("The forest has no trees".into());
+    };
+    if placements.iter().any(|placement| {
+        let config = &placement.config;
+        config.wind.is_some() || config.skin.is_some() || config.impostor.is_some() || config.collision.is_some()
+    }) {
+        println!("Wind, skin, impostors and colliders are only exported when the forest is written to a folder and are skipped");
+    }
     
-    // Start recursive branch generation from the trunk
-    generate_branch_hierarchy(
-        &mut generator, 
-        &config, 
-        Some(root_node), // Root node as parent
-        Point3::new(0.0, 0.0, 0.0), // Root position
-        trunk_material,
-        leaves_material,
-        0 // Level 0 = trunk
-    );
+    let mut generator = TreeGenerator::new(Some(first.seed));
+    let materials = generator.create_materials(&first.config);
+    let materials_leaf_color = first.config.leaves.as_ref().map_or(LEAVES_COLOR, |leaves| leaves.color);
+    let mut leaf_prototypes: Vec<(LeavesConfig, LeafNodes)> = Vec::new();
+    let forest_node = generator.builder.add_node(Some("Forest".to_string()), None, None, None, None);
     
-    // Create a scene with the root node
-    generator.builder.add_scene(Some("Tree".to_string()), Some(vec![root_node]));
+    for (index, placement) in placements.iter().enumerate() {
+        // Every tree grows from its own seed, whatever was grown before it
+        generator.seed = placement.seed;
+        generator.rng = ChaCha8Rng::seed_from_u64(placement.seed);
+        let mut tree = generator.grow(&placement.config);
+        if let Some(growth) = placement.config.growth {
+            tree = tree_at_age(&tree, growth.age);
+        }
+        if let Some(sag) = &placement.config.sag {
+            tree = sag_tree(&tree, sag);
+        }
+        
+        let quat = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), placement.rotation).into_inner();
+        let tree_node = generator.builder.add_node(
+            Some(format!("Tree_{:03}", index)),
+            None,
+            Some(placement.position),
+            Some([quat.i, quat.j, quat.k, quat.w]),
+            None
+        );
+        generator.builder.add_child_to_node(forest_node, tree_node);
+        let branch_nodes = generator.add_branch_nodes(&tree, tree_node, &materials);
+        
+        if let Some(leaves) = &placement.config.leaves {
+            let same_look = |(look, _): &(LeavesConfig, LeafNodes)| {
+                look.shape == leaves.shape && look.billboard == leaves.billboard && look.color == leaves.color
+            };
+            let prototype = match leaf_prototypes.iter().position(same_look) {
+                Some(prototype) => prototype,
+                None => {
+                    let material = if leaves.color == materials_leaf_color {
+                        materials.leaves
+                    } else {
+                        generator.create_leaf_material(leaves.color)
+                    };
+                    let mesh = generator.create_leaf_mesh(leaves, material);
+                    leaf_prototypes.push((*leaves, LeafNodes { mesh, nodes: Vec::new() }));
+                    leaf_prototypes.len() - 1
+                }
+            };
+            let nodes = generator.add_branch_leaves(&tree, leaves, leaf_prototypes[prototype].1.mesh, &branch_nodes);
+            leaf_prototypes[prototype].1.nodes.extend(nodes);
+        }
+    }
     
-    // Use the provided output path or default to "tree.glb"
-    let output = match output_path {
-        Some(path) => path.to_path_buf(),
-        None => std::path::PathBuf::from("tree.glb"),
-    };
+    generator.builder.add_scene(Some("Forest".to_string()), Some(vec![forest_node]));
+    generator.export(output_path)?;
     
-    generator.export(&output)?;
-    println!("Tree generated and saved to: {}", output.display());
+    // The leaf copies and bounds are written into the exported file
+    let mut document = GlbDocument::read(output_path)?;
+    for (_, leaves) in &leaf_prototypes {
+        add_leaf_instances(&mut document, leaves);
+    }
+    if let Some(export) = &first.config.export {
+        if let Some(forest) = document.json["scenes"][0]["nodes"][0].as_u64() {
+            set_node_frame(&mut document, forest as usize, &ExportFrame::new(Some(export), Point3::origin()));
+        }
+    }
+    add_bounds(&mut document);
+    document.write_glb(output_path)?;
     
     Ok(())
 }
 
+/// The document being edited, or the exported file read back if nothing was added to it yet
+fn exported_document(document: Option<GlbDocument>, glb_output: &Path) -> Result<GlbDocument, Box<dyn Error>> {
+    match document {
+        Some(document) => Ok(document),
+        None => GlbDocument::read(glb_output),
+    }
+}
+
+/// Path of a file in a growth sequence: `tree.glb` becomes `tree_000.glb`, `tree_001.glb`...
+fn growth_frame_path(output: &Path, frame: u32) -> std::path::PathBuf {
+    let stem = output.file_stem().and_then(|stem| stem.to_str()).unwrap_or("tree");
+    let extension = output.extension().and_then(|extension| extension.to_str()).unwrap_or("glb");
+    output.with_file_name(format!("{}_{:03}.{}", stem, frame, extension))
+}
+
+impl TreeGenerator {
+    /// Export a grown tree to the output file, or to a sequence of files when showing it grow
+    ///
+    /// Returns the exported tree, the last one of a sequence.
+    fn write_outputs(
+        &mut self,
+        tree: &Tree,
+        config: &TreeConfig,
+        output_path: Option<&Path>,
+        format: Option<OutputFormat>,
+    ) -> Result<Tree, Box<dyn Error>> {
+        // Use the provided output path or default to "tree.glb"
+        let output = match output_path {
+            Some(path) => path.to_path_buf(),
+            None => std::path::PathBuf::from("tree.glb"),
+        };
+        let format = match format {
+            Some(format) => format,
+            None => OutputFormat::from_path(&output)?,
+        };
+        
+        // Each exported tree sags under the weight it has at its age
+        let sagged = |tree: Tree| match &config.sag {
+            Some(sag) => sag_tree(&tree, sag),
+            None => tree,
+        };
+        
+        match config.growth {
+            // A sequence of files showing the tree growing, numbered after the output file
+            Some(GrowthConfig { age, end_age: Some(end_age), frames }) => {
+                let frames = frames.max(1);
+                for frame in 0..frames {
+                    let frame_age = if frames > 1 {
+                        age + (end_age - age) * frame as f32 / (frames - 1) as f32
+                    } else {
+                        age
+                    };
+                    let frame_output = growth_frame_path(&output, frame);
+                    
+                    // Every file needs its own builder
+                    self.builder = GltfBuilder::new();
+                    self.export_tree(&sagged(tree_at_age(tree, frame_age)), config, format, &frame_output)?;
+                    println!("Growth frame {} at age {:.3} saved to: {}", frame, frame_age, frame_output.display());
+                }
+                Ok(sagged(tree_at_age(tree, if frames > 1 { end_age } else { age })))
+            }
+            Some(GrowthConfig { age, .. }) => {
+                let exported = sagged(tree_at_age(tree, age));
+                self.export_tree(&exported, config, format, &output)?;
+                println!("Tree generated and saved to: {}", output.display());
+                Ok(exported)
+            }
+            None => {
+                let exported = sagged(tree.clone());
+                self.export_tree(&exported, config, format, &output)?;
+                println!("Tree generated and saved to: {}", output.display());
+                Ok(exported)
+            }
+        }
+    }
+    
+    /// Build the scene of a grown tree, export it and add what mesh-tools doesn't write
+    fn export_tree(
+        &mut self,
+        tree: &Tree,
+        config: &TreeConfig,
+        format: OutputFormat,
+        output: &Path,
+    ) -> Result<(), Box<dyn Error>> {
+        // The other formats have no root node to carry the export settings, so they're baked in
+        let frame = ExportFrame::of_tree(tree, config.export.as_ref());
+        
+        // The colliders are written next to every format, and into glTF files below
+        let colliders = match &config.collision {
+            Some(collision) => {
+                let colliders = build_colliders(tree, collision);
+                let path = colliders_path(output);
+                let exported: Vec<Collider> = colliders
+                    .iter()
+                    .map(|collider| Collider {
+                        start: frame.point(&collider.start.into()).into(),
+                        end: frame.point(&collider.end.into()).into(),
+                        start_radius: frame.length(collider.start_radius),
+                        end_radius: frame.length(collider.end_radius),
+                        ..collider.clone()
+                    })
+                    .collect();
+                write_colliders_json(&exported, collision, &path)?;
+                println!("Wrote {} colliders to {}", colliders.len(), path.display());
+                Some((colliders, collision))
+            }
+            None => None,
+        };
+        
+        // The other formats only hold the geometry and materials, or just the skeleton
+        if !matches!(format, OutputFormat::Glb | OutputFormat::Gltf) {
+            if config.wind.is_some() || config.skin.is_some() {
+                println!("Wind and skin are only exported to glTF and are skipped");
+            }
+            if config.leaves.is_some() && !matches!(format, OutputFormat::SkeletonJson | OutputFormat::SkeletonObj | OutputFormat::Usda) {
+                println!("Leaves are only exported to glTF and USD and are skipped");
+            }
+            if config.impostor.is_some() {
+                println!("Impostors are only exported to glTF and are skipped");
+            }
+            return match format {
+                OutputFormat::SkeletonJson => write_skeleton_json(tree, output),
+                OutputFormat::SkeletonObj => write_skeleton_obj(tree, &frame, output),
+                OutputFormat::Usda => {
+                    let leaves = match &config.leaves {
+                        Some(leaves) => leaves_in_tree_frame(tree, &place_leaves(tree, leaves, &mut self.leaves_rng())),
+                        None => Vec::new(),
+                    };
+                    write_usda(tree, config, &leaves, output)
+                }
+                OutputFormat::Ply => write_ply(&frame.mesh(solid_mesh(tree, config.weld.as_ref())), output),
+                OutputFormat::Stl => write_stl(&frame.mesh(solid_mesh(tree, config.weld.as_ref())), output),
+                _ => write_obj(tree, config, output),
+            };
+        }
+        
+        // Text glTF is converted from a GLB exported to a temporary file
+        let temp_glb = matches!(format, OutputFormat::Gltf).then(TempGlb::in_temp_dir);
+        let glb_output = match &temp_glb {
+            Some(temp_glb) => temp_glb.path().to_path_buf(),
+            None => output.to_path_buf(),
+        };
+        
+        // Shader-driven wind and skinning need the branches merged into one mesh
+        let vertex_wind = config.wind.filter(|wind| wind.mode == WindMode::Vertex);
+        let (document, leaves) = if vertex_wind.is_some() || config.skin.is_some() {
+            let (root_node, merged_meshes, leaves) = self.build_merged_scene(tree, config);
+            self.export(&glb_output)?;
+            
+            let mut document = GlbDocument::read(&glb_output)?;
+            if vertex_wind.is_some() {
+                for merged_mesh in &merged_meshes {
+                    add_wind_vertex_attributes(&mut document, tree, merged_mesh);
+                }
+            } else if config.wind.is_some() {
+                println!("The wind animation needs a node per branch and is skipped with a skin, use the vertex wind mode instead");
+            }
+            if let Some(skin) = &config.skin {
+                add_skin(&mut document, tree, root_node, &merged_meshes, skin)?;
+            }
+            (Some(document), leaves)
+        } else {
+            let (branch_nodes, leaves) = self.build_scene(tree, config);
+            self.export(&glb_output)?;
+            
+            // Add the wind animation to the exported file
+            let document = match &config.wind {
+                Some(wind) => {
+                    let mut document = GlbDocument::read(&glb_output)?;
+                    add_wind_animation(&mut document, tree, &branch_nodes, wind);
+                    Some(document)
+                }
+                None => None,
+            };
+            (document, leaves)
+        };
+        
+        // The leaf copies are written as instancing attributes
+        let document = match leaves {
+            Some(leaves) => {
+                let mut document = exported_document(document, &glb_output)?;
+                add_leaf_instances(&mut document, &leaves);
+                Some(document)
+            }
+            None => document,
+        };
+        
+        // The impostor is rendered from the same leaves as the exported ones
+        let document = match &config.impostor {
+            Some(impostor) => {
+                let leaves = match &config.leaves {
+                    Some(leaves) => leaves_in_tree_frame(tree, &place_leaves(tree, leaves, &mut self.leaves_rng())),
+                    None => Vec::new(),
+                };
+                let mut document = exported_document(document, &glb_output)?;
+                add_impostor(&mut document, tree, config, &leaves, impostor)?;
+                Some(document)
+            }
+            None => document,
+        };
+        
+        let document = match colliders {
+            Some((colliders, collision)) => {
+                let mut document = exported_document(document, &glb_output)?;
+                if let Some(root_node) = document.json["scenes"][0]["nodes"][0].as_u64() {
+                    add_colliders(&mut document, root_node as usize, &colliders, collision);
+                }
+                Some(document)
+            }
+            None => document,
+        };
+        
+        // The bounds go last, once every node is in place
+        let mut document = exported_document(document, &glb_output)?;
+        if let Some(export) = &config.export {
+            orient_root(&mut document, tree, export);
+        }
+        add_bounds(&mut document);
+        
+        match format {
+            OutputFormat::Gltf => document.write_gltf(output)?,
+            _ => document.write_glb(output)?,
+        }
+        
+        Ok(())
+    }
+    
+    /// Grow the skeleton of the tree and its roots
+    fn grow(&mut self, config: &TreeConfig) -> Tree {
+        let mut tree = Tree {
+            branches: Vec::new(),
+            ground_clip: config.roots.as_ref().and_then(|roots| roots.ground_clip),
+            ring_angle: config.adaptive_segments.map(|adaptive| adaptive.angle.to_radians()),
+        };
+        
+        // Start recursive branch generation from the trunk
+        let trunk_style = HierarchyStyle {
+            kind: BranchKind::Branch,
+            taper: 1.0,
+            force: None,
+        };
+        generate_branch_hierarchy(
+            self,
+            &mut tree,
+            &config.trunk,
+            &trunk_style,
+            None, // No parent branch
+            &Isometry3::identity(), // Root frame
+            Point3::new(0.0, 0.0, 0.0), // Root position
+            None, // Random rotation
+            None, // Not attached to a parent
+            0 // Level 0 = trunk
+        );
+        
+        // Grow the roots downward and outward from the trunk base
+        if let Some(roots) = &config.roots {
+            generate_roots(self, &mut tree, roots);
+        }
+        
+        tree
+    }
+    
+    /// Create the trunk, leaves and (if the tree has roots) roots materials
+    fn create_materials(&mut self, config: &TreeConfig) -> Materials {
+        let trunk = self.create_trunk_material();
+        let leaves = self.create_leaf_material(config.leaves.as_ref().map_or(LEAVES_COLOR, |leaves| leaves.color));
+        let roots = config.roots.as_ref().map(|roots| self.create_roots_material(roots.color));
+        Materials { trunk, leaves, roots }
+    }
+    
+    /// Create the prototype mesh every leaf is a copy of
+    fn create_leaf_mesh(&mut self, leaves: &LeavesConfig, material: usize) -> usize {
+        let (vertices, indices, normals, uvs) = leaf_prototype(leaves);
+        let uvs_vector: Vec<Vector2<f32>> = uvs.iter().map(|uv| Vector2::new(uv[0], uv[1])).collect();
+        self.builder.create_custom_mesh(
+            Some("Leaf".to_string()),
+            &vertices,
+            &indices,
+            Some(normals),
+            Some(vec![uvs_vector]),
+            Some(material)
+        )
+    }
+    
+    /// Create a node instancing the leaf mesh under each outermost branch node, so the leaves
+    /// follow the branches when they sway
+    fn add_branch_leaves(
+        &mut self,
+        tree: &Tree,
+        leaves: &LeavesConfig,
+        mesh: usize,
+        branch_nodes: &[usize],
+    ) -> Vec<(usize, Vec<LeafInstance>)> {
+        let placed = place_leaves(tree, leaves, &mut self.leaves_rng());
+        placed
+            .into_iter()
+            .map(|(branch, instances)| {
+                let node = self.builder.add_node(Some(format!("Leaves_{}", branch)), Some(mesh), None, None, None);
+                self.builder.add_child_to_node(branch_nodes[branch], node);
+                (node, instances)
+            })
+            .collect()
+    }
+    
+    /// Create the root node of the tree
+    fn create_root_node(&mut self) -> usize {
+        self.builder.add_node(
+            Some("Tree".to_string()),
+            None,
+            None,
+            None,
+            None
+        )
+    }
+    
+    /// Create the materials, meshes and nodes of the tree, returning the node of each branch and
+    /// the leaf nodes
+    fn build_scene(&mut self, tree: &Tree, config: &TreeConfig) -> (Vec<usize>, Option<LeafNodes>) {
+        // Create materials
+        let materials = self.create_materials(config);
+        
+        // Create a root node for the tree
+        let root_node = self.create_root_node();
+        let branch_nodes = self.add_branch_nodes(tree, root_node, &materials);
+        
+        let leaves = config.leaves.as_ref().map(|leaves| {
+            let mesh = self.create_leaf_mesh(leaves, materials.leaves);
+            let nodes = self.add_branch_leaves(tree, leaves, mesh, &branch_nodes);
+            LeafNodes { mesh, nodes }
+        });
+        
+        // Create a scene with the root node
+        self.builder.add_scene(Some("Tree".to_string()), Some(vec![root_node]));
+        
+        (branch_nodes, leaves)
+    }
+    
+    /// Create the mesh and node of every branch under the given tree node, returning the node of each branch
+    fn add_branch_nodes(
+        &mut self,
+        tree: &Tree,
+        root_node: usize,
+        materials: &Materials,
+    ) -> Vec<usize> {
+        let mut branch_nodes: Vec<usize> = Vec::with_capacity(tree.branches.len());
+        let names = tree.branch_names();
+        for (index, branch) in tree.branches.iter().enumerate() {
+            let (vertices, indices, normals, uvs) = tree.branch_mesh(index);
+            
+            // Convert UVs from [f32; 2] to Vector2<f32>
+            let uvs_vector: Vec<Vector2<f32>> = uvs.iter().map(|uv| Vector2::new(uv[0], uv[1])).collect();
+            
+            let material = match branch.kind {
+                BranchKind::Branch => materials.trunk,
+                BranchKind::Root => materials.roots.unwrap_or(materials.trunk),
+            };
+            
+            // Create custom mesh for the branch
+            let mesh_id = self.builder.create_custom_mesh(
+                Some(format!("{}_L{}", branch.kind.name(), branch.level)),
+                &vertices,
+                &indices,
+                Some(normals),
+                Some(vec![uvs_vector]),     // UVs in the format expected by the API
+                Some(material)              // Material
+            );
+            
+            // Extract quaternion components in the order expected by GLTF (x, y, z, w)
+            let quat = branch.rotation.into_inner();
+            let gltf_rotation = [quat.i, quat.j, quat.k, quat.w];
+            
+            let branch_node = self.builder.add_node(
+                Some(names[index].clone()),
+                Some(mesh_id),
+                Some(branch.position.into()),
+                Some(gltf_rotation),
+                None  // No scaling
+            );
+            
+            // Connect to the parent branch, or to the tree root for the trunk and main roots
+            let parent_node = branch.parent.map_or(root_node, |parent| branch_nodes[parent]);
+            self.builder.add_child_to_node(parent_node, branch_node);
+            branch_nodes.push(branch_node);
+        }
+        
+        branch_nodes
+    }
+    
+    /// Create the materials and one merged mesh per material (branches, roots) under the tree root node
+    ///
+    /// Returns the tree root node, the merged meshes and the leaf node, which holds every leaf in
+    /// the tree's frame.
+    fn build_merged_scene(&mut self, tree: &Tree, config: &TreeConfig) -> (usize, Vec<MergedMesh>, Option<LeafNodes>) {
+        let materials = self.create_materials(config);
+        let root_node = self.create_root_node();
+        
+        let mut merged_meshes = Vec::new();
+        let parts = [
+            ("Branches", BranchKind::Branch, Some(materials.trunk)),
+            ("Roots", BranchKind::Root, materials.roots),
+        ];
+        for (name, kind, material) in parts {
+            let Some(material) = material else { continue };
+            let ((vertices, indices, normals, uvs), vertex_branches) = tree.merged_mesh(kind);
+            if vertices.is_empty() {
+                continue;
+            }
+            
+            // The v coordinate runs from 0 at the base of each branch to 1 at its tip
+            let vertex_distances = uvs.iter().map(|uv| uv[1]).collect();
+            let uvs_vector: Vec<Vector2<f32>> = uvs.iter().map(|uv| Vector2::new(uv[0], uv[1])).collect();
+            
+            let mesh = self.builder.create_custom_mesh(
+                Some(name.to_string()),
+                &vertices,
+                &indices,
+                Some(normals),
+                Some(vec![uvs_vector]),
+                Some(material)
+            );
+            let node = self.builder.add_node(
+                Some(name.to_string()),
+                Some(mesh),
+                None,
+                None,
+                None
+            );
+            self.builder.add_child_to_node(root_node, node);
+            
+            merged_meshes.push(MergedMesh {
+                mesh,
+                node,
+                vertex_branches,
+                vertex_distances,
+            });
+        }
+        
+        let leaves = config.leaves.as_ref().map(|leaves| {
+            let mesh = self.create_leaf_mesh(leaves, materials.leaves);
+            let node = self.builder.add_node(Some("Leaves".to_string()), Some(mesh), None, None, None);
+            self.builder.add_child_to_node(root_node, node);
+            let instances = leaves_in_tree_frame(tree, &place_leaves(tree, leaves, &mut self.leaves_rng()));
+            LeafNodes { mesh, nodes: vec![(node, instances)] }
+        });
+        
+        self.builder.add_scene(Some("Tree".to_string()), Some(vec![root_node]));
+        
+        (root_node, merged_meshes, leaves)
+    }
+}
+
+/// Generate the main roots evenly spread around the trunk base, each with its own hierarchy
+fn generate_roots(
+    generator: &mut TreeGenerator,
+    tree: &mut Tree,
+    roots: &RootsConfig,
+) {
+    let roots_style = HierarchyStyle {
+        kind: BranchKind::Root,
+        taper: roots.taper,
+        force: Some(&roots.force),
+    };
+    
+    let count = roots.count.sample_u32(&mut generator.rng);
+    let azimuth_offset = generator.random_f32(0.0, 2.0 * PI);
+    
+    for i in 0..count {
+        // Point the growth axis (+Z) of the root outward and `angle` degrees below horizontal
+        let azimuth = azimuth_offset + 2.0 * PI * i as f32 / count as f32;
+        let pitch = roots.config.angle.sample(&mut generator.rng) * PI / 180.0;
+        let direction = Vector3::new(azimuth.cos() * pitch.cos(), azimuth.sin() * pitch.cos(), -pitch.sin());
+        let rotation = UnitQuaternion::rotation_between(&Vector3::z(), &direction)
+            .unwrap_or_else(|| UnitQuaternion::from_axis_angle(&Vector3::x_axis(), PI));
+        
+        generate_branch_hierarchy(
+            generator,
+            tree,
+            &roots.config,
+            &roots_style,
+            None,
+            &Isometry3::identity(),
+            Point3::new(0.0, 0.0, 0.0),
+            Some(rotation),
+            None,
+            0
+        );
+    }
+}
+
 /// Recursively generate branch hierarchy based on the BranchConfig
+#[allow(clippy::too_many_arguments)]
 fn generate_branch_hierarchy(
     generator: &mut TreeGenerator,
+    tree: &mut Tree,
     config: &BranchConfig,
-    parent_node: Option<usize>,
+    style: &HierarchyStyle,
+    parent: Option<usize>,
+    parent_world: &Isometry3<f32>,
     position: Point3<f32>,
-    trunk_material: usize,
-    leaves_material: usize,
+    rotation: Option<UnitQuaternion<f32>>,
+    attachment_index: Option<usize>,
     level: u32,
 )  {
-    println!("Generating branch at level {}, with {} children", level, config.children);
-    println!("  children_config is {}", if config.children_config.is_some() { "Some" } else { "None" });
+    // Sample the concrete values for this branch from the config
+    let mut params = config.sample(&mut generator.rng);
+    
+    // A branch continued by a leader doesn't taper away before the leader takes over
+    if config.leader.is_some() {
+        params.end_radius = params.end_radius.max(params.start_radius * LEADER_MIN_RADIUS_RATIO);
+    }
+    
+    // A forking branch only grows its own axis up to the fork, the sub-axes share the rest
+    let splits = if params.splits >= 2 { params.splits } else { 0 };
+    let fork_t = if splits > 0 {
+        (params.split_pos_pct / 100.0).clamp(0.05, 0.95)
+    } else {
+        1.0
+    };
+    let axis_segments = ((params.length_segments as f32 * fork_t).round() as u32).max(2);
+    let axis_end_radius = tapered_radius(params.start_radius, params.end_radius, style.taper, fork_t);
+    let transforms_seed = generator.rng.gen();
+    
+    // Use the given rotation (sub-axes of a fork) or pick a random one
+    let rotation = match rotation {
+        Some(rotation) => rotation,
+        None => random_branch_rotation(generator, &params),
+    };
+    let world = parent_world * Isometry3::from_parts(Translation3::from(position.coords), rotation);
+    
+    // Transforms are generated in the branch's own frame, so the force has to be as well
+    let local_force = style.force.map(|force| Force {
+        direction: (world.rotation.inverse() * Vector3::from(force.direction)).into(),
+        strength: force.strength,
+    });
     
     // Generate a series of transforms for a more natural branch shape
     let branch_transforms = generate_branch_transforms(
-        config.length_segments as usize,    // Number of segments
-        config.length / config.length_segments as f32,  // Segment length
-        config.gnarliness * 0.2,     // Curvature strength
-        config.twist,         // Curvature variation
-        Some(generator.rng.gen())    // Random seed
+        axis_segments as usize,    // Number of segments
+        params.length * fork_t / axis_segments as f32,  // Segment length
+        params.gnarliness * 0.2,     // Curvature strength
+        params.twist,         // Curvature variation
+        local_force.as_ref(),        // Growth force
+        Some(transforms_seed)        // Random seed
     );
     
-    println!("  Generated {} transforms for branch", branch_transforms.len());
+    // Radius of each ring along the axis, following the taper of the whole branch
+    let ring_count = branch_transforms.len();
+    let radii: Vec<f32> = (0..ring_count)
+        .map(|i| {
+            let t = fork_t * i as f32 / (ring_count.max(2) - 1) as f32;
+            tapered_radius(params.start_radius, params.end_radius, style.taper, t)
+        })
+        .collect();
     
-    // Generate the mesh data for this branch using the transforms
-    let (vertices, indices, normals, uvs) = create_transform_based_mesh(
-        &branch_transforms,
-        config.start_radius,        // Start radius
-        config.end_radius,          // End radius
-        config.radial_segments as usize, // Radial segments
-        config.gnarliness            // Noise level
-    );
+    // Add the branch to the skeleton, it's meshed once the whole tree has grown
+    let branch_index = tree.branches.len();
+    tree.branches.push(Branch {
+        kind: style.kind,
+        level,
+        parent,
+        attachment_index,
+        position,
+        rotation,
+        transforms: branch_transforms.clone(),
+        radii,
+        radial_segments: params.radial_segments,
+        noise_level: params.gnarliness,
+        noise_seed: generator.seed ^ (branch_index as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15),
+    });
     
-    // Convert UVs from [f32; 2] to Vector2<f32>
-    let uvs_vector: Vec<Vector2<f32>> = uvs.iter().map(|uv| Vector2::new(uv[0], uv[1])).collect();
-    
-    // Create custom mesh for the branch
-    let mesh_id = generator.builder.create_custom_mesh(
-        Some(format!("Branch_L{}", level)),
-        &vertices,
-        &indices,
-        Some(normals),
-        Some(vec![uvs_vector]),     // UVs in the format expected by the API
-        Some(trunk_material)        // Material
-    );
+    // Continue a forking branch with its sub-axes, which share out its children and leader
+    if splits > 0 {
+        if let Some(fork) = branch_transforms.last() {
+            let fork_position = fork.point();
+            let fork_rotation = fork.unit_quaternion();
+            
+            // Sub-axes start at the fork radius so the junction stays continuous
+            let mut sub_axis_config = config.clone();
+            sub_axis_config.length = Param::fixed(params.length * (1.0 - fork_t));
+            sub_axis_config.start_radius = Param::fixed(axis_end_radius);
+            sub_axis_config.end_radius = Param::fixed(params.end_radius);
+            sub_axis_config.length_segments = Param::fixed(params.length_segments.saturating_sub(axis_segments).max(2) as f32);
+            sub_axis_config.radial_segments = Param::fixed(params.radial_segments as f32);
+            sub_axis_config.splits = Param::fixed(0.0);
+            
+            // Each child group's count is spread over the sub-axes, the first one keeps the leader
+            let mut sub_axis_configs = vec![sub_axis_config; splits as usize];
+            for (group_index, group) in config.children.iter().enumerate() {
+                let count = group.count.sample_u32(&mut generator.rng);
+                for (i, sub_axis_config) in sub_axis_configs.iter_mut().enumerate() {
+                    let share = count / splits + u32::from((i as u32) < count % splits);
+                    sub_axis_config.children[group_index].count = Param::fixed(share as f32);
+                }
+            }
+            for sub_axis_config in sub_axis_configs.iter_mut().skip(1) {
+                sub_axis_config.leader = None;
+            }
+            
+            let split_angle = params.split_angle * PI / 180.0;
+            let azimuth_offset = generator.random_f32(0.0, 2.0 * PI);
+            for (i, sub_axis_config) in sub_axis_configs.iter().enumerate() {
+                // Spread the sub-axes evenly around the direction of the branch at the fork
+                let azimuth = azimuth_offset + 2.0 * PI * i as f32 / splits as f32;
+                let tilt_axis = Unit::new_normalize(fork_rotation * Vector3::new(azimuth.cos(), azimuth.sin(), 0.0));
+                let sub_axis_rotation = UnitQuaternion::from_axis_angle(&tilt_axis, split_angle) * fork_rotation;
+                
+                generate_branch_hierarchy(
+                    generator,
+                    tree,
+                    sub_axis_config,
+                    style,
+                    Some(branch_index),
+                    &world,
+                    fork_position,
+                    Some(sub_axis_rotation),
+                    Some(branch_transforms.len() - 1),
+                    level
+                );
+            }
+        }
+        return;
+    }
     
-    // Create node for this branch
-    let node_name = match level {
-        0 => "Trunk".to_string(),
-        _ => format!("Branch_L{}_{}", level, rand::random::<u32>() % 100000),
-    };
+    // Generate child branches for each child group
+    for group in &config.children {
+        let count = group.count.sample_u32(&mut generator.rng);
+        let min_branch_pos_pct = group.min_branch_pos_pct.sample(&mut generator.rng);
+        let max_branch_pos_pct = group.max_branch_pos_pct.sample(&mut generator.rng);
+        
+        // Create each child branch based on the number specified
+        for _ in 0..count {
+            
+            // Select a random position along the parent branch for the child based on percentage range
+            // First convert percentages to indices in branch_transforms
+            let total_transforms = branch_transforms.len();
+            
+            // Ensure we have at least one transform to work with
+            if total_transforms == 0 {
+                continue; // Skip this child if no transforms are available
+            }
+            
+            // Calculate the index range based on the percentage values
+            let min_index = ((min_branch_pos_pct / 100.0) * (total_transforms as f32)).floor() as usize;
+            let max_index = ((max_branch_pos_pct / 100.0) * (total_transforms as f32)).ceil() as usize;
+            
+            // Ensure indices are within valid bounds
+            let min_index = min_index.clamp(0, total_transforms - 1);
+            let max_index = max_index.clamp(min_index + 1, total_transforms);
+            
+            // Select transforms within the specified percentage range
+            let valid_transforms = &branch_transforms[min_index..max_index.min(total_transforms)];
+            
+            // If no valid transforms after applying percentage constraints, skip this child
+            if valid_transforms.is_empty() {
+                continue;
+            }
+            
+            let random_index = generator.rng.gen_range(0..valid_transforms.len());
+            let random_transform = &valid_transforms[random_index];
+            
+            // Extract the position from the randomly selected transform
+            let child_pos = Point3::new(
+                random_transform.position[0],
+                random_transform.position[1],
+                random_transform.position[2]
+            );
+            
+            // Recursively create this child branch and its descendants
+            generate_branch_hierarchy(
+                generator,
+                tree,
+                &group.config,
+                style,
+                Some(branch_index),
+                &world,
+                child_pos,
+                None,
+                Some(min_index + random_index),
+                level + 1
+            );
+            
+        }
+    }
     
-    // Generate random rotation angles between min_rotation and max_rotation from config with random sign
+    // Continue the main axis past the children with a leader ending in a sharp tip
+    if let (Some(leader), Some(tip)) = (&config.leader, branch_transforms.last()) {
+        let length_ratio = leader.length_ratio.sample_non_negative(&mut generator.rng);
+        let tip_position = tip.point();
+        let tip_rotation = tip.unit_quaternion();
+        let tip_radius = tree.branches[branch_index].radii.last().copied().unwrap_or(params.end_radius);
+        
+        // The side children fall off, only the leader keeps growing
+        let mut leader_config = config.clone();
+        leader_config.length = Param::fixed(params.length * length_ratio);
+        leader_config.start_radius = Param::fixed(tip_radius);
+        leader_config.end_radius = Param::fixed(0.0);
+        leader_config.length_segments = Param::fixed(((params.length_segments as f32 * length_ratio).round()).max(2.0));
+        leader_config.radial_segments = Param::fixed(params.radial_segments as f32);
+        leader_config.children = Vec::new();
+        leader_config.splits = Param::fixed(0.0);
+        leader_config.leader = None;
+        
+        generate_branch_hierarchy(
+            generator,
+            tree,
+            &leader_config,
+            style,
+            Some(branch_index),
+            &world,
+            tip_position,
+            Some(tip_rotation),
+            Some(branch_transforms.len() - 1),
+            level
+        );
+    }
+    
+}
+
+/// Radius at parametric position `t` (0 to 1) along a branch, `taper` is the falloff exponent (1.0 = linear)
+pub fn tapered_radius(start_radius: f32, end_radius: f32, taper: f32, t: f32) -> f32 {
+    end_radius + (start_radius - end_radius) * (1.0 - t.clamp(0.0, 1.0)).powf(taper.max(0.01))
+}
+
+/// Flatten the vertices of a mesh that stick out above the ground plane
+fn clip_to_ground(vertices: &mut [Point3<f32>], world: &Isometry3<f32>, ground_height: f32) {
+    for vertex in vertices.iter_mut() {
+        let mut world_vertex = world * *vertex;
+        if world_vertex.z > ground_height {
+            world_vertex.z = ground_height;
+            *vertex = world.inverse_transform_point(&world_vertex);
+        }
+    }
+}
+
+/// Pick a random branch rotation between the configured min and max rotation, with random signs
+fn random_branch_rotation(generator: &mut TreeGenerator, params: &BranchParams) -> UnitQuaternion<f32> {
     // Ensure min_rot and max_rot are at least 0.1 apart to avoid empty range errors
-    let min_rot = config.min_rotation;
-    let max_rot = if (config.max_rotation - config.min_rotation) < 0.1 {
-        config.min_rotation + 0.1
+    let min_rot = params.min_rotation;
+    let max_rot = if (params.max_rotation - params.min_rotation) < 0.1 {
+        params.min_rotation + 0.1
     } else {
-        config.max_rotation
+        params.max_rotation
     };
     
     // Generate random rotation with guaranteed non-empty ranges
@@ -294,121 +1356,52 @@ fn generate_branch_hierarchy(
     let rot_y = rot_y_deg * std::f32::consts::PI / 180.0;
     let rot_z = rot_z_deg * std::f32::consts::PI / 180.0;
     
-    // Create rotation quaternion
-    let rotation = UnitQuaternion::from_euler_angles(rot_x, rot_y, rot_z);
-    
-    // Extract quaternion components in the order expected by GLTF (x, y, z, w)
-    let quat = rotation.into_inner();
-    let gltf_rotation = [quat.i, quat.j, quat.k, quat.w];
-
-    // Add current branch node to scene
-    let center_position = position + Vector3::new(0.0,0.0,0.0);
-    
-    let branch_node = generator.builder.add_node(
-        Some(node_name),
-        Some(mesh_id),
-        Some(center_position.into()),
-        Some(gltf_rotation),
-        None  // No scaling
-    );
-    
-    // Connect to parent if this isn't the trunk
-    if let Some(parent_id) = parent_node {
-        generator.builder.add_child_to_node(parent_id, branch_node);
-    }
-    
-    // Generate child branches if any
-    if config.children > 0 {
-        println!("  Level {} has {} children to generate", level, config.children);
-        if let Some(child_config) = &config.children_config {
-            println!("  Level {} found child config with start_radius {}", level, (**child_config).start_radius);
-            let child_branch_config = (**child_config).clone();
-            
-            // Create each child branch based on the number specified
-            for i in 0..config.children {
-                
-                // Select a random position along the parent branch for the child based on percentage range
-                // First convert percentages to indices in branch_transforms
-                let total_transforms = branch_transforms.len();
-                
-                // Ensure we have at least one transform to work with
-                if total_transforms == 0 {
-                    continue; // Skip this child if no transforms are available
-                }
-                
-                // Calculate the index range based on the percentage values
-                let min_index = ((config.min_branch_pos_pct / 100.0) * (total_transforms as f32)).floor() as usize;
-                let max_index = ((config.max_branch_pos_pct / 100.0) * (total_transforms as f32)).ceil() as usize;
-                
-                // Ensure indices are within valid bounds
-                let min_index = min_index.clamp(0, total_transforms - 1);
-                let max_index = max_index.clamp(min_index + 1, total_transforms);
-                
-                // Select transforms within the specified percentage range
-                let valid_transforms = &branch_transforms[min_index..max_index.min(total_transforms)];
-                
-                // If no valid transforms after applying percentage constraints, skip this child
-                if valid_transforms.is_empty() {
-                    continue;
-                }
-                
-                let random_index = generator.rng.gen_range(0..valid_transforms.len());
-                let random_transform = &valid_transforms[random_index];
-                
-                // Extract the position from the randomly selected transform
-                let child_pos = Point3::new(
-                    random_transform.position[0],
-                    random_transform.position[1],
-                    random_transform.position[2]
-                );
-                println!("  Child position: ({}, {}, {})", child_pos.x, child_pos.y, child_pos.z);
-                
-                // Recursively create this child branch and its descendants
-                println!("  Creating child {} of {} for level {}", i+1, config.children, level);
-                generate_branch_hierarchy(
-                    generator,
-                    &child_branch_config,
-                    Some(branch_node),
-                    child_pos,
-                    trunk_material,
-                    leaves_material,
-                    level + 1
-                );
-                
-            }
-        }
-    }
-    
+    UnitQuaternion::from_euler_angles(rot_x, rot_y, rot_z)
 }
 
 /// A transform representing position and rotation in 3D space
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BranchTransform {
     pub position: [f32; 3],
     pub rotation: [f32; 4],
 }
 
+impl BranchTransform {
+    /// Position of the transform as a point
+    pub fn point(&self) -> Point3<f32> {
+        Point3::new(self.position[0], self.position[1], self.position[2])
+    }
+
+    /// Rotation of the transform as a unit quaternion
+    pub fn unit_quaternion(&self) -> UnitQuaternion<f32> {
+        UnitQuaternion::from_quaternion(Quaternion::new(
+            self.rotation[3], self.rotation[0], self.rotation[1], self.rotation[2],
+        ))
+    }
+}
+
 
 /// Create a mesh (vertices, indices, normals, uvs) from a series of transforms
 /// 
 /// # Arguments
 /// 
 /// * `transforms` - List of transforms defining the path of the branch
-/// * `start_radius` - Radius at the base of the branch
-/// * `end_radius` - Radius at the tip of the branch
+/// * `radii` - Radius of the ring at each transform
 /// * `radial_segments` - Number of segments around the branch circumference
 /// * `noise_level` - Amount of random variation (0.0-1.0) to apply to the vertices
+/// * `noise_seed` - Seed of the noise, drawn ring after ring so a shortened branch keeps the noise
+///   of its remaining rings
 /// 
 /// # Returns
 /// 
 /// Tuple containing (vertices, indices, normals, uvs) for the mesh
 pub fn create_transform_based_mesh(
     transforms: &[BranchTransform],
-    start_radius: f32,
-    end_radius: f32,
+    radii: &[f32],
     radial_segments: usize,
-    noise_level: f32
-) -> (Vec<Point3<f32>>, Vec<Triangle>, Vec<Vector3<f32>>, Vec<[f32; 2]>) {
+    noise_level: f32,
+    noise_seed: u64,
+) -> MeshData {
     let radial_segments = radial_segments.max(3); // Minimum 3 segments
     let noise_level = noise_level.max(0.0).min(1.0); // Clamp noise level between 0 and 1
     
@@ -432,12 +1425,12 @@ pub fn create_transform_based_mesh(
     let mut uvs = Vec::new();
     
     // Create a random number generator for noise
-    let mut rng = rand::thread_rng();
+    let mut rng = ChaCha8Rng::seed_from_u64(noise_seed);
     
     // For each transform, create a ring of vertices
     for (i, transform) in transforms.iter().enumerate() {
         let t = i as f32 / (segment_count - 1) as f32; // Parametric value (0 to 1)
-        let radius = start_radius * (1.0 - t) + end_radius * t; // Interpolate radius
+        let radius = radii.get(i).copied().unwrap_or(0.0);
         
         // Get the position and rotation
         let current_position = transforms[i].0;
@@ -496,20 +1489,21 @@ pub fn create_transform_based_mesh(
         }
     }
     
-    // Add cap for the bottom
+    // Add cap for the bottom, wound to face back along the branch
     let bottom_center_idx = vertices.len() as u32;
     vertices.push(transforms[0].0);
-    normals.push(Vector3::new(0.0, 0.0, -1.0));
+    normals.push(transforms[0].1 * Vector3::new(0.0, 0.0, -1.0));
     uvs.push([0.5, 0.0]);
     
     for j in 0..radial_segments {
         let current = j;
         let next = (j + 1) % radial_segments;
         
-        indices.push(Triangle::new(bottom_center_idx, current as u32, next as u32));
+        indices.push(Triangle::new(bottom_center_idx, next as u32, current as u32));
     }
     
     // Handle top of the branch based on end radius
+    let end_radius = radii.get(segment_count - 1).copied().unwrap_or(0.0);
     if end_radius < 0.0001 {
         // Create a single vertex at the tip
         let tip_idx = vertices.len() as u32;
@@ -532,13 +1526,13 @@ pub fn create_transform_based_mesh(
             let current = top_start + j;
             let next = top_start + ((j + 1) % radial_segments);
             
-            indices.push(Triangle::new(tip_idx, next as u32, current as u32));
+            indices.push(Triangle::new(tip_idx, current as u32, next as u32));
         }
     } else {
-        // Normal cap for non-zero end radius
+        // Normal cap for non-zero end radius, facing along the branch
         let top_center_idx = vertices.len() as u32;
         vertices.push(transforms[segment_count - 1].0);
-        normals.push(Vector3::new(0.0, 0.0, 1.0));
+        normals.push(transforms[segment_count - 1].1 * Vector3::new(0.0, 0.0, 1.0));
         uvs.push([0.5, 1.0]);
         
         let top_start = (segment_count - 1) * radial_segments;
@@ -546,7 +1540,7 @@ pub fn create_transform_based_mesh(
             let current = top_start + j;
             let next = top_start + ((j + 1) % radial_segments);
             
-            indices.push(Triangle::new(top_center_idx, next as u32, current as u32));
+            indices.push(Triangle::new(top_center_idx, current as u32, next as u32));
         }
     }
     
@@ -554,6 +1548,58 @@ pub fn create_transform_based_mesh(
     (vertices, indices, normals, uvs)
 }
 
+/// Pick the rings of a branch to mesh so the branch turns by at most `max_angle` (radians) between
+/// two of them
+///
+/// The first and last rings are always kept. A ring is dropped when the direction of the next one
+/// is still within `max_angle` of the previous kept ring, so straight runs collapse into a single
+/// segment. Only the direction counts, a ring rolling about the branch doesn't bend it.
+pub fn adaptive_rings(transforms: &[BranchTransform], max_angle: f32) -> Vec<usize> {
+    if transforms.len() <= 2 {
+        return (0..transforms.len()).collect();
+    }
+    
+    let direction = |transform: &BranchTransform| transform.unit_quaternion() * Vector3::z();
+    let mut rings = vec![0];
+    let mut kept = direction(&transforms[0]);
+    for i in 1..transforms.len() - 1 {
+        if kept.angle(&direction(&transforms[i + 1])) > max_angle {
+            rings.push(i);
+            kept = direction(&transforms[i]);
+        }
+    }
+    rings.push(transforms.len() - 1);
+    rings
+}
+
+/// Mesh a branch with only the rings picked by `adaptive_rings`
+///
+/// Every ring keeps the v coordinate of its place along the whole branch, which the textures and
+/// the wind and skin weights follow.
+fn adaptive_branch_mesh(branch: &Branch, max_angle: f32) -> MeshData {
+    let rings = adaptive_rings(&branch.transforms, max_angle);
+    let transforms: Vec<BranchTransform> = rings.iter().map(|&ring| branch.transforms[ring].clone()).collect();
+    let radii: Vec<f32> = rings.iter().map(|&ring| branch.radii.get(ring).copied().unwrap_or(0.0)).collect();
+    let (vertices, indices, normals, mut uvs) = create_transform_based_mesh(
+        &transforms,
+        &radii,
+        branch.radial_segments as usize,
+        branch.noise_level,
+        branch.noise_seed,
+    );
+    
+    // The ring vertices come first, one ring after the other, followed by the caps
+    let radial_segments = (branch.radial_segments as usize).max(3);
+    let last = branch.transforms.len().saturating_sub(1).max(1) as f32;
+    for (ring_uvs, &ring) in uvs.chunks_mut(radial_segments).zip(&rings) {
+        for uv in ring_uvs {
+            uv[1] = ring as f32 / last;
+        }
+    }
+    
+    (vertices, indices, normals, uvs)
+}
+
 /// Generate a list of transforms along a branch with natural growth
 /// 
 /// # Arguments
@@ -562,6 +1608,7 @@ pub fn create_transform_based_mesh(
 /// * `segment_length` - Length of each segment
 /// * `curvature_strength` - Strength of the branch curvature
 /// * `curvature_variation` - Variation in curvature
+/// * `force` - Optional force bending the growth direction, expressed in the branch's frame
 /// * `seed` - Optional random seed for reproducibility
 /// 
 /// # Returns
@@ -572,6 +1619,7 @@ pub fn generate_branch_transforms(
     segment_length: f32,
     curvature_strength: f32,
     curvature_variation: f32,
+    force: Option<&Force>,
     seed: Option<u64>
 ) -> Vec<BranchTransform> {
     // Initialize the random number generator with seed if provided
@@ -615,6 +1663,16 @@ pub fn generate_branch_transforms(
         // Apply the rotation to our cumulative rotation
         cumulative_rotation = cumulative_rotation * segment_rotation;
         
+        // Bend the growth direction towards the force, proportionally to the segment length
+        if let Some(force) = force {
+            let growth_direction = cumulative_rotation * initial_direction;
+            let force_direction = Vector3::from(force.direction);
+            if let Some(bend) = UnitQuaternion::rotation_between(&growth_direction, &force_direction) {
+                let amount = (force.strength * segment_length).clamp(0.0, 1.0);
+                cumulative_rotation = bend.powf(amount) * cumulative_rotation;
+            }
+        }
+        
         // Calculate new position by moving in the direction determined by the cumulative rotation
         let direction = cumulative_rotation * initial_direction;
         position += direction * segment_length;
@@ -633,3 +1691,164 @@ pub fn generate_branch_transforms(
 }
 
 // L-system approach no longer used - replaced with continuous growth vector
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::trunk_tree_config;
+    use serde_json::json;
+
+    /// Rings one unit apart along the direction of each rotation
+    fn rings_along(rotations: &[UnitQuaternion<f32>]) -> Vec<BranchTransform> {
+        let mut position = Point3::origin();
+        rotations
+            .iter()
+            .map(|rotation| {
+                let quat = rotation.into_inner();
+                let transform = BranchTransform {
+                    position: [position.x, position.y, position.z],
+                    rotation: [quat.i, quat.j, quat.k, quat.w],
+                };
+                position += rotation * Vector3::z();
+                transform
+            })
+            .collect()
+    }
+
+    #[test]
+    fn continuations_are_named_after_their_parent() {
+        let tree = Tree {
+            branches: vec![
+                Branch::straight(0, None, None, 3),
+                Branch::straight(1, Some(0), Some(1), 3),
+                Branch::straight(0, Some(0), Some(2), 3),
+                Branch::straight(0, Some(0), Some(2), 3),
+                Branch::straight(0, Some(3), Some(2), 3),
+                Branch::straight(1, Some(3), Some(2), 3),
+            ],
+            ground_clip: None,
+            ring_angle: None,
+        };
+        assert_eq!(
+            tree.branch_names(),
+            vec!["Trunk", "Branch_L1_1", "Trunk_fork1", "Trunk_fork2", "Trunk_fork2_leader", "Branch_L1_5"]
+        );
+    }
+
+    #[test]
+    fn fork_shares_its_children_and_leader() {
+        let child = json!({
+            "length": 1.0, "startRadius": 0.05, "endRadius": 0.02, "lengthSegments": 4,
+            "radialSegments": 6, "angle": 30.0, "twist": 0.0, "gnarliness": 0.0, "children": 0
+        });
+        let config = trunk_tree_config(json!({
+            "length": 5.0, "startRadius": 0.3, "endRadius": 0.1, "lengthSegments": 8,
+            "radialSegments": 8, "angle": 0.0, "twist": 0.0, "gnarliness": 0.0,
+            "splits": 2, "children": 5, "children_config": child,
+            "leader": {"lengthRatio": 0.5}
+        }));
+        let tree = TreeGenerator::new(Some(1)).grow(&config);
+
+        let trunk_axes: Vec<usize> = (0..tree.branches.len())
+            .filter(|&i| tree.branches[i].level == 0)
+            .collect();
+        let children = tree.branches.iter().filter(|branch| branch.level == 1).count();
+        let leaders = trunk_axes
+            .iter()
+            .filter(|&&i| tree.branches[i].parent.is_some_and(|parent| parent != 0))
+            .count();
+
+        // The trunk, its two sub-axes and the leader of the first one
+        assert_eq!(trunk_axes.len(), 4);
+        assert_eq!(leaders, 1);
+        assert_eq!(children, 5);
+    }
+
+    #[test]
+    fn leader_keeps_the_tip_of_its_branch_open() {
+        let config = trunk_tree_config(json!({
+            "length": 5.0, "startRadius": 0.5, "endRadius": 0.0, "lengthSegments": 8,
+            "radialSegments": 8, "angle": 0.0, "twist": 0.0, "gnarliness": 0.0,
+            "children": 0, "leader": {"lengthRatio": 0.5}
+        }));
+        let tree = TreeGenerator::new(Some(1)).grow(&config);
+
+        // The explicit endRadius of 0 is raised to a fifth of the start radius
+        let trunk = &tree.branches[0];
+        let tip = *trunk.radii.last().unwrap();
+        assert!((tip - 0.5 * LEADER_MIN_RADIUS_RATIO).abs() < 1.0e-5, "tip radius {}", tip);
+        let leader = &tree.branches[1];
+        assert_eq!(leader.parent, Some(0));
+        assert!((leader.radii[0] - tip).abs() < 1.0e-5, "leader starts at {}", leader.radii[0]);
+
+        // Without a leader the branch ends in a point
+        let mut config = config;
+        config.trunk.leader = None;
+        let tree = TreeGenerator::new(Some(1)).grow(&config);
+        assert_eq!(tree.branches.len(), 1);
+        assert!(tree.branches[0].radii.last().unwrap().abs() < 1.0e-5);
+    }
+
+    #[test]
+    fn branch_mesh_faces_outward() {
+        // Every face points away from the axis, the caps along it, so the enclosed volume is positive
+        for radii in [vec![0.1; 3], vec![0.1, 0.05, 0.0]] {
+            let tree = Tree { branches: vec![Branch { radii, ..Branch::straight(0, None, None, 3) }], ..Default::default() };
+            let (vertices, indices, normals, _) = tree.branch_mesh(0);
+            let mut volume = 0.0;
+            for triangle in &indices {
+                let [a, b, c] = [triangle.a, triangle.b, triangle.c].map(|i| vertices[i as usize].coords);
+                let face = (b - a).cross(&(c - a));
+                volume += a.dot(&(b.cross(&c))) / 6.0;
+                if face.norm() < 1.0e-9 {
+                    continue;
+                }
+                let center = (a + b + c) / 3.0;
+                let outward = match center.z {
+                    z if z < 1.0e-5 => -Vector3::z(),
+                    z if z > 2.0 - 1.0e-5 => Vector3::z(),
+                    _ => Vector3::new(center.x, center.y, 0.0),
+                };
+                assert!(face.dot(&outward) > 0.0, "face {:?} points inward", triangle);
+            }
+            assert!(volume > 0.0);
+            let bottom = &normals[normals.len() - 2];
+            assert!((bottom + Vector3::z()).norm() < 1.0e-5);
+        }
+    }
+
+    #[test]
+    fn straight_branch_collapses_to_two_rings() {
+        // The rings roll about the branch without bending it
+        let rotations: Vec<UnitQuaternion<f32>> = (0..10)
+            .map(|i| UnitQuaternion::from_axis_angle(&Vector3::z_axis(), i as f32 * 0.5))
+            .collect();
+        assert_eq!(adaptive_rings(&rings_along(&rotations), 5.0_f32.to_radians()), vec![0, 9]);
+    }
+
+    #[test]
+    fn bent_branch_keeps_its_bends() {
+        let max_angle = 5.0_f32.to_radians();
+
+        // Straight up to ring 5, where it turns by 30 degrees
+        let bend = UnitQuaternion::from_axis_angle(&Vector3::x_axis(), 30.0_f32.to_radians());
+        let rotations: Vec<UnitQuaternion<f32>> = (0..10)
+            .map(|i| if i < 5 { UnitQuaternion::identity() } else { bend })
+            .collect();
+        let rings = adaptive_rings(&rings_along(&rotations), max_angle);
+        assert!(rings.contains(&5), "rings {:?}", rings);
+        assert!(!rings.contains(&2) && !rings.contains(&7), "rings {:?}", rings);
+
+        // A gentle curve keeps a ring every time it has turned past the angle
+        let rotations: Vec<UnitQuaternion<f32>> = (0..10)
+            .map(|i| UnitQuaternion::from_axis_angle(&Vector3::x_axis(), i as f32 * 2.0_f32.to_radians()))
+            .collect();
+        let transforms = rings_along(&rotations);
+        let rings = adaptive_rings(&transforms, max_angle);
+        assert!(rings.len() > 2 && rings.len() < transforms.len(), "rings {:?}", rings);
+        for pair in rings.windows(2) {
+            let turn = rotations[pair[0]].angle_to(&rotations[pair[1]]);
+            assert!(turn <= max_angle + 1.0e-4, "rings {:?} turn by {}", pair, turn);
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::leaves::{leaf_prototype, LeafInstance};
+use crate::orientation::ExportFrame;
+use crate::tree::{BranchKind, MeshData, Tree, TRUNK_COLOR};
+use crate::{TreeConfig, UpAxis};
+
+/// Write the tree as a USD text file (`.usda`)
+///
+/// The branches form an Xform hierarchy matching the glTF node tree, each holding a Mesh prim in
+/// its own frame. The leaves are a PointInstancer at `/Tree/Leaves` placing copies of the
+/// prototype leaf. The meshes are bound to UsdPreviewSurface materials under `/Tree/Looks`. The
+/// export settings transform the `/Tree` prim and set the stage's `upAxis` and `metersPerUnit`.
+/// The file is written directly, so no USD runtime is needed.
+///
+/// # Arguments
+///
+/// * `tree` - Skeleton to mesh and write
+/// * `config` - Tree configuration, for the materials and export settings
+/// * `leaves` - The leaves in the tree's frame
+/// * `output_path` - Path of the USDA file
+pub fn write_usda(tree: &Tree, config: &TreeConfig, leaves: &[LeafInstance], output_path: &Path) -> Result<(), Box<dyn Error>> {
+    let up_axis = match config.export.map(|export| export.up_axis) {
+        Some(UpAxis::Y) => "Y",
+        _ => "Z",
+    };
+    let units = config.export.map_or(1.0, |export| export.units.per_meter());
+
+    let mut usda = BufWriter::new(File::create(output_path)?);
+    writeln!(usda, "#usda 1.0")?;
+    writeln!(usda, "(")?;
+    writeln!(usda, "    defaultPrim = \"Tree\"")?;
+    writeln!(usda, "    doc = \"Generated by tree-maker\"")?;
+    writeln!(usda, "    metersPerUnit = {}", 1.0 / units)?;
+    writeln!(usda, "    upAxis = \"{}\"", up_axis)?;
+    writeln!(usda, ")")?;
+    writeln!(usda)?;
+    writeln!(usda, "def Xform \"Tree\" (")?;
+    writeln!(usda, "    kind = \"component\"")?;
+    writeln!(usda, ")")?;
+    writeln!(usda, "{{")?;
+
+    // The export settings go on the tree prim, like on the root node of a glTF file
+    if config.export.is_some() {
+        let frame = ExportFrame::of_tree(tree, config.export.as_ref());
+        let (translation, rotation, scale) = (frame.translation, frame.rotation.into_inner(), frame.scale);
+        writeln!(usda, "    double3 xformOp:translate = ({}, {}, {})", translation.x, translation.y, translation.z)?;
+        writeln!(usda, "    quatf xformOp:orient = ({}, {}, {}, {})", rotation.w, rotation.i, rotation.j, rotation.k)?;
+        writeln!(usda, "    float3 xformOp:scale = ({}, {}, {})", scale.x, scale.y, scale.z)?;
+        writeln!(usda, "    uniform token[] xformOpOrder = [\"xformOp:translate\", \"xformOp:orient\", \"xformOp:scale\"]")?;
+        writeln!(usda)?;
+    }
+
+    writeln!(usda, "    def Scope \"Looks\"")?;
+    writeln!(usda, "    {{")?;
+    write_material(&mut usda, "Bark", TRUNK_COLOR)?;
+    if let Some(leaves_config) = config.leaves.as_ref().filter(|_| !leaves.is_empty()) {
+        writeln!(usda)?;
+        write_material(&mut usda, "Leaves", leaves_config.color)?;
+    }
+    if let Some(roots) = &config.roots {
+        writeln!(usda)?;
+        write_material(&mut usda, "Roots", roots.color)?;
+    }
+    writeln!(usda, "    }}")?;
+
+    // Children of each branch, the trunk and main roots hang off the tree prim
+    let mut children = vec![Vec::new(); tree.branches.len()];
+    for (index, branch) in tree.branches.iter().enumerate() {
+        if let Some(parent) = branch.parent {
+            children[parent].push(index);
+        }
+    }
+    let names = tree.branch_names();
+    for (index, branch) in tree.branches.iter().enumerate() {
+        if branch.parent.is_none() {
+            writeln!(usda)?;
+            write_branch(&mut usda, tree, config, &children, &names, index, 1)?;
+        }
+    }
+
+    if let Some(leaves_config) = config.leaves.as_ref().filter(|_| !leaves.is_empty()) {
+        writeln!(usda)?;
+        write_leaves(&mut usda, &leaf_prototype(leaves_config), leaves)?;
+    }
+
+    writeln!(usda, "}}")?;
+    usda.flush()?;
+    Ok(())
+}
+
+/// Write a branch Xform with its mesh and, nested inside, its children
+fn write_branch(
+    usda: &mut impl Write,
+    tree: &Tree,
+    config: &TreeConfig,
+    children: &[Vec<usize>],
+    names: &[String],
+    index: usize,
+    depth: usize,
+) -> Result<(), Box<dyn Error>> {
+    let indent = "    ".repeat(depth);
+    let branch = &tree.branches[index];
+    let name = &names[index];
+    let material = match branch.kind {
+        BranchKind::Root if config.roots.is_some() => "Roots",
+        _ => "Bark",
+    };
+
+    let position = branch.position;
+    let rotation = branch.rotation.into_inner();
+    writeln!(usda, "{}def Xform \"{}\"", indent, name)?;
+    writeln!(usda, "{}{{", indent)?;
+    writeln!(usda, "{}    double3 xformOp:translate = ({}, {}, {})", indent, position.x, position.y, position.z)?;
+    writeln!(usda, "{}    quatf xformOp:orient = ({}, {}, {}, {})", indent, rotation.w, rotation.i, rotation.j, rotation.k)?;
+    writeln!(usda, "{}    uniform token[] xformOpOrder = [\"xformOp:translate\", \"xformOp:orient\"]", indent)?;
+
+    let mesh = tree.branch_mesh(index);
+    if !mesh.0.is_empty() {
+        writeln!(usda)?;
+        write_mesh(usda, &format!("{}    ", indent), "Mesh", &mesh, material, false)?;
+    }
+
+    for &child in &children[index] {
+        writeln!(usda)?;
+        write_branch(usda, tree, config, children, names, child, depth + 1)?;
+    }
+
+    writeln!(usda, "{}}}", indent)?;
+    Ok(())
+}
+
+/// Write the leaves as a PointInstancer of the prototype leaf, which is double sided like in glTF
+fn write_leaves(usda: &mut impl Write, prototype: &MeshData, leaves: &[LeafInstance]) -> Result<(), Box<dyn Error>> {
+    let positions = join(leaves.iter().map(|leaf| format!("({}, {}, {})", leaf.translation[0], leaf.translation[1], leaf.translation[2])));
+    // USD quaternions start with the real part
+    let orientations = join(leaves.iter().map(|leaf| format!("({}, {}, {}, {})", leaf.rotation[3], leaf.rotation[0], leaf.rotation[1], leaf.rotation[2])));
+    let scales = join(leaves.iter().map(|leaf| format!("({}, {}, {})", leaf.scale[0], leaf.scale[1], leaf.scale[2])));
+    let proto_indices = vec!["0"; leaves.len()].join(", ");
+
+    writeln!(usda, "    def PointInstancer \"Leaves\"")?;
+    writeln!(usda, "    {{")?;
+    writeln!(usda, "        point3f[] positions = [{}]", positions)?;
+    writeln!(usda, "        quath[] orientations = [{}]", orientations)?;
+    writeln!(usda, "        float3[] scales = [{}]", scales)?;
+    writeln!(usda, "        int[] protoIndices = [{}]", proto_indices)?;
+    writeln!(usda, "        rel prototypes = [</Tree/Leaves/Prototypes/Leaf>]")?;
+    writeln!(usda)?;
+    writeln!(usda, "        def Scope \"Prototypes\"")?;
+    writeln!(usda, "        {{")?;
+    write_mesh(usda, "            ", "Leaf", prototype, "Leaves", true)?;
+    writeln!(usda, "        }}")?;
+    writeln!(usda, "    }}")?;
+    Ok(())
+}
+
+/// Write a Mesh prim bound to a material of the Looks scope
+fn write_mesh(
+    usda: &mut impl Write,
+    indent: &str,
+    name: &str,
+    mesh: &MeshData,
+    material: &str,
+    double_sided: bool,
+) -> Result<(), Box<dyn Error>> {
+    let (vertices, indices, normals, uvs) = mesh;
+    let face_vertex_counts = vec!["3"; indices.len()].join(", ");
+    let face_vertex_indices = join(indices.iter().map(|triangle| format!("{}, {}, {}", triangle.a, triangle.b, triangle.c)));
+    let points = join(vertices.iter().map(|vertex| format!("({}, {}, {})", vertex.x, vertex.y, vertex.z)));
+    let normals = join(normals.iter().map(|normal| format!("({}, {}, {})", normal.x, normal.y, normal.z)));
+    let uvs = join(uvs.iter().map(|uv| format!("({}, {})", uv[0], uv[1])));
+
+    writeln!(usda, "{}def Mesh \"{}\" (", indent, name)?;
+    writeln!(usda, "{}    prepend apiSchemas = [\"MaterialBindingAPI\"]", indent)?;
+    writeln!(usda, "{})", indent)?;
+    writeln!(usda, "{}{{", indent)?;
+    if double_sided {
+        writeln!(usda, "{}    uniform bool doubleSided = 1", indent)?;
+    }
+    writeln!(usda, "{}    int[] faceVertexCounts = [{}]", indent, face_vertex_counts)?;
+    writeln!(usda, "{}    int[] faceVertexIndices = [{}]", indent, face_vertex_indices)?;
+    writeln!(usda, "{}    point3f[] points = [{}]", indent, points)?;
+    writeln!(usda, "{}    normal3f[] normals = [{}] (", indent, normals)?;
+    writeln!(usda, "{}        interpolation = \"vertex\"", indent)?;
+    writeln!(usda, "{}    )", indent)?;
+    writeln!(usda, "{}    texCoord2f[] primvars:st = [{}] (", indent, uvs)?;
+    writeln!(usda, "{}        interpolation = \"vertex\"", indent)?;
+    writeln!(usda, "{}    )", indent)?;
+    writeln!(usda, "{}    uniform token subdivisionScheme = \"none\"", indent)?;
+    writeln!(usda, "{}    rel material:binding = </Tree/Looks/{}>", indent, material)?;
+    writeln!(usda, "{}}}", indent)?;
+    Ok(())
+}
+
+/// Write a UsdPreviewSurface material inside the Looks scope
+fn write_material(usda: &mut impl Write, name: &str, color: [f32; 4]) -> Result<(), Box<dyn Error>> {
+    writeln!(usda, "        def Material \"{}\"", name)?;
+    writeln!(usda, "        {{")?;
+    writeln!(usda, "            token outputs:surface.connect = </Tree/Looks/{}/PreviewSurface.outputs:surface>", name)?;
+    writeln!(usda)?;
+    writeln!(usda, "            def Shader \"PreviewSurface\"")?;
+    writeln!(usda, "            {{")?;
+    writeln!(usda, "                uniform token info:id = \"UsdPreviewSurface\"")?;
+    writeln!(usda, "                color3f inputs:diffuseColor = ({}, {}, {})", color[0], color[1], color[2])?;
+    writeln!(usda, "                float inputs:opacity = {}", color[3])?;
+    writeln!(usda, "                float inputs:roughness = 0.9")?;
+    writeln!(usda, "                token outputs:surface")?;
+    writeln!(usda, "            }}")?;
+    writeln!(usda, "        }}")?;
+    Ok(())
+}
+
+/// Join formatted values into a USD array body
+fn join(values: impl Iterator<Item = String>) -> String {
+    values.collect::<Vec<_>>().join(", ")
+}
@@ -0,0 +1,302 @@
+use mesh_tools::Triangle;
+use nalgebra::{Point3, Vector3};
+use std::collections::HashMap;
+
+use crate::tree::{BranchKind, MeshData, Tree};
+use crate::WeldConfig;
+
+/// Corners of the unit cube, indexed by bit (x = 1, y = 2, z = 4)
+const CUBE_CORNERS: [[usize; 3]; 8] = [
+    [0, 0, 0], [1, 0, 0], [0, 1, 0], [1, 1, 0],
+    [0, 0, 1], [1, 0, 1], [0, 1, 1], [1, 1, 1],
+];
+
+/// Tetrahedra splitting a cube along its main diagonal, every path from corner 0 to corner 7
+///
+/// Neighbouring cubes split their shared faces the same way, so the surface has no cracks.
+const CUBE_TETRAHEDRA: [[usize; 4]; 6] = [
+    [0, 1, 3, 7], [0, 1, 5, 7], [0, 2, 3, 7],
+    [0, 2, 6, 7], [0, 4, 5, 7], [0, 4, 6, 7],
+];
+
+/// Largest number of grid cells along the longest side, about 4 GB of field at most
+const MAX_RESOLUTION: u32 = 1024;
+
+/// Merge every branch of the tree into a single mesh in the tree's frame
+///
+/// With a weld config the branches are fused into one closed, manifold solid for 3D printing;
+/// otherwise the branch meshes are simply concatenated.
+pub fn solid_mesh(tree: &Tree, weld: Option<&WeldConfig>) -> MeshData {
+    match weld {
+        Some(weld) => weld_tree(tree, weld),
+        None => {
+            let (mut mesh, _) = tree.merged_mesh(BranchKind::Branch);
+            let (roots, _) = tree.merged_mesh(BranchKind::Root);
+            append_mesh(&mut mesh, roots);
+            mesh
+        }
+    }
+}
+
+/// Fuse the branches into a single closed, manifold solid
+///
+/// The branches are sampled as a distance field of tapered capsules on a regular grid, then the
+/// surface is extracted with marching tetrahedra. Radii below half the minimum thickness are
+/// raised so every twig stays printable. The welded mesh has no UVs.
+pub fn weld_tree(tree: &Tree, weld: &WeldConfig) -> MeshData {
+    // Segments of every branch in the tree's frame as (start, end, start radius, end radius)
+    let mut segments = Vec::new();
+    let mut min = Point3::new(f32::MAX, f32::MAX, f32::MAX);
+    let mut max = Point3::new(f32::MIN, f32::MIN, f32::MIN);
+    let mut max_radius: f32 = 0.0;
+    for (index, branch) in tree.branches.iter().enumerate() {
+        let world = tree.world_transform(index);
+        let points: Vec<Point3<f32>> = branch.transforms.iter().map(|transform| world * transform.point()).collect();
+        for (i, pair) in points.windows(2).enumerate() {
+            let start_radius = branch.radii.get(i).copied().unwrap_or(0.0);
+            let end_radius = branch.radii.get(i + 1).copied().unwrap_or(start_radius);
+            segments.push((pair[0], pair[1], start_radius, end_radius));
+            for point in pair {
+                min = min.inf(point);
+                max = max.sup(point);
+            }
+            max_radius = max_radius.max(start_radius).max(end_radius);
+        }
+    }
+    if segments.is_empty() {
+        return (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    }
+
+    // The grid spans the thickened branches too, so its size only depends on the resolution
+    if let Some(min_thickness) = weld.min_thickness {
+        max_radius = max_radius.max(min_thickness / 2.0);
+    }
+    let extent = (max - min).max() + 2.0 * max_radius;
+    let resolution = weld.resolution.clamp(8, MAX_RESOLUTION);
+    if resolution != weld.resolution {
+        println!("The weld resolution is limited to 8 to {} cells, using {}", MAX_RESOLUTION, resolution);
+    }
+    let voxel_size = extent / resolution as f32;
+    let min_radius = weld.min_thickness.unwrap_or(2.0 * voxel_size) / 2.0;
+    let max_radius = max_radius.max(min_radius);
+
+    // Pad the grid so the field is positive all around and the surface is closed
+    let padding = max_radius + 2.0 * voxel_size;
+    let origin = min - Vector3::repeat(padding);
+    let size = (max - min) + Vector3::repeat(2.0 * padding);
+    let dims = [
+        (size.x / voxel_size).ceil() as usize + 1,
+        (size.y / voxel_size).ceil() as usize + 1,
+        (size.z / voxel_size).ceil() as usize + 1,
+    ];
+    let grid_index = |x: usize, y: usize, z: usize| (z * dims[1] + y) * dims[0] + x;
+    let grid_point = |x: usize, y: usize, z: usize| {
+        origin + Vector3::new(x as f32, y as f32, z as f32) * voxel_size
+    };
+
+    // Only the cells around each segment are sampled, the rest stays outside
+    let mut field = vec![padding; dims[0] * dims[1] * dims[2]];
+    for &(start, end, start_radius, end_radius) in &segments {
+        let (start_radius, end_radius) = (start_radius.max(min_radius), end_radius.max(min_radius));
+        let reach = start_radius.max(end_radius) + 2.0 * voxel_size;
+        let low = start.inf(&end) - Vector3::repeat(reach) - origin;
+        let high = start.sup(&end) + Vector3::repeat(reach) - origin;
+        let range = |axis: usize| {
+            let low = (low[axis] / voxel_size).floor().max(0.0) as usize;
+            let high = ((high[axis] / voxel_size).ceil() as usize).min(dims[axis] - 1);
+            low..=high
+        };
+        for z in range(2) {
+            for y in range(1) {
+                for x in range(0) {
+                    let distance = capsule_distance(&grid_point(x, y, z), &start, &end, start_radius, end_radius);
+                    let value = &mut field[grid_index(x, y, z)];
+                    *value = value.min(distance);
+                }
+            }
+        }
+    }
+
+    // Values exactly on the surface would create degenerate triangles
+    let epsilon = voxel_size * 1.0e-4;
+    for value in field.iter_mut() {
+        if value.abs() < epsilon {
+            *value = epsilon;
+        }
+    }
+
+    // Outward normal from the gradient of the field at a grid point
+    let gradient = |x: usize, y: usize, z: usize| {
+        let sample = |x: usize, y: usize, z: usize| field[grid_index(x, y, z)];
+        Vector3::new(
+            sample((x + 1).min(dims[0] - 1), y, z) - sample(x.saturating_sub(1), y, z),
+            sample(x, (y + 1).min(dims[1] - 1), z) - sample(x, y.saturating_sub(1), z),
+            sample(x, y, (z + 1).min(dims[2] - 1)) - sample(x, y, z.saturating_sub(1)),
+        )
+    };
+
+    let mut vertices = Vec::new();
+    let mut normals = Vec::new();
+    let mut indices = Vec::new();
+    // Surface vertex on each crossed grid edge, shared by every tetrahedron around the edge
+    let mut edge_vertices: HashMap<(usize, usize), u32> = HashMap::new();
+
+    for z in 0..dims[2] - 1 {
+        for y in 0..dims[1] - 1 {
+            for x in 0..dims[0] - 1 {
+                let corners = CUBE_CORNERS.map(|[dx, dy, dz]| (x + dx, y + dy, z + dz));
+                let values = corners.map(|(x, y, z)| field[grid_index(x, y, z)]);
+                if values.iter().all(|value| *value > 0.0) || values.iter().all(|value| *value < 0.0) {
+                    continue;
+                }
+
+                for tetrahedron in CUBE_TETRAHEDRA {
+                    let inside: Vec<usize> = tetrahedron.iter().copied().filter(|&corner| values[corner] < 0.0).collect();
+                    let outside: Vec<usize> = tetrahedron.iter().copied().filter(|&corner| values[corner] > 0.0).collect();
+                    if inside.is_empty() || outside.is_empty() {
+                        continue;
+                    }
+
+                    let mut edge_vertex = |a: usize, b: usize| {
+                        let (ia, ib) = (grid_index(corners[a].0, corners[a].1, corners[a].2), grid_index(corners[b].0, corners[b].1, corners[b].2));
+                        let key = (ia.min(ib), ia.max(ib));
+                        *edge_vertices.entry(key).or_insert_with(|| {
+                            let t = values[a] / (values[a] - values[b]);
+                            let (pa, pb) = (grid_point(corners[a].0, corners[a].1, corners[a].2), grid_point(corners[b].0, corners[b].1, corners[b].2));
+                            let (na, nb) = (gradient(corners[a].0, corners[a].1, corners[a].2), gradient(corners[b].0, corners[b].1, corners[b].2));
+                            vertices.push(pa + (pb - pa) * t);
+                            normals.push((na + (nb - na) * t).try_normalize(1.0e-12).unwrap_or_else(Vector3::z));
+                            (vertices.len() - 1) as u32
+                        })
+                    };
+
+                    // One inside or one outside corner cuts a triangle, two and two cut a quad
+                    let polygon: Vec<u32> = match (inside.len(), outside.len()) {
+                        (1, _) => outside.iter().map(|&corner| edge_vertex(inside[0], corner)).collect(),
+                        (_, 1) => inside.iter().map(|&corner| edge_vertex(corner, outside[0])).collect(),
+                        _ => vec![
+                            edge_vertex(inside[0], outside[0]),
+                            edge_vertex(inside[0], outside[1]),
+                            edge_vertex(inside[1], outside[1]),
+                            edge_vertex(inside[1], outside[0]),
+                        ],
+                    };
+
+                    // Face the triangles away from the inside of the solid
+                    let direction = grid_point(corners[outside[0]].0, corners[outside[0]].1, corners[outside[0]].2)
+                        - grid_point(corners[inside[0]].0, corners[inside[0]].1, corners[inside[0]].2);
+                    for i in 1..polygon.len() - 1 {
+                        let (a, b, c) = (polygon[0], polygon[i], polygon[i + 1]);
+                        let normal = (vertices[b as usize] - vertices[a as usize]).cross(&(vertices[c as usize] - vertices[a as usize]));
+                        if normal.dot(&direction) >= 0.0 {
+                            indices.push(Triangle::new(a, b, c));
+                        } else {
+                            indices.push(Triangle::new(a, c, b));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    println!("Welded {} segments into a solid with {} triangles", segments.len(), indices.len());
+    (vertices, indices, normals, Vec::new())
+}
+
+/// Signed distance from a point to a capsule whose radius tapers from `start_radius` to `end_radius`
+fn capsule_distance(point: &Point3<f32>, start: &Point3<f32>, end: &Point3<f32>, start_radius: f32, end_radius: f32) -> f32 {
+    let axis = end - start;
+    let length_squared = axis.norm_squared();
+    let t = if length_squared > f32::EPSILON {
+        ((point - start).dot(&axis) / length_squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let radius = start_radius + (end_radius - start_radius) * t;
+    (point - (start + axis * t)).norm() - radius
+}
+
+/// Append a mesh to another, offsetting its indices
+fn append_mesh(mesh: &mut MeshData, other: MeshData) {
+    let offset = mesh.0.len() as u32;
+    mesh.0.extend(other.0);
+    mesh.1.extend(other.1.iter().map(|triangle| Triangle::new(triangle.a + offset, triangle.b + offset, triangle.c + offset)));
+    mesh.2.extend(other.2);
+    mesh.3.extend(other.3);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::Branch;
+    use nalgebra::UnitQuaternion;
+    use std::f32::consts::FRAC_PI_4;
+
+    fn branch(parent: Option<usize>, attachment_index: Option<usize>, rotation: UnitQuaternion<f32>) -> Branch {
+        Branch {
+            rotation,
+            radii: vec![0.3, 0.25, 0.2, 0.1],
+            ..Branch::straight(parent.map_or(0, |_| 1), parent, attachment_index, 4)
+        }
+    }
+
+    /// Every edge is used once in each direction: the surface is closed, manifold and consistently
+    /// wound
+    fn assert_closed_manifold(mesh: &MeshData) {
+        let (vertices, indices, _, _) = mesh;
+        assert!(!indices.is_empty());
+        let mut edges: HashMap<(u32, u32), usize> = HashMap::new();
+        for triangle in indices {
+            assert!(triangle.a != triangle.b && triangle.b != triangle.c && triangle.c != triangle.a);
+            for edge in [(triangle.a, triangle.b), (triangle.b, triangle.c), (triangle.c, triangle.a)] {
+                *edges.entry(edge).or_default() += 1;
+            }
+        }
+        for (&(a, b), &count) in &edges {
+            assert_eq!(count, 1, "edge {} {} is used {} times", a, b, count);
+            assert_eq!(edges.get(&(b, a)), Some(&1), "edge {} {} has no opposite", a, b);
+        }
+        assert!(indices.iter().all(|triangle| (triangle.a as usize) < vertices.len()
+            && (triangle.b as usize) < vertices.len()
+            && (triangle.c as usize) < vertices.len()));
+    }
+
+    #[test]
+    fn welded_branch_is_closed_and_manifold() {
+        let tree = Tree {
+            branches: vec![branch(None, None, UnitQuaternion::identity())],
+            ground_clip: None,
+            ring_angle: None,
+        };
+        let weld = WeldConfig { resolution: 24, min_thickness: None };
+        assert_closed_manifold(&weld_tree(&tree, &weld));
+    }
+
+    #[test]
+    fn welded_fork_is_closed_and_manifold() {
+        let tree = Tree {
+            branches: vec![
+                branch(None, None, UnitQuaternion::identity()),
+                branch(Some(0), Some(1), UnitQuaternion::from_axis_angle(&Vector3::x_axis(), FRAC_PI_4)),
+                branch(Some(0), Some(2), UnitQuaternion::from_axis_angle(&Vector3::y_axis(), -FRAC_PI_4)),
+            ],
+            ground_clip: None,
+            ring_angle: None,
+        };
+        let weld = WeldConfig { resolution: 32, min_thickness: Some(0.3) };
+        assert_closed_manifold(&weld_tree(&tree, &weld));
+    }
+
+    #[test]
+    fn resolution_is_bounded() {
+        let tree = Tree {
+            branches: vec![branch(None, None, UnitQuaternion::identity())],
+            ground_clip: None,
+            ring_angle: None,
+        };
+        let coarse = weld_tree(&tree, &WeldConfig { resolution: 0, min_thickness: None });
+        let smallest = weld_tree(&tree, &WeldConfig { resolution: 8, min_thickness: None });
+        assert_eq!(coarse.1.len(), smallest.1.len());
+        assert_closed_manifold(&coarse);
+    }
+}
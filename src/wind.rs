@@ -0,0 +1,242 @@
+use nalgebra::{Unit, UnitQuaternion, Vector3};
+use serde_json::json;
+use std::f32::consts::PI;
+
+use crate::gltf::{GlbDocument, TARGET_ARRAY_BUFFER};
+use crate::tree::{BranchKind, MergedMesh, Tree};
+use crate::WindConfig;
+
+/// Keyframes per sway cycle of the fastest branch
+const KEYFRAMES_PER_CYCLE: u32 = 8;
+
+/// Add a looping wind animation that sways every branch node around its attachment point
+///
+/// Each branch node is rotated around the horizontal axis perpendicular to the wind. Higher level
+/// and thinner branches sway faster and further than the trunk. Every branch completes a whole
+/// number of cycles over the loop, so the last keyframe matches the first one.
+///
+/// # Arguments
+///
+/// * `document` - Exported GLB document to add the animation to
+/// * `tree` - Skeleton the document was built from
+/// * `branch_nodes` - Node index of each branch in `tree.branches`
+/// * `wind` - Wind configuration
+pub fn add_wind_animation(document: &mut GlbDocument, tree: &Tree, branch_nodes: &[usize], wind: &WindConfig) {
+    let duration = wind.duration.max(0.1);
+    let base_cycles = (wind.frequency * duration).round().max(1.0) as u32;
+
+    // The branches sway around the horizontal axis perpendicular to the wind (the tree grows along +Z)
+    let wind_direction = Vector3::from(wind.direction);
+    let sway_axis = Unit::try_new(Vector3::z().cross(&wind_direction), 1.0e-6)
+        .unwrap_or_else(Vector3::x_axis);
+
+    // Shared keyframe times, dense enough for the fastest branch
+    let max_level = tree.branches.iter().map(|branch| branch.level).max().unwrap_or(0);
+    let keyframe_count = base_cycles * (max_level + 1) * KEYFRAMES_PER_CYCLE + 1;
+    let times: Vec<f32> = (0..keyframe_count)
+        .map(|i| duration * i as f32 / (keyframe_count - 1) as f32)
+        .collect();
+    let input = document.add_float_accessor(&times, 1, None);
+
+    let mut samplers = Vec::new();
+    let mut channels = Vec::new();
+    for (index, branch) in tree.branches.iter().enumerate() {
+        // Roots are underground and don't move
+        if branch.kind == BranchKind::Root {
+            continue;
+        }
+
+        // Thin branches sway further, each level sways faster than its parent
+        let base_radius = branch.radii.first().copied().unwrap_or(0.0);
+        let amplitude = wind.strength * PI / 180.0 * (branch.level + 1) as f32 / (1.0 + 20.0 * base_radius);
+        let cycles = (base_cycles * (branch.level + 1)) as f32;
+
+        // Spread the phases so neighbouring branches don't move in lockstep
+        let phase = 2.0 * PI * branch_phase(index);
+
+        // The sway axis is fixed in the tree's frame, node rotations are in the parent's frame
+        let parent_rotation = branch
+            .parent
+            .map_or(UnitQuaternion::identity(), |parent| tree.world_transform(parent).rotation);
+        let local_axis = Unit::new_normalize(parent_rotation.inverse() * sway_axis.into_inner());
+
+        let mut rotations = Vec::with_capacity(times.len() * 4);
+        for time in &times {
+            let angle = amplitude * (2.0 * PI * cycles * time / duration + phase).sin();
+            let rotation = UnitQuaternion::from_axis_angle(&local_axis, angle) * branch.rotation;
+            let quat = rotation.into_inner();
+            rotations.extend_from_slice(&[quat.i, quat.j, quat.k, quat.w]);
+        }
+        let output = document.add_float_accessor(&rotations, 4, None);
+
+        channels.push(json!({
+            "sampler": samplers.len(),
+            "target": { "node": branch_nodes[index], "path": "rotation" },
+        }));
+        samplers.push(json!({
+            "input": input,
+            "output": output,
+            "interpolation": "LINEAR",
+        }));
+    }
+
+    document.push("animations", json!({
+        "name": "Wind",
+        "samplers": samplers,
+        "channels": channels,
+    }));
+}
+
+/// Add the per-vertex attributes wind shaders animate the merged tree mesh with
+///
+/// Adds to the first primitive of the mesh:
+///
+/// * `_WIND_LEVEL` - Hierarchy level of the vertex's branch (0 = trunk)
+/// * `_WIND_DISTANCE` - Distance along the branch, 0 at its base and 1 at its tip
+/// * `_WIND_PIVOT` - Base of the branch in the tree's frame, the point it sways around
+/// * `_WIND_PHASE` - Phase offset of the branch (0.0-1.0)
+///
+/// # Arguments
+///
+/// * `document` - Exported GLB document containing the merged mesh
+/// * `tree` - Skeleton the document was built from
+/// * `merged` - The merged mesh and the branch of each of its vertices
+pub fn add_wind_vertex_attributes(document: &mut GlbDocument, tree: &Tree, merged: &MergedMesh) {
+    let mut levels = Vec::with_capacity(merged.vertex_branches.len());
+    let mut pivots = Vec::with_capacity(merged.vertex_branches.len() * 3);
+    let mut phases = Vec::with_capacity(merged.vertex_branches.len());
+    for &index in &merged.vertex_branches {
+        let pivot = tree.world_transform(index).translation.vector;
+        levels.push(tree.branches[index].level as f32);
+        pivots.extend_from_slice(&[pivot.x, pivot.y, pivot.z]);
+        phases.push(branch_phase(index));
+    }
+
+    let level = document.add_float_accessor(&levels, 1, Some(TARGET_ARRAY_BUFFER));
+    let distance = document.add_float_accessor(&merged.vertex_distances, 1, Some(TARGET_ARRAY_BUFFER));
+    let pivot = document.add_float_accessor(&pivots, 3, Some(TARGET_ARRAY_BUFFER));
+    let phase = document.add_float_accessor(&phases, 1, Some(TARGET_ARRAY_BUFFER));
+
+    let attributes = &mut document.json["meshes"][merged.mesh]["primitives"][0]["attributes"];
+    attributes["_WIND_LEVEL"] = json!(level);
+    attributes["_WIND_DISTANCE"] = json!(distance);
+    attributes["_WIND_PIVOT"] = json!(pivot);
+    attributes["_WIND_PHASE"] = json!(phase);
+}
+
+/// Phase offset of a branch (0.0-1.0), spread with the golden ratio so neighbours differ
+fn branch_phase(index: usize) -> f32 {
+    (index as f32 * 0.618_034).fract()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::Branch;
+    use crate::WindMode;
+    use nalgebra::{Point3, Quaternion};
+
+    /// A trunk with a tilted branch at its third ring and a root at its base
+    fn tree() -> Tree {
+        let branch = Branch {
+            position: Point3::new(0.0, 0.0, 2.0),
+            rotation: UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 1.0),
+            ..Branch::straight(1, Some(0), Some(2), 3)
+        };
+        let mut root = Branch::straight(1, Some(0), Some(0), 3);
+        root.kind = BranchKind::Root;
+        Tree { branches: vec![Branch::straight(0, None, None, 5), branch, root], ..Default::default() }
+    }
+
+    fn wind(direction: [f32; 3]) -> WindConfig {
+        WindConfig { duration: 2.0, direction, strength: 10.0, frequency: 0.7, mode: WindMode::Nodes }
+    }
+
+    /// Rotation keyframes of an animation channel
+    fn channel_rotations(document: &GlbDocument, channel: usize) -> Vec<UnitQuaternion<f32>> {
+        let animation = &document.json["animations"][0];
+        let sampler = animation["channels"][channel]["sampler"].as_u64().unwrap() as usize;
+        let output = animation["samplers"][sampler]["output"].as_u64().unwrap() as usize;
+        let (values, _) = document.read_float_accessor(output).unwrap();
+        values
+            .chunks(4)
+            .map(|q| UnitQuaternion::from_quaternion(Quaternion::new(q[3], q[0], q[1], q[2])))
+            .collect()
+    }
+
+    #[test]
+    fn animation_loops_and_leaves_the_roots() {
+        let tree = tree();
+        let mut document = GlbDocument { json: json!({}), bin: Vec::new() };
+        add_wind_animation(&mut document, &tree, &[10, 11, 12], &wind([1.0, 0.0, 0.0]));
+
+        let channels = document.json["animations"][0]["channels"].as_array().unwrap();
+        let targets: Vec<u64> = channels.iter().map(|channel| channel["target"]["node"].as_u64().unwrap()).collect();
+        assert_eq!(targets, vec![10, 11], "the root gets no channel");
+
+        for channel in 0..channels.len() {
+            let rotations = channel_rotations(&document, channel);
+            assert!(rotations.len() > 2);
+            assert!(rotations[0].angle_to(rotations.last().unwrap()) < 1.0e-4);
+            assert!(rotations.iter().any(|rotation| rotation.angle_to(&rotations[0]) > 1.0e-3), "channel {} sways", channel);
+        }
+    }
+
+    #[test]
+    fn branches_sway_across_the_wind() {
+        let tree = tree();
+        let direction = Vector3::new(1.0, 1.0, 0.0).normalize();
+        let mut document = GlbDocument { json: json!({}), bin: Vec::new() };
+        add_wind_animation(&mut document, &tree, &[0, 1, 2], &wind(direction.into()));
+
+        for (channel, index) in [0, 1].into_iter().enumerate() {
+            // The sway in the tree's frame, without the branch's own rotation
+            let parent = tree.branches[index].parent.map_or(UnitQuaternion::identity(), |parent| tree.world_transform(parent).rotation);
+            for rotation in channel_rotations(&document, channel) {
+                let sway = parent * rotation * tree.branches[index].rotation.inverse() * parent.inverse();
+                if let Some(axis) = sway.axis() {
+                    assert!(axis.dot(&direction).abs() < 1.0e-3, "axis {:?}", axis);
+                    assert!(axis.z.abs() < 1.0e-3, "axis {:?}", axis);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_vertex_gets_the_wind_attributes() {
+        let tree = tree();
+        let ((vertices, _, _, uvs), vertex_branches) = tree.merged_mesh(BranchKind::Branch);
+        let mut document = GlbDocument { json: json!({}), bin: Vec::new() };
+        let positions: Vec<f32> = vertices.iter().flat_map(|vertex| [vertex.x, vertex.y, vertex.z]).collect();
+        let position = document.add_float_accessor(&positions, 3, Some(TARGET_ARRAY_BUFFER));
+        let mesh = document.push("meshes", json!({ "primitives": [{ "attributes": { "POSITION": position } }] }));
+        let merged = MergedMesh {
+            mesh,
+            node: 0,
+            vertex_distances: uvs.iter().map(|uv| uv[1]).collect(),
+            vertex_branches,
+        };
+        add_wind_vertex_attributes(&mut document, &tree, &merged);
+
+        let attributes = document.json["meshes"][mesh]["primitives"][0]["attributes"].clone();
+        let attribute = |name: &str| {
+            let accessor = attributes[name].as_u64().unwrap() as usize;
+            assert_eq!(document.json["accessors"][accessor]["count"].as_u64(), Some(vertices.len() as u64), "{}", name);
+            document.read_float_accessor(accessor).unwrap()
+        };
+        let (levels, _) = attribute("_WIND_LEVEL");
+        let (distances, _) = attribute("_WIND_DISTANCE");
+        let (pivots, components) = attribute("_WIND_PIVOT");
+        let (phases, _) = attribute("_WIND_PHASE");
+        assert_eq!(components, 3);
+
+        for (vertex, &branch) in merged.vertex_branches.iter().enumerate() {
+            let base = tree.world_transform(branch) * Point3::origin();
+            assert_eq!(&pivots[vertex * 3..vertex * 3 + 3], &[base.x, base.y, base.z]);
+            assert_eq!(levels[vertex], tree.branches[branch].level as f32);
+            assert!((0.0..=1.0).contains(&distances[vertex]) && (0.0..1.0).contains(&phases[vertex]));
+        }
+        let child = merged.vertex_branches.iter().position(|&branch| branch == 1).unwrap();
+        assert_eq!(&pivots[child * 3..child * 3 + 3], &[0.0, 0.0, 2.0]);
+    }
+}